@@ -1,8 +1,15 @@
 use std::error::Error;
-use log::warn;
+use std::ffi::c_void;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::time::Duration;
+use async_std::task;
+use log::{warn, Level};
 use trident_engine_2024::game::GameObject;
+use trident_engine_2024::game::world::WorldContext;
 use trident_engine_2024::game::mesh_management::Mesh;
-use trident_engine_2024::game::services::ServiceManager;
+use trident_engine_2024::hot_reload::{
+    ClientActionEvent, ClientFrame, ClientHost, Host, StateWriterFn, CLIENT_ABI_VERSION
+};
 
 pub struct DefaultObject {
     name: String,
@@ -10,36 +17,159 @@ pub struct DefaultObject {
 }
 
 impl DefaultObject {
-    pub fn new(name: &str) -> Self {
+    pub async fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
             mesh: Mesh::from_file("My Mesh", "res/models/my_mesh.txt").await,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
 }
 
 impl GameObject for DefaultObject {
-    fn get_mesh(&self) -> &Mesh {
-        self.mesh.as_ref().unwrap()
+    fn get_mesh(&self) -> Option<&Mesh> {
+        self.mesh.as_ref()
     }
 
-    fn update(&mut self) {
+    fn update(&mut self, _dt: f32, _world: &mut WorldContext) {
         warn!("Player update method not implemented yet.");
     }
 }
 
-pub async fn init() {
-    let sm = ServiceManager::get_service_manager();
-    let sm_read = sm.read().await;
-    
-    let actions = sm_read
-        .get_service("ActionsService")
-        .unwrap();
-    let actions_read = actions.read().await;
-    
-    let my_object = DefaultObject::new("PlayerObject");
+/// Everything the client keeps between frames. Lives behind the pointer handed to the engine.
+pub struct ClientState {
+    player: DefaultObject,
+    play_time: Duration,
+}
+
+impl ClientState {
+    pub fn player(&self) -> &DefaultObject {
+        &self.player
+    }
+
+    fn save(&self) -> Vec<u8> {
+        self.play_time.as_secs_f64().to_le_bytes().to_vec()
+    }
+
+    fn restore_play_time(saved_state: &[u8]) -> Duration {
+        saved_state
+            .try_into()
+            .map(|bytes| Duration::from_secs_f64(f64::from_le_bytes(bytes)))
+            .unwrap_or_default()
+    }
+}
+
+/// `user_data` is the `ClientHost`, which outlives every action the client registers.
+extern "C" fn on_jump(user_data: *mut c_void, _event: *const ClientActionEvent) {
+    if let Some(host) = unsafe { Host::from_ptr(user_data as *const ClientHost) } {
+        host.log(Level::Warn, "Player jump not implemented yet.");
+    }
+}
+
+pub async fn init(host: Host<'_>, host_ptr: *const ClientHost) -> Result<DefaultObject, Box<dyn Error>> {
+    if !host.register_action("Jump", "Key:Space", Some("Make the player jump"), on_jump, host_ptr as *mut c_void) {
+        return Err("Failed to register the Jump action".into());
+    }
+
+    Ok(DefaultObject::new("PlayerObject").await)
+}
+
+pub fn shutdown(host: Host<'_>) {
+    host.unregister_action("Jump");
+}
+
+/// Run an entry point without letting a panic unwind into the engine.
+fn guarded<T>(host: Option<Host<'_>>, entry_point: &str, function: impl FnOnce() -> T) -> Option<T> {
+    match catch_unwind(AssertUnwindSafe(function)) {
+        Ok(value) => Some(value),
+        Err(_) => {
+            if let Some(host) = host {
+                host.log(Level::Error, &format!("Client panicked in {}", entry_point));
+            }
+            None
+        },
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn trident_client_abi_version() -> u32 {
+    CLIENT_ABI_VERSION
+}
+
+/// # Safety
+/// `host_ptr` must be the engine's `ClientHost`, and `saved_state` null or valid for
+/// `saved_state_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn trident_client_init(host_ptr: *const ClientHost, saved_state: *const u8, saved_state_len: usize) -> *mut c_void {
+    let Some(host) = (unsafe { Host::from_ptr(host_ptr) }) else {
+        return std::ptr::null_mut();
+    };
+
+    let result = guarded(Some(host), "init", || {
+        let play_time = if saved_state.is_null() {
+            Duration::ZERO
+        } else {
+            ClientState::restore_play_time(unsafe { std::slice::from_raw_parts(saved_state, saved_state_len) })
+        };
+
+        let player = task::block_on(init(host, host_ptr))?;
+        host.log(Level::Info, &format!("Client initialized after {:.1?} of play", play_time));
+
+        Ok::<_, Box<dyn Error>>(ClientState { player, play_time })
+    });
+
+    match result {
+        Some(Ok(state)) => Box::into_raw(Box::new(state)) as *mut c_void,
+        Some(Err(e)) => {
+            host.log(Level::Error, &e.to_string());
+            std::ptr::null_mut()
+        },
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `state` must come from `trident_client_init`, `host` must be the engine's `ClientHost` and
+/// `frame` must point at a valid `ClientFrame`.
+#[no_mangle]
+pub unsafe extern "C" fn trident_client_update(state: *mut c_void, host: *const ClientHost, frame: *const ClientFrame) {
+    let host = unsafe { Host::from_ptr(host) };
+    guarded(host, "update", || {
+        let state = unsafe { &mut *(state as *mut ClientState) };
+        let frame = unsafe { &*frame };
+        state.play_time += Duration::from_secs_f64(frame.delta_secs);
+    });
+}
+
+/// # Safety
+/// `state` must come from `trident_client_init`.
+#[no_mangle]
+pub unsafe extern "C" fn trident_client_save_state(state: *mut c_void, write: StateWriterFn, writer: *mut c_void) {
+    guarded(None, "save_state", || {
+        let state = unsafe { &*(state as *const ClientState) };
+        let saved_state = state.save();
+        write(writer, saved_state.as_ptr(), saved_state.len());
+    });
+}
+
+/// # Safety
+/// `state` must come from `trident_client_init` and isn't used again afterwards, and `host` must
+/// be the engine's `ClientHost`.
+#[no_mangle]
+pub unsafe extern "C" fn trident_client_shutdown(state: *mut c_void, host: *const ClientHost) {
+    let host = unsafe { Host::from_ptr(host) };
+    guarded(host, "shutdown", || {
+        let state = unsafe { Box::from_raw(state as *mut ClientState) };
+        if let Some(host) = host {
+            shutdown(host);
+        }
+        drop(state);
+    });
 }
 
 pub fn play() -> Result<(), Box<dyn Error>> {
     Ok(())
-}
\ No newline at end of file
+}
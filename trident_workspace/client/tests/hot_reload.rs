@@ -0,0 +1,95 @@
+//! Loads the `client` library cargo builds next to this test, the way the engine does.
+
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_std::sync::RwLock;
+use libloading::library_filename;
+use log::{LevelFilter, Log, Metadata, Record};
+use trident_engine_2024::game::services::input_actions::ActionsService;
+use trident_engine_2024::game::services::ServiceManager;
+use trident_engine_2024::game_loop::FrameContext;
+use trident_engine_2024::hot_reload::ClientLibrary;
+
+static MESSAGES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Collects what the engine logs, which includes everything the client logs through its host.
+struct CapturingLogger;
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        MESSAGES.lock().unwrap().push(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger;
+
+fn logged(pattern: &str) -> Vec<String> {
+    MESSAGES.lock().unwrap().iter().filter(|message| message.contains(pattern)).cloned().collect()
+}
+
+/// Cargo puts the libraries of the workspace next to the test executable.
+fn library_path(name: &str) -> PathBuf {
+    let executable = std::env::current_exe().unwrap();
+    executable.parent().unwrap().join(library_filename(name))
+}
+
+fn services() -> Arc<RwLock<ServiceManager>> {
+    let _ = log::set_logger(&LOGGER).map(|()| log::set_max_level(LevelFilter::Info));
+
+    let services = ServiceManager::new_shared();
+    async_std::task::block_on(services.write()).add_service("Actions", ActionsService::new());
+    services
+}
+
+fn frame(delta: Duration, frame_index: u64) -> FrameContext {
+    FrameContext {
+        delta,
+        total_time: delta * (frame_index as u32 + 1),
+        frame_index,
+        alpha: 0.0,
+    }
+}
+
+#[test]
+fn saved_state_survives_a_reload() -> Result<(), Box<dyn Error>> {
+    let mut client = ClientLibrary::load(library_path("client"), services())?;
+    assert!(logged("Client initialized after 0.0ns of play").len() == 1, "{:?}", logged("Client initialized"));
+
+    client.update(&frame(Duration::from_millis(1500), 0));
+    client.update(&frame(Duration::from_millis(1500), 1));
+    client.reload()?;
+
+    assert_eq!(client.reload_count(), 1);
+    assert_eq!(logged("Client initialized after 3.0s of play").len(), 1, "{:?}", logged("Client initialized"));
+    Ok(())
+}
+
+#[test]
+fn libraries_built_for_another_abi_are_refused() {
+    let path = library_path("wrong_abi_client");
+    let Err(error) = ClientLibrary::load(&path, services()) else {
+        panic!("Loaded {}, which was built for another client ABI", path.display());
+    };
+
+    assert!(error.to_string().contains("client ABI version 4294967295"), "{}", error);
+
+    // The copy the library was loaded from is gone again.
+    let shadow_prefix = format!("trident-{}-", std::process::id());
+    let shadow_suffix = library_filename("wrong_abi_client").to_string_lossy().into_owned();
+    let leftovers: Vec<_> = fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with(&shadow_prefix) && name.ends_with(&shadow_suffix))
+        .collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+}
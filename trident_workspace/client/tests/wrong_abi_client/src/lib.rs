@@ -0,0 +1,7 @@
+//! A client library built for a client ABI version the engine doesn't speak, for the hot reload
+//! tests. It exports nothing else: the engine has to refuse it before looking for anything more.
+
+#[no_mangle]
+pub extern "C" fn trident_client_abi_version() -> u32 {
+    u32::MAX
+}
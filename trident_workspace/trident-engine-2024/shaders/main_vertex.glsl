@@ -7,8 +7,12 @@ layout (location = 2) in vec2 texture_coords;
 out vec3 output_color;
 out vec2 out_tex_coords;
 
+// Uniforms
+uniform mat4 u_Model;
+uniform mat4 u_ViewProjection;
+
 void main() {
-    gl_Position = vec4(vertex_positions, 1.0);
+    gl_Position = u_ViewProjection * u_Model * vec4(vertex_positions, 1.0);
     output_color = vertex_colors;
     out_tex_coords = texture_coords;
 }
\ No newline at end of file
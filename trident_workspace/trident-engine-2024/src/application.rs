@@ -1,95 +1,300 @@
+use std::collections::HashMap;
 use std::error::Error;
-use log::info;
-use sdl2::{EventPump, Sdl, VideoSubsystem};
+use std::sync::Arc;
+use log::{info, warn};
+use async_std::sync::RwLock;
+use async_std::task;
+use sdl2::{EventPump, GameControllerSubsystem, Sdl, VideoSubsystem};
+use sdl2::controller::GameController;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::video::{GLContext, Window};
+use sdl2::video::{GLContext, SwapInterval, Window};
+use crate::application_config::{ApplicationConfig, WindowMode};
+use crate::game::camera::Camera;
+use crate::game::services::input_events::InputEvent;
+use crate::game::services::input_recording::{InputRecording, ReplayStep};
+use crate::game::services::ServiceManager;
+use crate::game_loop::{fixed_timestep, FrameClock, FrameContext, GameLoop};
+use crate::headless::{OffscreenTarget, RgbaImage};
 
 pub struct Application {
-    sdl_context: Sdl,
-    video: VideoSubsystem,
+    // Never read, but SDL and the GL context shut down when these are dropped.
+    _sdl_context: Sdl,
+    _video: VideoSubsystem,
     window: Window,
-    gl_context: GLContext,
+    _gl_context: GLContext,
     event_pump: EventPump,
+    game_controller: GameControllerSubsystem,
+    controllers: HashMap<u32, GameController>,
+    config: ApplicationConfig,
+    services: Arc<RwLock<ServiceManager>>,
+    camera: Arc<RwLock<Camera>>,
     running: bool,
 }
 
 impl Application {
-    fn init() -> Result<(Sdl, VideoSubsystem, Window), Box<dyn Error>> {
+    fn init(config: &ApplicationConfig) -> Result<(Sdl, VideoSubsystem, Window), Box<dyn Error>> {
+        if config.headless {
+            sdl2::hint::set("SDL_VIDEODRIVER", "offscreen");
+        }
+
         let sdl = sdl2::init()?;
         let video = sdl.video()?;
 
         let gl_attr = video.gl_attr();
-        gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-        gl_attr.set_context_version(4, 5);
+        gl_attr.set_context_profile(config.gl_profile.into());
+        gl_attr.set_context_version(config.gl_version.0, config.gl_version.1);
+        gl_attr.set_depth_size(config.depth_bits);
+        gl_attr.set_stencil_size(config.stencil_bits);
+
+        if config.msaa_samples > 0 {
+            gl_attr.set_multisample_buffers(1);
+            gl_attr.set_multisample_samples(config.msaa_samples);
+        }
 
-        let (minor, major) = gl_attr.context_version();
+        if config.debug_context {
+            gl_attr.set_context_flags().debug().set();
+        }
+
+        let mut window_builder = video.window(config.title.as_str(), config.width, config.height);
+        window_builder.position_centered().opengl();
 
-        let window = video
-            .window(
-                "Trident Engine - OpenGL".to_string().as_str(),
-                800,
-                600)
-            .position_centered()
-            .opengl()
-            .resizable()
-            .build()?;
+        if config.resizable {
+            window_builder.resizable();
+        }
+
+        match config.window_mode {
+            _ if config.headless => { window_builder.hidden(); },
+            WindowMode::Windowed => {},
+            WindowMode::Borderless => { window_builder.borderless(); },
+            WindowMode::Fullscreen => { window_builder.fullscreen(); },
+            WindowMode::FullscreenDesktop => { window_builder.fullscreen_desktop(); },
+        }
+
+        let window = window_builder.build()?;
+        info!("Created window \"{}\" ({}x{}) with OpenGL {}.{}",
+            config.title, config.width, config.height, config.gl_version.0, config.gl_version.1);
 
         Ok((sdl, video, window))
     }
 
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        let (sdl_context, video, window) = Self::init()
-            .expect("Failed to init SDL");
+    /// Create an application with its own, empty `ServiceManager`.
+    pub fn new(config: ApplicationConfig) -> Result<Self, Box<dyn Error>> {
+        Self::with_services(config, ServiceManager::new_shared())
+    }
+
+    /// Create an application that drives `services`, e.g. `ServiceManager::get_service_manager()`
+    /// to use the process-wide instance.
+    pub fn with_services(config: ApplicationConfig, services: Arc<RwLock<ServiceManager>>) -> Result<Self, Box<dyn Error>> {
+        config.validate()?;
+        let (sdl_context, video, window) = Self::init(&config)?;
 
         let gl_context = window.gl_create_context()?;
         gl::load_with(|name| video.gl_get_proc_address(name) as *const _);
 
+        let swap_interval = if config.vsync { SwapInterval::VSync } else { SwapInterval::Immediate };
+        video.gl_set_swap_interval(swap_interval)?;
 
         let event_pump = sdl_context.event_pump()?;
+        let game_controller = sdl_context.game_controller()?;
+
+        let (width, height) = window.drawable_size();
+        let camera = Camera::perspective(60f32.to_radians(), 0.1, 1000.0).with_viewport_size(width, height);
 
         Ok(Self {
-            sdl_context,
-            video,
+            _sdl_context: sdl_context,
+            _video: video,
             window,
-            gl_context,
+            _gl_context: gl_context,
             event_pump,
+            game_controller,
+            controllers: HashMap::new(),
+            config,
+            services,
+            camera: Arc::new(RwLock::new(camera)),
             running: false,
         })
     }
 
-    pub fn run<F>(&mut self, mut main_closure: F) -> Result<(), Box<dyn Error>>
+    pub fn config(&self) -> &ApplicationConfig {
+        &self.config
+    }
+
+    pub fn services(&self) -> Arc<RwLock<ServiceManager>> {
+        self.services.clone()
+    }
+
+    /// Create an application without a visible window, for rendering tests. See
+    /// `ApplicationConfig::headless`.
+    pub fn headless(mut config: ApplicationConfig) -> Result<Self, Box<dyn Error>> {
+        config.headless = true;
+        Self::new(config)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.config.headless
+    }
+
+    /// Run `frames` frames of `game` into an offscreen framebuffer the size of the window and read
+    /// the last one back. Every frame gets one `update` with the fixed timestep and one `render`.
+    /// Services are neither initialized nor updated, and no events are processed.
+    pub fn render_to_image<G>(&mut self, game: &mut G, frames: u32) -> Result<RgbaImage, Box<dyn Error>>
     where
-        F: FnMut(&mut Window)
+        G: GameLoop
+    {
+        let delta = fixed_timestep(self.config.fixed_update_rate)?;
+        let target = OffscreenTarget::new(self.config.width, self.config.height)?;
+        task::block_on(self.camera.write()).set_viewport_size(self.config.width, self.config.height);
+        target.bind();
+
+        unsafe {
+            let [r, g, b, a] = self.config.clear_color;
+            gl::ClearColor(r, g, b, a);
+        }
+
+        for frame_index in 0..frames as u64 {
+            let frame = FrameContext {
+                delta,
+                total_time: delta * frame_index as u32,
+                frame_index,
+                alpha: 0.0,
+            };
+
+            game.update(&frame);
+            game.render(&frame, &mut self.window);
+        }
+
+        let image = target.read_pixels();
+        target.unbind();
+        Ok(image)
+    }
+
+    /// Run `game` through `recording` instead of live input, as fast as it renders. Services are
+    /// initialized and shut down like in `run`; see `InputRecording::replay` for the frame order.
+    pub fn replay<G>(&mut self, game: &mut G, recording: &InputRecording) -> Result<(), Box<dyn Error>>
+    where
+        G: GameLoop
+    {
+        let service_manager = self.services.clone();
+
+        unsafe {
+            let [r, g, b, a] = self.config.clear_color;
+            gl::ClearColor(r, g, b, a);
+        }
+
+        task::block_on(async {
+            service_manager.write().await.init_services().await
+        })?;
+
+        recording.replay(&service_manager, |step| match step {
+            ReplayStep::FixedUpdate(frame) => game.update(frame),
+            ReplayStep::Frame(frame) => {
+                game.render(frame, &mut self.window);
+                self.window.gl_swap_window();
+            },
+        });
+
+        task::block_on(async {
+            service_manager.write().await.shutdown_services().await;
+        });
+
+        Ok(())
+    }
+
+    /// The main camera. Its aspect ratio follows the window size.
+    pub fn camera(&self) -> Arc<RwLock<Camera>> {
+        self.camera.clone()
+    }
+
+    pub fn run<G>(&mut self, game: &mut G) -> Result<(), Box<dyn Error>>
+    where
+        G: GameLoop
     {
         self.running = true;
         let event_pump = &mut self.event_pump;
+        let service_manager = self.services.clone();
 
         unsafe {
-            gl::ClearColor(0.0, 0.6, 0.8, 1.0);
+            let [r, g, b, a] = self.config.clear_color;
+            gl::ClearColor(r, g, b, a);
         }
 
+        task::block_on(async {
+            service_manager.write().await.init_services().await
+        })?;
+
+        let mut clock = FrameClock::new(
+            self.config.fixed_update_rate,
+            self.config.max_frame_time,
+            self.config.target_fps)?;
+
         while self.running {
+            clock.begin_frame();
+
+            let mut input_events = Vec::new();
             for event in event_pump.poll_iter() {
                 match event {
                     Event::KeyDown { keycode: Some(Keycode::Escape), .. } => { self.running = false; },
+                    Event::Quit { .. } => { self.running = false; },
                     Event::Window {
-                        win_event: sdl2::event::WindowEvent::Resized(w, h),
+                        win_event: sdl2::event::WindowEvent::Resized(..),
                         ..
                     } => {
+                        // `Resized` reports the size in screen coordinates, which is smaller than
+                        // the framebuffer on high-DPI displays.
+                        let (width, height) = self.window.drawable_size();
                         unsafe {
-                            gl::Viewport(0, 0, w, h);
+                            gl::Viewport(0, 0, width as i32, height as i32);
                         }
+                        task::block_on(self.camera.write()).set_viewport_size(width, height);
+                    }
+                    Event::ControllerDeviceAdded { which, .. } => {
+                        match self.game_controller.open(which) {
+                            Ok(controller) => {
+                                info!("Opened game controller \"{}\"", controller.name());
+                                self.controllers.insert(controller.instance_id(), controller);
+                            },
+                            Err(e) => warn!("Failed to open game controller {}: {}", which, e),
+                        }
+                    }
+                    Event::ControllerDeviceRemoved { which, .. } => {
+                        self.controllers.remove(&which);
                     }
                     _ => {}
                 }
+
+                input_events.extend(InputEvent::from_sdl(&event));
+            }
+
+            task::block_on(async {
+                service_manager.read().await.dispatch_events(&input_events).await;
+            });
+            ServiceManager::run_callbacks(&service_manager);
+
+            while let Some(frame) = clock.next_update() {
+                task::block_on(async {
+                    service_manager.read().await.fixed_update_services(&frame).await;
+                });
+                ServiceManager::run_callbacks(&service_manager);
+                game.update(&frame);
             }
 
-            main_closure(&mut self.window);
+            let frame = clock.render_context();
+            task::block_on(async {
+                service_manager.read().await.update_services(&frame).await;
+            });
+            ServiceManager::run_callbacks(&service_manager);
+
+            game.render(&frame, &mut self.window);
 
             self.window.gl_swap_window();
+            clock.end_frame();
         }
 
+        task::block_on(async {
+            service_manager.write().await.shutdown_services().await;
+        });
+
         Ok(())
     }
-}
\ No newline at end of file
+}
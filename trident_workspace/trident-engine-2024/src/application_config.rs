@@ -0,0 +1,312 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use sdl2::video::GLProfile;
+use crate::game_loop::{fixed_timestep, max_frame_duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    Fullscreen,
+    FullscreenDesktop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GlProfile {
+    Core,
+    Compatibility,
+    Es,
+}
+
+impl From<GlProfile> for GLProfile {
+    fn from(profile: GlProfile) -> Self {
+        match profile {
+            GlProfile::Core => GLProfile::Core,
+            GlProfile::Compatibility => GLProfile::Compatibility,
+            GlProfile::Es => GLProfile::GLES,
+        }
+    }
+}
+
+/// Everything `Application::new` needs to create the window and the OpenGL context.
+/// Fields missing from a config file fall back to the values in `Default`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApplicationConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub window_mode: WindowMode,
+    pub resizable: bool,
+    pub vsync: bool,
+    pub msaa_samples: u8,
+    pub depth_bits: u8,
+    pub stencil_bits: u8,
+    pub gl_version: (u8, u8),
+    pub gl_profile: GlProfile,
+    pub debug_context: bool,
+    pub clear_color: [f32; 4],
+    /// Rate of `GameLoop::update` calls, in Hz.
+    pub fixed_update_rate: f64,
+    /// Longest frame time fed into the fixed-update accumulator, in seconds.
+    pub max_frame_time: f64,
+    /// Frame cap applied by sleeping. `None` leaves the loop uncapped, or paced by vsync.
+    pub target_fps: Option<u32>,
+    /// Create a hidden window through SDL's offscreen video driver, which gets its OpenGL context
+    /// from EGL without a display. With Mesa, `LIBGL_ALWAYS_SOFTWARE=1` selects llvmpipe, so this
+    /// also works on CI machines without a GPU.
+    pub headless: bool,
+    /// The hot-reloaded game logic library. Defaults to the `client` library next to the executable,
+    /// see `ClientLibrary::default_path`.
+    pub client_library: Option<PathBuf>,
+}
+
+impl Default for ApplicationConfig {
+    fn default() -> Self {
+        Self {
+            title: "Trident Engine - OpenGL".to_string(),
+            width: 800,
+            height: 600,
+            window_mode: WindowMode::Windowed,
+            resizable: true,
+            vsync: false,
+            msaa_samples: 0,
+            depth_bits: 24,
+            stencil_bits: 8,
+            gl_version: (4, 5),
+            gl_profile: GlProfile::Core,
+            debug_context: false,
+            clear_color: [0.0, 0.6, 0.8, 1.0],
+            fixed_update_rate: 60.0,
+            max_frame_time: 0.25,
+            target_fps: Some(60),
+            headless: false,
+            client_library: None,
+        }
+    }
+}
+
+impl ApplicationConfig {
+    pub fn builder() -> ApplicationConfigBuilder {
+        ApplicationConfigBuilder {
+            config: Self::default(),
+        }
+    }
+
+    /// Load a config from a `.toml` or `.ron` file. The format is picked from the file extension.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("ron") => Self::from_ron(&contents),
+            _ => Err(format!("Unsupported application config format: {}", path.display()).into()),
+        }
+    }
+
+    /// Check the values that would otherwise only fail once the application runs.
+    /// `Application::new` and the `from_*` functions call this.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        fixed_timestep(self.fixed_update_rate)?;
+        max_frame_duration(self.max_frame_time)?;
+        Ok(())
+    }
+
+    /// Like `from_file`, but a missing file gives the default config.
+    /// Any other problem, like a file that doesn't parse, is still an error.
+    pub fn from_file_or_default<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        match Self::from_file(path) {
+            Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::NotFound) => {
+                Ok(Self::default())
+            },
+            result => result,
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, Box<dyn Error>> {
+        let config: Self = toml::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_ron(contents: &str) -> Result<Self, Box<dyn Error>> {
+        let config: Self = ron::from_str(contents)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+pub struct ApplicationConfigBuilder {
+    config: ApplicationConfig,
+}
+
+impl ApplicationConfigBuilder {
+    pub fn title(mut self, title: &str) -> Self {
+        self.config.title = title.to_string();
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.config.width = width;
+        self.config.height = height;
+        self
+    }
+
+    pub fn window_mode(mut self, window_mode: WindowMode) -> Self {
+        self.config.window_mode = window_mode;
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.config.resizable = resizable;
+        self
+    }
+
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.config.vsync = vsync;
+        self
+    }
+
+    pub fn msaa_samples(mut self, samples: u8) -> Self {
+        self.config.msaa_samples = samples;
+        self
+    }
+
+    pub fn depth_bits(mut self, bits: u8) -> Self {
+        self.config.depth_bits = bits;
+        self
+    }
+
+    pub fn stencil_bits(mut self, bits: u8) -> Self {
+        self.config.stencil_bits = bits;
+        self
+    }
+
+    pub fn gl_version(mut self, major: u8, minor: u8) -> Self {
+        self.config.gl_version = (major, minor);
+        self
+    }
+
+    pub fn gl_profile(mut self, profile: GlProfile) -> Self {
+        self.config.gl_profile = profile;
+        self
+    }
+
+    pub fn debug_context(mut self, debug: bool) -> Self {
+        self.config.debug_context = debug;
+        self
+    }
+
+    pub fn clear_color(mut self, color: [f32; 4]) -> Self {
+        self.config.clear_color = color;
+        self
+    }
+
+    pub fn fixed_update_rate(mut self, rate: f64) -> Self {
+        self.config.fixed_update_rate = rate;
+        self
+    }
+
+    pub fn max_frame_time(mut self, seconds: f64) -> Self {
+        self.config.max_frame_time = seconds;
+        self
+    }
+
+    pub fn target_fps(mut self, target_fps: Option<u32>) -> Self {
+        self.config.target_fps = target_fps;
+        self
+    }
+
+    pub fn headless(mut self, headless: bool) -> Self {
+        self.config.headless = headless;
+        self
+    }
+
+    pub fn client_library<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.config.client_library = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn build(self) -> ApplicationConfig {
+        self.config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_fills_missing_fields_from_default() {
+        let config = ApplicationConfig::from_toml(r#"
+            title = "Test"
+            width = 1280
+            window_mode = "Borderless"
+            gl_version = [3, 3]
+            target_fps = 144
+        "#).unwrap();
+
+        assert_eq!(config.title, "Test");
+        assert_eq!(config.width, 1280);
+        assert_eq!(config.height, ApplicationConfig::default().height);
+        assert_eq!(config.window_mode, WindowMode::Borderless);
+        assert_eq!(config.gl_version, (3, 3));
+        assert_eq!(config.target_fps, Some(144));
+    }
+
+    #[test]
+    fn ron_fills_missing_fields_from_default() {
+        let config = ApplicationConfig::from_ron(r#"(
+            height: 720,
+            gl_profile: Es,
+            vsync: true,
+            target_fps: None,
+        )"#).unwrap();
+
+        assert_eq!(config.height, 720);
+        assert_eq!(config.width, ApplicationConfig::default().width);
+        assert_eq!(config.gl_profile, GlProfile::Es);
+        assert!(config.vsync);
+        assert_eq!(config.target_fps, None);
+    }
+
+    #[test]
+    fn invalid_timing_is_an_error() {
+        assert!(ApplicationConfig::from_toml("fixed_update_rate = 0.0").is_err());
+        assert!(ApplicationConfig::from_ron("(max_frame_time: -1.0)").is_err());
+        assert!(ApplicationConfig::builder().fixed_update_rate(f64::NAN).build().validate().is_err());
+        assert!(ApplicationConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn from_file_picks_the_format_from_the_extension() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_path = dir.path().join("trident.toml");
+        let ron_path = dir.path().join("trident.ron");
+        fs::write(&toml_path, "width = 640").unwrap();
+        fs::write(&ron_path, "(width: 320)").unwrap();
+
+        assert_eq!(ApplicationConfig::from_file(&toml_path).unwrap().width, 640);
+        assert_eq!(ApplicationConfig::from_file(&ron_path).unwrap().width, 320);
+
+        let json_path = dir.path().join("trident.json");
+        fs::write(&json_path, "{}").unwrap();
+        assert!(ApplicationConfig::from_file(&json_path).is_err());
+    }
+
+    #[test]
+    fn from_file_or_default_only_falls_back_when_the_file_is_missing() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let missing = ApplicationConfig::from_file_or_default(dir.path().join("missing.toml")).unwrap();
+        assert_eq!(missing.title, ApplicationConfig::default().title);
+
+        let broken_path = dir.path().join("broken.toml");
+        fs::write(&broken_path, "width = \"wide\"").unwrap();
+        assert!(ApplicationConfig::from_file_or_default(&broken_path).is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use crate::game::camera::{Camera, Projection};
+use crate::game::services::input_actions::ActionsService;
+use crate::game::services::input_bindings::{AxisAction, AxisSource, InputButton};
+use crate::game::transform::Transform;
+use nalgebra_glm as glm;
+use nalgebra_glm::{Vec2, Vec3};
+use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
+use std::f32::consts::FRAC_PI_2;
+
+/// Keeps pitch just short of straight up or down, where yaw would flip.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Moves a `Camera` from the axis values in `ActionsService`. Call `update` once per rendered
+/// frame, after the services have updated.
+pub trait CameraController {
+    /// Register default bindings for the axes this controller reads.
+    fn register_axes(&self, actions: &mut ActionsService);
+    fn update(&mut self, camera: &mut Camera, actions: &ActionsService, dt: f32);
+}
+
+fn buttons(negative: Keycode, positive: Keycode) -> AxisSource {
+    AxisSource::Buttons {
+        negative: InputButton::Key(negative),
+        positive: InputButton::Key(positive),
+    }
+}
+
+fn rotation_from_angles(yaw: f32, pitch: f32) -> glm::Quat {
+    let yaw = glm::quat_angle_axis(yaw, &glm::vec3(0.0, 1.0, 0.0));
+    let pitch = glm::quat_angle_axis(pitch, &glm::vec3(1.0, 0.0, 0.0));
+    glm::quat_normalize(&(yaw * pitch))
+}
+
+/// Free-flying camera: WASD to move, Q/E to go down and up, mouse to look around.
+pub struct FlyController {
+    pub speed: f32,
+    /// Radians turned per pixel of mouse movement.
+    pub look_sensitivity: f32,
+    /// Only look around while this is held, e.g. the right mouse button.
+    pub look_button: Option<InputButton>,
+    pub move_x_axis: String,
+    pub move_y_axis: String,
+    pub move_z_axis: String,
+    pub look_x_axis: String,
+    pub look_y_axis: String,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl FlyController {
+    pub fn new(speed: f32) -> FlyController {
+        Self {
+            speed,
+            look_sensitivity: 0.003,
+            look_button: None,
+            move_x_axis: "CameraMoveX".to_string(),
+            move_y_axis: "CameraMoveY".to_string(),
+            move_z_axis: "CameraMoveZ".to_string(),
+            look_x_axis: "CameraLookX".to_string(),
+            look_y_axis: "CameraLookY".to_string(),
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+
+    pub fn with_look_button(mut self, button: impl Into<InputButton>) -> Self {
+        self.look_button = Some(button.into());
+        self
+    }
+
+    /// Start from where `camera` is currently looking instead of straight down -Z.
+    pub fn look_from(&mut self, camera: &Camera) {
+        let forward = camera.transform.forward();
+        self.yaw = (-forward.x).atan2(-forward.z);
+        self.pitch = forward.y.clamp(-1.0, 1.0).asin().clamp(-MAX_PITCH, MAX_PITCH);
+    }
+}
+
+impl CameraController for FlyController {
+    fn register_axes(&self, actions: &mut ActionsService) {
+        actions.register_axis(AxisAction::new(&self.move_x_axis, buttons(Keycode::A, Keycode::D)));
+        actions.register_axis(AxisAction::new(&self.move_y_axis, buttons(Keycode::Q, Keycode::E)));
+        actions.register_axis(AxisAction::new(&self.move_z_axis, buttons(Keycode::S, Keycode::W)));
+        actions.register_axis(AxisAction::new(&self.look_x_axis, AxisSource::MouseX));
+        actions.register_axis(AxisAction::new(&self.look_y_axis, AxisSource::MouseY));
+    }
+
+    fn update(&mut self, camera: &mut Camera, actions: &ActionsService, dt: f32) {
+        let looking = self.look_button.is_none_or(|button| actions.is_button_held(button));
+        if looking {
+            self.yaw -= actions.axis_value(&self.look_x_axis) * self.look_sensitivity;
+            self.pitch -= actions.axis_value(&self.look_y_axis) * self.look_sensitivity;
+            self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        camera.transform.rotation = rotation_from_angles(self.yaw, self.pitch);
+
+        let movement = camera.transform.right() * actions.axis_value(&self.move_x_axis)
+            + glm::vec3(0.0, 1.0, 0.0) * actions.axis_value(&self.move_y_axis)
+            + camera.transform.forward() * actions.axis_value(&self.move_z_axis);
+
+        if glm::length2(&movement) > f32::EPSILON {
+            camera.transform.translate(&(glm::normalize(&movement) * self.speed * dt));
+        }
+    }
+}
+
+/// Circles a target point: drag with the right mouse button to orbit, scroll to zoom.
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Radians turned per pixel of mouse movement.
+    pub orbit_sensitivity: f32,
+    /// Fraction of the distance covered per wheel tick.
+    pub zoom_sensitivity: f32,
+    pub orbit_button: Option<InputButton>,
+    pub orbit_x_axis: String,
+    pub orbit_y_axis: String,
+    pub zoom_axis: String,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl OrbitController {
+    pub fn new(target: Vec3, distance: f32) -> OrbitController {
+        Self {
+            target,
+            distance,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+            orbit_sensitivity: 0.005,
+            zoom_sensitivity: 0.1,
+            orbit_button: Some(InputButton::Mouse(MouseButton::Right)),
+            orbit_x_axis: "CameraOrbitX".to_string(),
+            orbit_y_axis: "CameraOrbitY".to_string(),
+            zoom_axis: "CameraZoom".to_string(),
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+
+    pub fn with_angles(mut self, yaw: f32, pitch: f32) -> Self {
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        self
+    }
+}
+
+impl CameraController for OrbitController {
+    fn register_axes(&self, actions: &mut ActionsService) {
+        actions.register_axis(AxisAction::new(&self.orbit_x_axis, AxisSource::MouseX));
+        actions.register_axis(AxisAction::new(&self.orbit_y_axis, AxisSource::MouseY));
+        actions.register_axis(AxisAction::new(&self.zoom_axis, AxisSource::WheelY));
+    }
+
+    fn update(&mut self, camera: &mut Camera, actions: &ActionsService, _dt: f32) {
+        let orbiting = self.orbit_button.is_none_or(|button| actions.is_button_held(button));
+        if orbiting {
+            self.yaw -= actions.axis_value(&self.orbit_x_axis) * self.orbit_sensitivity;
+            self.pitch -= actions.axis_value(&self.orbit_y_axis) * self.orbit_sensitivity;
+            self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let zoom = actions.axis_value(&self.zoom_axis);
+        self.distance *= (1.0 - self.zoom_sensitivity).powf(zoom);
+        self.distance = self.distance.clamp(self.min_distance, self.max_distance);
+
+        let rotation = rotation_from_angles(self.yaw, self.pitch);
+        let offset = glm::quat_rotate_vec3(&rotation, &glm::vec3(0.0, 0.0, self.distance));
+
+        camera.transform = Transform::new(self.target + offset, rotation, camera.transform.scale);
+    }
+}
+
+/// Follows a target in the XY plane for 2D games, with smoothing and a dead zone.
+/// Scrolling zooms orthographic cameras.
+pub struct FollowController2D {
+    pub target: Vec2,
+    /// How quickly the camera catches up, per second. Higher is snappier; 0 disables smoothing.
+    pub smoothing: f32,
+    /// Half-size of the box around the camera the target can move in without the camera moving.
+    pub dead_zone: Vec2,
+    pub min_height: f32,
+    pub max_height: f32,
+    /// Fraction of the view height covered per wheel tick.
+    pub zoom_sensitivity: f32,
+    pub zoom_axis: String,
+}
+
+impl FollowController2D {
+    pub fn new(target: Vec2) -> FollowController2D {
+        Self {
+            target,
+            smoothing: 5.0,
+            dead_zone: glm::vec2(0.0, 0.0),
+            min_height: 1.0,
+            max_height: 100.0,
+            zoom_sensitivity: 0.1,
+            zoom_axis: "CameraZoom".to_string(),
+        }
+    }
+
+    pub fn set_target(&mut self, target: Vec2) {
+        self.target = target;
+    }
+}
+
+impl CameraController for FollowController2D {
+    fn register_axes(&self, actions: &mut ActionsService) {
+        actions.register_axis(AxisAction::new(&self.zoom_axis, AxisSource::WheelY));
+    }
+
+    fn update(&mut self, camera: &mut Camera, actions: &ActionsService, dt: f32) {
+        if let Projection::Orthographic { height } = &mut camera.projection {
+            let zoom = actions.axis_value(&self.zoom_axis);
+            *height = (*height * (1.0 - self.zoom_sensitivity).powf(zoom)).clamp(self.min_height, self.max_height);
+        }
+
+        let position = camera.transform.translation.xy();
+        let offset = self.target - position;
+
+        // Only chase the part of the offset that's outside the dead zone.
+        let outside = |offset: f32, dead_zone: f32| offset.signum() * (offset.abs() - dead_zone).max(0.0);
+        let chase = glm::vec2(outside(offset.x, self.dead_zone.x), outside(offset.y, self.dead_zone.y));
+
+        let blend = if self.smoothing > 0.0 { 1.0 - (-self.smoothing * dt).exp() } else { 1.0 };
+        let position = position + chase * blend;
+
+        camera.transform.translation.x = position.x;
+        camera.transform.translation.y = position.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::services::input_events::InputEvent;
+    use crate::game::services::Service;
+    use crate::game_loop::FrameContext;
+    use sdl2::keyboard::Mod;
+    use std::time::Duration;
+
+    fn assert_near(actual: &Vec3, expected: &Vec3) {
+        assert!(glm::distance(actual, expected) < 1e-4, "{actual:?} != {expected:?}");
+    }
+
+    /// Feed `events` to `actions` and run one frame, so the axes read them.
+    fn frame_with(actions: &mut ActionsService, events: &[InputEvent]) {
+        for event in events {
+            actions.handle_event(event);
+        }
+
+        actions.update(&FrameContext {
+            delta: Duration::from_millis(16),
+            total_time: Duration::ZERO,
+            frame_index: 0,
+            alpha: 0.0,
+        });
+    }
+
+    fn mouse_motion(xrel: i32, yrel: i32) -> InputEvent {
+        InputEvent::MouseMotion { x: 0, y: 0, xrel, yrel, timestamp: 0 }
+    }
+
+    fn wheel(y: i32) -> InputEvent {
+        InputEvent::MouseWheel { x: 0, y, timestamp: 0 }
+    }
+
+    #[test]
+    fn fly_controller_turns_with_the_mouse_and_moves_where_it_looks() {
+        let mut actions = ActionsService::new();
+        let mut controller = FlyController::new(2.0);
+        controller.register_axes(&mut actions);
+        let mut camera = Camera::perspective(1.0, 0.1, 100.0);
+
+        // 100 pixels right at 0.003 radians per pixel, while walking forward.
+        frame_with(&mut actions, &[
+            mouse_motion(100, 0),
+            InputEvent::KeyDown { keycode: Keycode::W, keymod: Mod::NOMOD, repeat: false, timestamp: 0 },
+        ]);
+        controller.update(&mut camera, &actions, 0.5);
+
+        let forward = glm::vec3(0.3f32.sin(), 0.0, -0.3f32.cos());
+        assert_near(&camera.transform.forward(), &forward);
+        assert_near(&camera.transform.translation, &forward);
+    }
+
+    #[test]
+    fn fly_controller_only_looks_while_its_button_is_held() {
+        let mut actions = ActionsService::new();
+        let mut controller = FlyController::new(2.0).with_look_button(MouseButton::Right);
+        controller.register_axes(&mut actions);
+        let mut camera = Camera::perspective(1.0, 0.1, 100.0);
+
+        frame_with(&mut actions, &[mouse_motion(100, 100)]);
+        controller.update(&mut camera, &actions, 0.5);
+
+        assert_near(&camera.transform.forward(), &glm::vec3(0.0, 0.0, -1.0));
+        assert_near(&camera.transform.translation, &glm::vec3(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn orbit_controller_zooms_and_keeps_facing_the_target() {
+        let mut actions = ActionsService::new();
+        let target = glm::vec3(1.0, 0.0, 0.0);
+        let mut controller = OrbitController::new(target, 10.0).with_angles(FRAC_PI_2, 0.0);
+        controller.register_axes(&mut actions);
+        let mut camera = Camera::perspective(1.0, 0.1, 100.0);
+
+        // One tick in zooms 10%; the drag is ignored without the orbit button.
+        frame_with(&mut actions, &[wheel(1), mouse_motion(50, 50)]);
+        controller.update(&mut camera, &actions, 0.016);
+
+        assert!((controller.distance - 9.0).abs() < 1e-4, "{}", controller.distance);
+        assert_near(&camera.transform.translation, &glm::vec3(10.0, 0.0, 0.0));
+        assert_near(&camera.transform.forward(), &glm::vec3(-1.0, 0.0, 0.0));
+
+        frame_with(&mut actions, &[
+            InputEvent::MouseButtonDown { button: MouseButton::Right, x: 0, y: 0, timestamp: 0 },
+            mouse_motion(0, -100),
+        ]);
+        controller.update(&mut camera, &actions, 0.016);
+
+        let to_target = glm::normalize(&(target - camera.transform.translation));
+        assert!((glm::distance(&camera.transform.translation, &target) - 9.0).abs() < 1e-4);
+        assert!(camera.transform.translation.y < 0.0, "{:?}", camera.transform.translation);
+        assert_near(&camera.transform.forward(), &to_target);
+    }
+
+    #[test]
+    fn follow_controller_chases_past_the_dead_zone_and_zooms() {
+        let mut actions = ActionsService::new();
+        let mut controller = FollowController2D::new(glm::vec2(10.0, 4.0));
+        controller.smoothing = 0.0;
+        controller.dead_zone = glm::vec2(1.0, 1.0);
+        controller.register_axes(&mut actions);
+        let mut camera = Camera::orthographic(10.0, 0.1, 100.0);
+
+        frame_with(&mut actions, &[wheel(-1)]);
+        controller.update(&mut camera, &actions, 0.016);
+
+        assert_near(&camera.transform.translation, &glm::vec3(9.0, 3.0, 0.0));
+        let Projection::Orthographic { height } = camera.projection else {
+            panic!("Expected an orthographic camera");
+        };
+        assert!((height - 10.0 / 0.9).abs() < 1e-4, "{}", height);
+
+        // Halfway there after a second at ln 2 per second.
+        controller.smoothing = 2f32.ln();
+        controller.set_target(glm::vec2(19.0, 3.0));
+        frame_with(&mut actions, &[]);
+        controller.update(&mut camera, &actions, 1.0);
+
+        assert_near(&camera.transform.translation, &glm::vec3(13.5, 3.0, 0.0));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::game::transform::Transform;
+use nalgebra_glm as glm;
+use nalgebra_glm::{Mat4, Vec2, Vec3};
+
+pub mod controllers;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `fov_y` is the vertical field of view in radians.
+    Perspective { fov_y: f32 },
+    /// `height` is how many world units fit vertically; the width follows from the aspect ratio.
+    Orthographic { height: f32 },
+}
+
+/// A half-line in world space, e.g. from the camera through the cursor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Always normalized.
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    /// Distance along the ray to the plane through `point` facing `normal`, if the ray hits it.
+    pub fn intersect_plane(&self, point: &Vec3, normal: &Vec3) -> Option<f32> {
+        let facing = glm::dot(&self.direction, normal);
+        if facing.abs() <= f32::EPSILON {
+            return None;
+        }
+
+        let distance = glm::dot(&(point - self.origin), normal) / facing;
+        (distance >= 0.0).then_some(distance)
+    }
+}
+
+/// View and projection for rendering. The camera sits at `transform` in world space and looks
+/// down its local -Z; scale is ignored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub transform: Transform,
+    pub projection: Projection,
+    pub near: f32,
+    pub far: f32,
+    viewport_width: u32,
+    viewport_height: u32,
+}
+
+impl Camera {
+    pub fn perspective(fov_y: f32, near: f32, far: f32) -> Camera {
+        Self {
+            transform: Transform::IDENTITY,
+            projection: Projection::Perspective { fov_y },
+            near,
+            far,
+            viewport_width: 1,
+            viewport_height: 1,
+        }
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Camera {
+        Self {
+            projection: Projection::Orthographic { height },
+            ..Self::perspective(0.0, near, far)
+        }
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_viewport_size(mut self, width: u32, height: u32) -> Self {
+        self.set_viewport_size(width, height);
+        self
+    }
+
+    /// Called by `Application::run` with the drawable size when the window is resized. Zero sizes, e.g. from a minimized
+    /// window, are ignored so the aspect ratio stays usable.
+    pub fn set_viewport_size(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.viewport_width = width;
+            self.viewport_height = height;
+        }
+    }
+
+    pub fn viewport_size(&self) -> (u32, u32) {
+        (self.viewport_width, self.viewport_height)
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.viewport_width as f32 / self.viewport_height as f32
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.transform.translation
+    }
+
+    /// World to camera space.
+    pub fn view_matrix(&self) -> Mat4 {
+        let inverse_rotation = glm::quat_conjugate(&self.transform.rotation);
+        glm::quat_to_mat4(&inverse_rotation) * glm::translation(&-self.transform.translation)
+    }
+
+    /// Camera space to clip space.
+    pub fn projection_matrix(&self) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { fov_y } => {
+                glm::perspective(self.aspect_ratio(), fov_y, self.near, self.far)
+            },
+            Projection::Orthographic { height } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * self.aspect_ratio();
+                glm::ortho(-half_width, half_width, -half_height, half_height, self.near, self.far)
+            },
+        }
+    }
+
+    pub fn view_projection_matrix(&self) -> Mat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// The ray from the camera through `screen_position`, in pixels from the top-left corner of
+    /// the viewport. It starts on the near plane. The viewport is sized in drawable pixels, so on
+    /// high-DPI displays SDL mouse positions, which are in screen coordinates, need scaling first.
+    pub fn screen_to_world_ray(&self, screen_position: &Vec2) -> Ray {
+        let ndc_x = 2.0 * screen_position.x / self.viewport_width as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * screen_position.y / self.viewport_height as f32;
+
+        let inverse = glm::inverse(&self.view_projection_matrix());
+        let unproject = |ndc_z: f32| {
+            let point = inverse * glm::vec4(ndc_x, ndc_y, ndc_z, 1.0);
+            point.xyz() / point.w
+        };
+
+        let near_point = unproject(-1.0);
+        let far_point = unproject(1.0);
+
+        Ray {
+            origin: near_point,
+            direction: glm::normalize(&(far_point - near_point)),
+        }
+    }
+
+    /// Where `point` lands on screen, in pixels from the top-left corner, or `None` if it's
+    /// behind the camera.
+    pub fn world_to_screen(&self, point: &Vec3) -> Option<Vec2> {
+        let clip = self.view_projection_matrix() * glm::vec4(point.x, point.y, point.z, 1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        let ndc = clip.xyz() / clip.w;
+        Some(glm::vec2(
+            (ndc.x + 1.0) / 2.0 * self.viewport_width as f32,
+            (1.0 - ndc.y) / 2.0 * self.viewport_height as f32,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: &Vec2, expected: &Vec2) {
+        assert!(glm::distance(actual, expected) < 1e-2, "{actual:?} != {expected:?}");
+    }
+
+    fn cameras() -> [Camera; 2] {
+        let transform = Transform::from_translation(glm::vec3(1.0, 2.0, 5.0))
+            .with_rotation(glm::quat_angle_axis(0.3, &glm::vec3(0.0, 1.0, 0.0)));
+
+        [
+            Camera::perspective(60f32.to_radians(), 0.1, 100.0).with_viewport_size(800, 600).with_transform(transform),
+            Camera::orthographic(10.0, 0.1, 100.0).with_viewport_size(1280, 720).with_transform(transform),
+        ]
+    }
+
+    #[test]
+    fn screen_rays_land_back_on_their_pixel() {
+        for camera in cameras() {
+            let (width, height) = camera.viewport_size();
+            let pixels = [
+                glm::vec2(0.0, 0.0),
+                glm::vec2(width as f32 / 2.0, height as f32 / 2.0),
+                glm::vec2(width as f32 * 0.8, height as f32 * 0.25),
+            ];
+
+            for pixel in pixels {
+                let ray = camera.screen_to_world_ray(&pixel);
+                for distance in [0.0, 1.0, 20.0] {
+                    let screen = camera.world_to_screen(&ray.at(distance)).unwrap();
+                    assert_near(&screen, &pixel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn center_ray_looks_forward_and_points_behind_are_hidden() {
+        let [perspective, orthographic] = cameras();
+
+        for camera in [perspective, orthographic] {
+            let (width, height) = camera.viewport_size();
+            let ray = camera.screen_to_world_ray(&glm::vec2(width as f32 / 2.0, height as f32 / 2.0));
+            assert!(glm::distance(&ray.direction, &camera.transform.forward()) < 1e-4);
+        }
+
+        let behind = perspective.position() - perspective.transform.forward() * 3.0;
+        assert!(perspective.world_to_screen(&behind).is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use entity::{Entity, EntityAllocator};
+use query::{Query, QueryFilter, QueryParam};
+use sparse_set::{ComponentStorage, SparseSet};
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+
+pub mod entity;
+pub mod query;
+pub mod schedule;
+pub mod sparse_set;
+
+/// Entities, their components and world-wide resources. Any `'static` type can be a component
+/// or a resource, including `Mesh` and plain `nalgebra-glm` matrices. Nothing here needs a window
+/// or a GL context.
+#[derive(Default)]
+pub struct EcsWorld {
+    entities: EntityAllocator,
+    storages: HashMap<TypeId, Box<dyn ComponentStorage>>,
+    resources: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl EcsWorld {
+    pub fn new() -> EcsWorld {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.entities.allocate()
+    }
+
+    /// Spawn an entity with every component in `bundle`, e.g. `world.spawn_with((mesh, transform))`.
+    pub fn spawn_with<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.spawn();
+        bundle.insert_into(self, entity);
+        entity
+    }
+
+    /// Remove the entity and all of its components. Returns `false` if it was already gone.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.entities.free(entity) {
+            return false;
+        }
+
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+
+        true
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.is_alive(entity)
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter()
+    }
+
+    pub(crate) fn storage<T: 'static>(&self) -> Option<&RefCell<SparseSet<T>>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref::<RefCell<SparseSet<T>>>())
+    }
+
+    /// Add or replace a component, returning the replaced one. Does nothing for despawned entities.
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(RefCell::new(SparseSet::<T>::new())));
+
+        self.storage::<T>()
+            .and_then(|storage| storage.borrow_mut().insert(entity, component))
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.storage::<T>().and_then(|storage| storage.borrow_mut().remove(entity))
+    }
+
+    pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+        self.storage::<T>().is_some_and(|storage| storage.borrow().contains(entity))
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let storage = self.storage::<T>()?.borrow();
+        Ref::filter_map(storage, |storage| storage.get(entity)).ok()
+    }
+
+    pub fn get_mut<T: 'static>(&self, entity: Entity) -> Option<RefMut<'_, T>> {
+        let storage = self.storage::<T>()?.borrow_mut();
+        RefMut::filter_map(storage, |storage| storage.get_mut(entity)).ok()
+    }
+
+    pub fn query<Q: QueryParam, F: QueryFilter>(&self) -> Query<'_, Q, F> {
+        Query::new(self)
+    }
+
+    /// Add or replace the resource of type `R`, returning the replaced one.
+    pub fn insert_resource<R: 'static>(&mut self, resource: R) -> Option<R> {
+        self.resources
+            .insert(TypeId::of::<R>(), Box::new(RefCell::new(resource)))
+            .and_then(|old| old.downcast::<RefCell<R>>().ok())
+            .map(|old| old.into_inner())
+    }
+
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R> {
+        self.resources
+            .remove(&TypeId::of::<R>())
+            .and_then(|old| old.downcast::<RefCell<R>>().ok())
+            .map(|old| old.into_inner())
+    }
+
+    pub fn resource<R: 'static>(&self) -> Option<Ref<'_, R>> {
+        self.resources
+            .get(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast_ref::<RefCell<R>>())
+            .map(|resource| resource.borrow())
+    }
+
+    pub fn resource_mut<R: 'static>(&self) -> Option<RefMut<'_, R>> {
+        self.resources
+            .get(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast_ref::<RefCell<R>>())
+            .map(|resource| resource.borrow_mut())
+    }
+}
+
+/// A set of components inserted together. Implemented for tuples of up to six components.
+pub trait Bundle {
+    fn insert_into(self, world: &mut EcsWorld, entity: Entity);
+}
+
+macro_rules! impl_bundle {
+    ($($component:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($component: 'static),+> Bundle for ($($component,)+) {
+            fn insert_into(self, world: &mut EcsWorld, entity: Entity) {
+                let ($($component,)+) = self;
+                $(world.insert(entity, $component);)+
+            }
+        }
+    };
+}
+
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use query::{With, Without};
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(f32);
+
+    struct Frozen;
+
+    #[test]
+    fn despawned_indices_are_reused_with_a_new_generation() {
+        let mut world = EcsWorld::new();
+        let first = world.spawn_with((Position(1.0),));
+        let kept = world.spawn();
+
+        assert!(world.despawn(first));
+        assert!(!world.despawn(first));
+        assert!(!world.is_alive(first));
+
+        let reused = world.spawn();
+        assert_eq!(reused.index(), first.index());
+        assert_eq!(reused.generation(), first.generation() + 1);
+        assert!(world.is_alive(reused));
+        assert!(world.is_alive(kept));
+        assert_eq!(world.entity_count(), 2);
+
+        // The old handle doesn't reach the new entity, and its components went with it.
+        assert!(!world.has::<Position>(reused));
+        assert!(world.insert(first, Position(2.0)).is_none());
+        assert!(!world.has::<Position>(reused));
+    }
+
+    #[test]
+    fn filters_narrow_queries() {
+        let mut world = EcsWorld::new();
+        let moving = world.spawn_with((Position(0.0), Velocity(1.0)));
+        let frozen = world.spawn_with((Position(0.0), Velocity(1.0), Frozen));
+        let still = world.spawn_with((Position(0.0),));
+
+        world.query::<(&mut Position, &Velocity), Without<Frozen>>()
+            .for_each(|_, (position, velocity)| position.0 += velocity.0);
+
+        assert_eq!(*world.get::<Position>(moving).unwrap(), Position(1.0));
+        assert_eq!(*world.get::<Position>(frozen).unwrap(), Position(0.0));
+        assert_eq!(world.query::<&Position, With<Frozen>>().entities(), [frozen]);
+        assert_eq!(world.query::<&Position, Without<Velocity>>().entities(), [still]);
+        assert!(world.query::<&Position, (With<Velocity>, Without<Frozen>)>().get(frozen).is_none());
+    }
+
+    #[test]
+    fn filters_on_fetched_components_do_not_borrow_them_again() {
+        let mut world = EcsWorld::new();
+        let entity = world.spawn_with((Position(0.0),));
+        world.spawn_with((Velocity(0.0),));
+
+        let mut with = world.query::<&mut Position, With<Position>>();
+        assert_eq!(with.entities(), [entity]);
+        with.get(entity).unwrap().0 = 5.0;
+        drop(with);
+
+        assert_eq!(world.query::<&mut Position, Without<Position>>().count(), 0);
+        assert_eq!(*world.get::<Position>(entity).unwrap(), Position(5.0));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::game::ecs::entity::Entity;
+use crate::game::ecs::sparse_set::SparseSet;
+use crate::game::ecs::EcsWorld;
+use std::any::TypeId;
+use std::cell::{Ref, RefMut};
+use std::marker::PhantomData;
+
+/// Something that can be fetched per entity by a `Query`: `&T`, `&mut T`, or a tuple of those.
+/// Storages are borrowed through their `RefCell`s, so asking for the same component mutably
+/// twice in one query panics instead of aliasing.
+pub trait QueryParam {
+    type Storage<'w>;
+    type Item<'s>;
+
+    /// Add the component types this parameter fetches to `types`.
+    fn component_types(types: &mut Vec<TypeId>);
+
+    /// Borrow the storages this parameter reads. `None` if a component type has never been inserted,
+    /// in which case nothing can match.
+    fn borrow(world: &EcsWorld) -> Option<Self::Storage<'_>>;
+
+    /// The entities worth visiting: those in the smallest storage involved.
+    fn entities<'s>(storage: &'s Self::Storage<'_>) -> &'s [Entity];
+
+    fn fetch<'s>(storage: &'s mut Self::Storage<'_>, entity: Entity) -> Option<Self::Item<'s>>;
+}
+
+impl<T: 'static> QueryParam for &T {
+    type Storage<'w> = Ref<'w, SparseSet<T>>;
+    type Item<'s> = &'s T;
+
+    fn component_types(types: &mut Vec<TypeId>) {
+        types.push(TypeId::of::<T>());
+    }
+
+    fn borrow(world: &EcsWorld) -> Option<Self::Storage<'_>> {
+        world.storage::<T>().map(|storage| storage.borrow())
+    }
+
+    fn entities<'s>(storage: &'s Self::Storage<'_>) -> &'s [Entity] {
+        storage.entities()
+    }
+
+    fn fetch<'s>(storage: &'s mut Self::Storage<'_>, entity: Entity) -> Option<Self::Item<'s>> {
+        storage.get(entity)
+    }
+}
+
+impl<T: 'static> QueryParam for &mut T {
+    type Storage<'w> = RefMut<'w, SparseSet<T>>;
+    type Item<'s> = &'s mut T;
+
+    fn component_types(types: &mut Vec<TypeId>) {
+        types.push(TypeId::of::<T>());
+    }
+
+    fn borrow(world: &EcsWorld) -> Option<Self::Storage<'_>> {
+        world.storage::<T>().map(|storage| storage.borrow_mut())
+    }
+
+    fn entities<'s>(storage: &'s Self::Storage<'_>) -> &'s [Entity] {
+        storage.entities()
+    }
+
+    fn fetch<'s>(storage: &'s mut Self::Storage<'_>, entity: Entity) -> Option<Self::Item<'s>> {
+        storage.get_mut(entity)
+    }
+}
+
+macro_rules! impl_query_param {
+    ($($param:ident),+) => {
+        #[allow(non_snake_case)]
+        impl<$($param: QueryParam),+> QueryParam for ($($param,)+) {
+            type Storage<'w> = ($($param::Storage<'w>,)+);
+            type Item<'s> = ($($param::Item<'s>,)+);
+
+            fn component_types(types: &mut Vec<TypeId>) {
+                $($param::component_types(types);)+
+            }
+
+            fn borrow(world: &EcsWorld) -> Option<Self::Storage<'_>> {
+                Some(($($param::borrow(world)?,)+))
+            }
+
+            fn entities<'s>(storage: &'s Self::Storage<'_>) -> &'s [Entity] {
+                let ($($param,)+) = storage;
+                let candidates = [$($param::entities($param)),+];
+                candidates.into_iter().min_by_key(|entities| entities.len()).unwrap_or(&[])
+            }
+
+            fn fetch<'s>(storage: &'s mut Self::Storage<'_>, entity: Entity) -> Option<Self::Item<'s>> {
+                let ($($param,)+) = storage;
+                Some(($($param::fetch($param, entity)?,)+))
+            }
+        }
+    };
+}
+
+impl_query_param!(A);
+impl_query_param!(A, B);
+impl_query_param!(A, B, C);
+impl_query_param!(A, B, C, D);
+impl_query_param!(A, B, C, D, E);
+impl_query_param!(A, B, C, D, E, F);
+
+/// Narrows a query down by the components an entity has. `fetched` lists the component types the
+/// query itself fetches: their storages are already borrowed, possibly mutably, so filters answer
+/// for those types without borrowing them again. A fetched entity always has them.
+pub trait QueryFilter {
+    fn matches(world: &EcsWorld, entity: Entity, fetched: &[TypeId]) -> bool;
+}
+
+/// Only entities that have a `T`.
+pub struct With<T>(PhantomData<T>);
+
+/// Only entities that don't have a `T`.
+pub struct Without<T>(PhantomData<T>);
+
+impl QueryFilter for () {
+    fn matches(_world: &EcsWorld, _entity: Entity, _fetched: &[TypeId]) -> bool {
+        true
+    }
+}
+
+impl<T: 'static> QueryFilter for With<T> {
+    fn matches(world: &EcsWorld, entity: Entity, fetched: &[TypeId]) -> bool {
+        fetched.contains(&TypeId::of::<T>()) || world.has::<T>(entity)
+    }
+}
+
+impl<T: 'static> QueryFilter for Without<T> {
+    fn matches(world: &EcsWorld, entity: Entity, fetched: &[TypeId]) -> bool {
+        !fetched.contains(&TypeId::of::<T>()) && !world.has::<T>(entity)
+    }
+}
+
+macro_rules! impl_query_filter {
+    ($($filter:ident),+) => {
+        impl<$($filter: QueryFilter),+> QueryFilter for ($($filter,)+) {
+            fn matches(world: &EcsWorld, entity: Entity, fetched: &[TypeId]) -> bool {
+                $($filter::matches(world, entity, fetched))&&+
+            }
+        }
+    };
+}
+
+impl_query_filter!(A);
+impl_query_filter!(A, B);
+impl_query_filter!(A, B, C);
+impl_query_filter!(A, B, C, D);
+
+/// Borrowed view over every entity with the components in `Q` that also passes `F`:
+///
+/// ```ignore
+/// world.query::<(&Transform, &mut Velocity), Without<Frozen>>()
+///     .for_each(|entity, (transform, velocity)| { /* ... */ });
+/// ```
+pub struct Query<'w, Q: QueryParam, F: QueryFilter = ()> {
+    world: &'w EcsWorld,
+    storage: Option<Q::Storage<'w>>,
+    fetched: Vec<TypeId>,
+    _filter: PhantomData<F>,
+}
+
+impl<'w, Q: QueryParam, F: QueryFilter> Query<'w, Q, F> {
+    pub(crate) fn new(world: &'w EcsWorld) -> Self {
+        let mut fetched = Vec::new();
+        Q::component_types(&mut fetched);
+
+        Self {
+            world,
+            storage: Q::borrow(world),
+            fetched,
+            _filter: PhantomData,
+        }
+    }
+
+    /// Every matching entity, in storage order.
+    pub fn entities(&self) -> Vec<Entity> {
+        let Some(storage) = self.storage.as_ref() else {
+            return Vec::new();
+        };
+
+        Q::entities(storage)
+            .iter()
+            .copied()
+            .filter(|entity| F::matches(self.world, *entity, &self.fetched))
+            .collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.entities().len()
+    }
+
+    pub fn get(&mut self, entity: Entity) -> Option<Q::Item<'_>> {
+        if !F::matches(self.world, entity, &self.fetched) {
+            return None;
+        }
+
+        Q::fetch(self.storage.as_mut()?, entity)
+    }
+
+    pub fn for_each<C>(&mut self, mut callback: C)
+    where C: FnMut(Entity, Q::Item<'_>)
+    {
+        let entities = self.entities();
+        let Some(storage) = self.storage.as_mut() else {
+            return;
+        };
+
+        for entity in entities {
+            if let Some(item) = Q::fetch(storage, entity) {
+                callback(entity, item);
+            }
+        }
+    }
+}
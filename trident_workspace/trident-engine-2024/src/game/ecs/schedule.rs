@@ -0,0 +1,104 @@
+use crate::game::ecs::EcsWorld;
+use crate::game_loop::FrameContext;
+use std::collections::BTreeMap;
+
+/// When a system runs within a frame. Stages run in declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    First,
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Last,
+}
+
+pub type System = Box<dyn FnMut(&mut EcsWorld)>;
+
+/// Systems grouped by `Stage`. Within a stage, systems run in the order they were added.
+#[derive(Default)]
+pub struct Schedule {
+    stages: BTreeMap<Stage, Vec<(String, System)>>,
+}
+
+impl Schedule {
+    pub fn new() -> Schedule {
+        Self::default()
+    }
+
+    pub fn add_system<F>(&mut self, stage: Stage, name: &str, system: F) -> &mut Self
+    where F: FnMut(&mut EcsWorld) + 'static
+    {
+        self.stages
+            .entry(stage)
+            .or_default()
+            .push((name.to_string(), Box::new(system)));
+        self
+    }
+
+    pub fn remove_system(&mut self, name: &str) -> bool {
+        let mut removed = false;
+
+        for systems in self.stages.values_mut() {
+            let before = systems.len();
+            systems.retain(|(system_name, _)| system_name != name);
+            removed |= systems.len() != before;
+        }
+
+        removed
+    }
+
+    pub fn system_names(&self) -> impl Iterator<Item = (Stage, &str)> {
+        self.stages
+            .iter()
+            .flat_map(|(stage, systems)| systems.iter().map(move |(name, _)| (*stage, name.as_str())))
+    }
+
+    /// Run every stage once.
+    pub fn run(&mut self, world: &mut EcsWorld) {
+        for systems in self.stages.values_mut() {
+            for (_, system) in systems.iter_mut() {
+                system(world);
+            }
+        }
+    }
+
+    /// Make `frame` available as a `FrameContext` resource, then run every stage once.
+    pub fn run_frame(&mut self, world: &mut EcsWorld, frame: &FrameContext) {
+        world.insert_resource(*frame);
+        self.run(world);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Log(Vec<&'static str>);
+
+    fn logging(entry: &'static str) -> impl FnMut(&mut EcsWorld) {
+        move |world| world.resource_mut::<Log>().unwrap().0.push(entry)
+    }
+
+    #[test]
+    fn stages_run_in_order_and_systems_in_insertion_order() {
+        let mut world = EcsWorld::new();
+        world.insert_resource(Log::default());
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Last, "render", logging("render"))
+            .add_system(Stage::Update, "move", logging("move"))
+            .add_system(Stage::First, "input", logging("input"))
+            .add_system(Stage::Update, "collide", logging("collide"));
+
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Log>().unwrap().0, ["input", "move", "collide", "render"]);
+
+        assert!(schedule.remove_system("move"));
+        assert!(!schedule.remove_system("move"));
+        world.insert_resource(Log::default());
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Log>().unwrap().0, ["input", "collide", "render"]);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::game::ecs::entity::Entity;
+use std::any::Any;
+use std::cell::RefCell;
+
+const EMPTY: u32 = u32::MAX;
+
+/// Packed storage for one component type. Lookups go through `sparse`, which maps an entity index
+/// to a slot in the densely packed `entities`/`components` arrays, so iteration never skips holes.
+pub struct SparseSet<T> {
+    sparse: Vec<u32>,
+    entities: Vec<Entity>,
+    components: Vec<T>,
+}
+
+impl<T> SparseSet<T> {
+    pub fn new() -> SparseSet<T> {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+
+    fn slot(&self, entity: Entity) -> Option<usize> {
+        let slot = *self.sparse.get(entity.index() as usize)?;
+        if slot == EMPTY || self.entities[slot as usize] != entity {
+            return None;
+        }
+
+        Some(slot as usize)
+    }
+
+    /// Insert or replace the component for `entity`, returning the old one.
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(slot) = self.slot(entity) {
+            return Some(std::mem::replace(&mut self.components[slot], component));
+        }
+
+        let index = entity.index() as usize;
+        if index >= self.sparse.len() {
+            self.sparse.resize(index + 1, EMPTY);
+        }
+
+        self.sparse[index] = self.entities.len() as u32;
+        self.entities.push(entity);
+        self.components.push(component);
+        None
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let slot = self.slot(entity)?;
+
+        self.sparse[entity.index() as usize] = EMPTY;
+        self.entities.swap_remove(slot);
+        let component = self.components.swap_remove(slot);
+
+        if let Some(moved) = self.entities.get(slot) {
+            self.sparse[moved.index() as usize] = slot as u32;
+        }
+
+        Some(component)
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.slot(entity).is_some()
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.slot(entity).map(|slot| &self.components[slot])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.slot(entity).map(|slot| &mut self.components[slot])
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Type-erased view of a component storage, so an entity can be removed from every storage
+/// without knowing their types.
+pub trait ComponentStorage {
+    fn contains(&self, entity: Entity) -> bool;
+    fn remove_entity(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: 'static> ComponentStorage for RefCell<SparseSet<T>> {
+    fn contains(&self, entity: Entity) -> bool {
+        self.borrow().contains(entity)
+    }
+
+    fn remove_entity(&mut self, entity: Entity) {
+        self.get_mut().remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
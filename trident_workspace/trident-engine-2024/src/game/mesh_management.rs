@@ -12,28 +12,33 @@ pub struct Mesh {
 }
 
 impl Mesh {
+    /// A mesh from planar data: every vertex position (3 floats each) followed by every texture
+    /// coordinate (2 floats each), drawn with one index per vertex.
     pub fn new(name: &str, mut mesh_data: Vec<f32>, texture_coords: Vec<f32>) -> Self {
-        let mut indices = Vec::with_capacity(mesh_data.len());
+        let vertex_count = mesh_data.len() / 3;
+        let texture_coords_offset = mesh_data.len() * size_of::<f32>();
+        mesh_data.extend(texture_coords);
 
-        mesh_data.append(&mut texture_coords.to_vec());
-        for index in 0..mesh_data.len() {
-            indices.push(index as u32);
-        }
-
-        let vao = VertexArrayObject::new(vec![
+        Self::from_vertices(name, mesh_data, (0..vertex_count as u32).collect(), vec![
            VertexAttributePointer::new((0, 3, gl::FLOAT, gl::FALSE, 0, 0)),
-           VertexAttributePointer::new((1, 2, gl::FLOAT, gl::FALSE, 0, 3 * size_of::<f32>())),
-        ]);
+           VertexAttributePointer::new((1, 2, gl::FLOAT, gl::FALSE, 0, texture_coords_offset)),
+        ])
+    }
 
-        let vbo = BufferObject::new(mesh_data, BufferType::ArrayBuffer);
+    /// A mesh from a vertex buffer laid out as `attributes` describe and triangles in `indices`.
+    pub fn from_vertices(name: &str, vertices: Vec<f32>, indices: Vec<u32>, attributes: Vec<VertexAttributePointer>) -> Self {
+        let attribute_count = attributes.len();
+        let vao = VertexArrayObject::new(attributes);
+        let vbo = BufferObject::new(vertices, BufferType::ArrayBuffer);
         let ebo = BufferObject::new(indices, BufferType::ElementArrayBuffer);
 
         vao.bind();
         vbo.bind();
         ebo.bind();
 
-        vao.set_attrib_pointer(0);
-        vao.set_attrib_pointer(1);
+        for index in 0..attribute_count {
+            vao.set_attrib_pointer(index);
+        }
         vao.enable_attrib_pointers();
 
         Self {
@@ -44,6 +49,29 @@ impl Mesh {
         }
     }
 
+    /// A unit quad in the XY plane facing +Z, with a position, a color and texture coordinates
+    /// per vertex at attribute locations 0, 1 and 2.
+    pub fn quad(name: &str) -> Self {
+        let vertices = vec![
+            -0.5f32, 0.5, 0.0, 1.0f32, 0.0, 0.0, 0.0, 1.0, // Vertex 0
+            -0.5f32, -0.5, 0.0, 0.0f32, 1.0, 0.0, 0.0, 0.0, // Vertex 1
+            0.5f32, -0.5, 0.0, 0.0f32, 0.0, 1.0, 1.0, 0.0, // Vertex 2
+            0.5f32, 0.5, 0.0, 0.0f32, 0.0, 0.0, 1.0, 1.0, // Vertex 3
+        ];
+
+        let indices = vec![
+            0u32, 1, 2,
+            2, 3, 0,
+        ];
+
+        let stride = 8 * size_of::<f32>();
+        Self::from_vertices(name, vertices, indices, vec![
+            VertexAttributePointer::new((0, 3, gl::FLOAT, gl::FALSE, stride, 0)),
+            VertexAttributePointer::new((1, 3, gl::FLOAT, gl::FALSE, stride, 3 * size_of::<f32>())),
+            VertexAttributePointer::new((2, 2, gl::FLOAT, gl::FALSE, stride, 6 * size_of::<f32>())),
+        ])
+    }
+
     pub async fn from_file(mesh_name: &str, path: &str) -> Option<Self> {
         let path = Path::new(path);
 
@@ -91,7 +119,20 @@ impl Mesh {
         }
     }
 
+    pub fn get_vbo(&self) -> &BufferObject<f32> {
+        &self.vbo
+    }
+
     pub fn get_ebo(&self) -> &BufferObject<u32> {
         &self.ebo
     }
+
+    pub fn get_vao(&self) -> &VertexArrayObject {
+        &self.vao
+    }
+
+    /// How many indices a draw call covers, three per triangle.
+    pub fn index_count(&self) -> usize {
+        self.ebo.get_data_len()
+    }
 }
\ No newline at end of file
@@ -1,9 +1,92 @@
+use std::collections::BTreeMap;
 use mesh_management::Mesh;
+use services::OwnerId;
+use world::WorldContext;
 
+pub mod camera;
+pub mod ecs;
 pub mod mesh_management;
+pub mod scene;
+pub mod scene_graph;
 pub mod services;
+pub mod transform;
+pub mod world;
 
+/// Something that lives in a `World`. Only `update` is required; the other lifecycle methods
+/// default to doing nothing. `dt` is the frame delta in seconds.
 pub trait GameObject {
-    fn get_mesh(&self) -> &Mesh;
-    fn update(&mut self);
-}
\ No newline at end of file
+    fn get_mesh(&self) -> Option<&Mesh> {
+        None
+    }
+
+    /// Called once, before the object's first `update`.
+    fn start(&mut self, _world: &mut WorldContext) {}
+
+    fn update(&mut self, dt: f32, world: &mut WorldContext);
+
+    /// Called after every object has had its `update` for the frame.
+    fn late_update(&mut self, _dt: f32, _world: &mut WorldContext) {}
+
+    /// Called when the object is despawned or the world is dropped.
+    fn on_destroy(&mut self, _world: &mut WorldContext) {}
+}
+
+pub type GameObjectFactory = Box<dyn Fn() -> Box<dyn GameObject> + Send + Sync>;
+
+struct RegisteredGameObject {
+    owner: Option<OwnerId>,
+    factory: GameObjectFactory,
+}
+
+/// `GameObject` types that can be created by name, e.g. ones registered by plugins.
+#[derive(Default)]
+pub struct GameObjectRegistry {
+    factories: BTreeMap<String, RegisteredGameObject>,
+}
+
+impl GameObjectRegistry {
+    pub fn new() -> GameObjectRegistry {
+        Self::default()
+    }
+
+    pub fn register<F>(&mut self, type_name: &str, factory: F)
+    where F: Fn() -> Box<dyn GameObject> + Send + Sync + 'static
+    {
+        self.insert(type_name, None, Box::new(factory));
+    }
+
+    /// Like `register`, remembering who registered the type so `unregister_owner` can remove it
+    /// and `World::despawn_owned` can find the objects created from it.
+    pub fn register_owned<F>(&mut self, owner: OwnerId, type_name: &str, factory: F)
+    where F: Fn() -> Box<dyn GameObject> + Send + Sync + 'static
+    {
+        self.insert(type_name, Some(owner), Box::new(factory));
+    }
+
+    fn insert(&mut self, type_name: &str, owner: Option<OwnerId>, factory: GameObjectFactory) {
+        self.factories.insert(type_name.to_string(), RegisteredGameObject { owner, factory });
+    }
+
+    pub fn unregister(&mut self, type_name: &str) -> bool {
+        self.factories.remove(type_name).is_some()
+    }
+
+    /// Remove every type `owner` registered. Returns how many there were.
+    pub fn unregister_owner(&mut self, owner: OwnerId) -> usize {
+        let count = self.factories.len();
+        self.factories.retain(|_, registered| registered.owner != Some(owner));
+        count - self.factories.len()
+    }
+
+    pub fn owner(&self, type_name: &str) -> Option<OwnerId> {
+        self.factories.get(type_name).and_then(|registered| registered.owner)
+    }
+
+    pub fn create(&self, type_name: &str) -> Option<Box<dyn GameObject>> {
+        self.factories.get(type_name).map(|registered| (registered.factory)())
+    }
+
+    pub fn type_names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(|name| name.as_str())
+    }
+}
@@ -0,0 +1,69 @@
+use crate::game::ecs::entity::Entity;
+use crate::game::ecs::EcsWorld;
+use crate::game::scene::scene_file::SceneValue;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+type SaveComponent = Box<dyn Fn(&EcsWorld, Entity) -> Option<Result<SceneValue, serde_json::Error>>>;
+type LoadComponent = Box<dyn Fn(&mut EcsWorld, Entity, SceneValue) -> Result<(), serde_json::Error>>;
+
+struct RegisteredComponent {
+    save: SaveComponent,
+    load: LoadComponent,
+}
+
+/// ECS component types that can be written to and read from scene files, by name.
+/// Components that aren't registered stay in memory but are left out of saved scenes.
+#[derive(Default)]
+pub struct ComponentRegistry {
+    components: BTreeMap<String, RegisteredComponent>,
+}
+
+impl ComponentRegistry {
+    pub fn new() -> ComponentRegistry {
+        Self::default()
+    }
+
+    pub fn register<T>(&mut self, name: &str) -> &mut Self
+    where T: Serialize + DeserializeOwned + 'static
+    {
+        self.components.insert(name.to_string(), RegisteredComponent {
+            save: Box::new(|world, entity| {
+                world.get::<T>(entity).map(|component| serde_json::to_value(&*component))
+            }),
+            load: Box::new(|world, entity, value| {
+                world.insert(entity, serde_json::from_value::<T>(value)?);
+                Ok(())
+            }),
+        });
+        self
+    }
+
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.components.contains_key(name)
+    }
+
+    /// Every registered component `entity` has, serialized by name.
+    pub fn save(&self, world: &EcsWorld, entity: Entity) -> Result<BTreeMap<String, SceneValue>, Box<dyn Error>> {
+        let mut saved = BTreeMap::new();
+
+        for (name, component) in self.components.iter() {
+            if let Some(value) = (component.save)(world, entity) {
+                saved.insert(name.clone(), value?);
+            }
+        }
+
+        Ok(saved)
+    }
+
+    pub fn load(&self, world: &mut EcsWorld, entity: Entity, name: &str, value: SceneValue) -> Result<(), Box<dyn Error>> {
+        let component = self.components
+            .get(name)
+            .ok_or_else(|| format!("Component {} is not registered", name))?;
+
+        (component.load)(world, entity, value)
+            .map_err(|e| format!("Invalid {} component: {}", name, e).into())
+    }
+}
@@ -0,0 +1,247 @@
+use crate::game::ecs::entity::Entity;
+use crate::game::ecs::EcsWorld;
+use crate::game::scene_graph::{NodeId, SceneGraph};
+use crate::game::transform::Transform;
+use components::ComponentRegistry;
+use prefab::{diff_overrides, PrefabLibrary, PrefabLink};
+use scene_file::{AssetReferences, PrefabInstance, SceneDescription, SceneObject, SceneValue};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::Path;
+
+pub mod components;
+pub mod prefab;
+pub mod scene_file;
+
+/// Links an entity to its node in the scene graph, where its transform lives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneNode(pub NodeId);
+
+/// Free-form game data loaded from a scene file, kept on the entity as-is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GameData(pub BTreeMap<String, SceneValue>);
+
+/// A loaded scene: the transform hierarchy in a `SceneGraph` and everything else in an `EcsWorld`.
+/// Every scene object is one graph node plus one entity carrying a `SceneNode`.
+pub struct Scene {
+    pub name: String,
+    pub graph: SceneGraph,
+    pub world: EcsWorld,
+    entities: HashMap<NodeId, Entity>,
+}
+
+impl Scene {
+    pub fn new(name: &str) -> Scene {
+        Self {
+            name: name.to_string(),
+            graph: SceneGraph::new(),
+            world: EcsWorld::new(),
+            entities: HashMap::new(),
+        }
+    }
+
+    /// Add an object under `parent`, or at the root with `None`.
+    pub fn spawn_object(&mut self, name: &str, parent: Option<NodeId>, transform: Transform) -> Result<(NodeId, Entity), Box<dyn Error>> {
+        let node = match parent {
+            Some(parent) => self.graph.add_child(parent, name, transform)?,
+            None => self.graph.add_node(name, transform),
+        };
+
+        let entity = self.world.spawn_with((SceneNode(node),));
+        self.entities.insert(node, entity);
+        Ok((node, entity))
+    }
+
+    /// Remove an object and all of its children.
+    pub fn despawn_object(&mut self, node: NodeId) {
+        let mut stack = vec![node];
+        while let Some(current) = stack.pop() {
+            stack.extend(self.graph.children(current).iter().copied());
+            if let Some(entity) = self.entities.remove(&current) {
+                self.world.despawn(entity);
+            }
+        }
+
+        self.graph.remove_node(node);
+    }
+
+    pub fn entity(&self, node: NodeId) -> Option<Entity> {
+        self.entities.get(&node).copied()
+    }
+
+    pub fn node(&self, entity: Entity) -> Option<NodeId> {
+        self.world.get::<SceneNode>(entity).map(|scene_node| scene_node.0)
+    }
+
+    /// Load a scene file, expanding the prefabs it references through `prefabs`.
+    pub fn load<P: AsRef<Path>>(path: P, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary) -> Result<Self, Box<dyn Error>> {
+        let description = SceneDescription::load(path, prefabs.migrations())?;
+        Self::from_description(&description, registry, prefabs)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary) -> Result<(), Box<dyn Error>> {
+        self.to_description(registry, prefabs)?.save(path)
+    }
+
+    pub fn from_description(description: &SceneDescription, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary) -> Result<Self, Box<dyn Error>> {
+        let mut scene = Self::new(&description.name);
+
+        for object in description.objects.iter() {
+            let object = prefabs.expand(object)?;
+            scene.instantiate(&object, None, registry)?;
+        }
+
+        Ok(scene)
+    }
+
+    /// Add `object` and its children under `parent`. Returns the node of `object`.
+    /// Prefab references must already be expanded, see `PrefabLibrary::expand`.
+    pub fn instantiate(&mut self, object: &SceneObject, parent: Option<NodeId>, registry: &ComponentRegistry) -> Result<NodeId, Box<dyn Error>> {
+        let (node, entity) = self.spawn_object(&object.name, parent, object.transform.into())?;
+
+        if !object.assets.is_empty() {
+            self.world.insert(entity, object.assets.clone());
+        }
+        if !object.data.is_empty() {
+            self.world.insert(entity, GameData(object.data.clone()));
+        }
+        if let Some(instance) = &object.prefab {
+            self.world.insert(entity, PrefabLink(instance.clone()));
+        }
+        for (name, value) in object.components.iter() {
+            registry.load(&mut self.world, entity, name, value.clone())?;
+        }
+
+        for child in object.children.iter() {
+            self.instantiate(child, Some(node), registry)?;
+        }
+
+        Ok(node)
+    }
+
+    /// Add an instance of the prefab at `path` under `parent`, named after the prefab.
+    pub fn instantiate_prefab(
+        &mut self,
+        path: &str,
+        parent: Option<NodeId>,
+        transform: Transform,
+        registry: &ComponentRegistry,
+        prefabs: &mut PrefabLibrary) -> Result<NodeId, Box<dyn Error>>
+    {
+        let object = SceneObject {
+            transform: transform.into(),
+            prefab: Some(PrefabInstance { path: path.to_string(), overrides: BTreeMap::new() }),
+            ..SceneObject::default()
+        };
+
+        let object = prefabs.expand(&object)?;
+        self.instantiate(&object, parent, registry)
+    }
+
+    /// Re-read the prefab at `path` and rebuild every instance that uses it, directly or nested
+    /// inside another prefab. Changes to the prefab show up wherever an instance didn't override
+    /// them. Rebuilt instances get new nodes in the same place among their siblings; the returned
+    /// pairs map old nodes to new ones.
+    pub fn reload_prefab(&mut self, path: &str, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary) -> Result<Vec<(NodeId, NodeId)>, Box<dyn Error>> {
+        // Work out the overrides against the old prefab, before it's replaced.
+        let mut instances = Vec::new();
+        let mut stack: Vec<NodeId> = self.graph.roots().to_vec();
+        while let Some(node) = stack.pop() {
+            match self.prefab_instance(node) {
+                Some(instance) => {
+                    if prefabs.depends_on(&instance.path, path)? {
+                        instances.push((node, self.graph.parent(node), self.describe(node, registry, prefabs)?));
+                    }
+                },
+                None => stack.extend(self.graph.children(node).iter().copied()),
+            }
+        }
+
+        prefabs.reload(path)?;
+
+        let mut rebuilt = Vec::with_capacity(instances.len());
+        for (node, parent, object) in instances {
+            let object = prefabs.expand(&object)?;
+            let index = self.graph.sibling_index(node).unwrap_or(usize::MAX);
+            self.despawn_object(node);
+
+            let new_node = self.instantiate(&object, parent, registry)?;
+            self.graph.set_sibling_index(new_node, index);
+            rebuilt.push((node, new_node));
+        }
+
+        Ok(rebuilt)
+    }
+
+    /// The prefab `node` is the root of an instance of, if any.
+    pub fn prefab_instance(&self, node: NodeId) -> Option<PrefabInstance> {
+        let entity = self.entity(node)?;
+        self.world.get::<PrefabLink>(entity).map(|link| link.0.clone())
+    }
+
+    pub fn to_description(&self, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary) -> Result<SceneDescription, Box<dyn Error>> {
+        let mut description = SceneDescription::new(&self.name);
+
+        for root in self.graph.roots() {
+            description.objects.push(self.describe(*root, registry, prefabs)?);
+        }
+
+        Ok(description)
+    }
+
+    /// The `SceneObject` for `node` and everything under it. Prefab instances are written as a
+    /// reference to the prefab plus whatever differs from it.
+    pub fn describe(&self, node: NodeId, registry: &ComponentRegistry, prefabs: &mut PrefabLibrary) -> Result<SceneObject, Box<dyn Error>> {
+        let Some(instance) = self.prefab_instance(node) else {
+            let mut object = self.describe_node(node, registry)?;
+            for child in self.graph.children(node) {
+                object.children.push(self.describe(*child, registry, prefabs)?);
+            }
+            return Ok(object);
+        };
+
+        let base = prefabs.expand_prefab(&instance.path)?;
+        let current = self.describe_expanded(node, registry)?;
+
+        Ok(SceneObject {
+            name: current.name.clone(),
+            transform: current.transform,
+            prefab: Some(PrefabInstance {
+                path: instance.path,
+                overrides: diff_overrides(&base, &current)?,
+            }),
+            ..SceneObject::default()
+        })
+    }
+
+    /// `node` and everything under it written out in full, ignoring prefab links.
+    fn describe_expanded(&self, node: NodeId, registry: &ComponentRegistry) -> Result<SceneObject, Box<dyn Error>> {
+        let mut object = self.describe_node(node, registry)?;
+
+        for child in self.graph.children(node) {
+            object.children.push(self.describe_expanded(*child, registry)?);
+        }
+
+        Ok(object)
+    }
+
+    fn describe_node(&self, node: NodeId, registry: &ComponentRegistry) -> Result<SceneObject, Box<dyn Error>> {
+        let mut object = SceneObject {
+            name: self.graph.name(node).unwrap_or_default().to_string(),
+            transform: self.graph.local_transform(node).copied().unwrap_or_default().into(),
+            ..SceneObject::default()
+        };
+
+        if let Some(entity) = self.entity(node) {
+            if let Some(assets) = self.world.get::<AssetReferences>(entity) {
+                object.assets = assets.clone();
+            }
+            if let Some(data) = self.world.get::<GameData>(entity) {
+                object.data = data.0.clone();
+            }
+            object.components = registry.save(&self.world, entity)?;
+        }
+
+        Ok(object)
+    }
+}
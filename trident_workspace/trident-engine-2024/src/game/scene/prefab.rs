@@ -0,0 +1,436 @@
+use super::scene_file::{PrefabInstance, SceneDescription, SceneMigrations, SceneObject, SceneValue};
+use log::warn;
+use serde_json::Map;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+
+/// Put on the root entity of a prefab instance, so the instance is saved back as a reference
+/// plus overrides instead of a copy of the prefab.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabLink(pub PrefabInstance);
+
+/// Loads and caches prefabs. A prefab file is a scene file with exactly one root object.
+#[derive(Default)]
+pub struct PrefabLibrary {
+    migrations: SceneMigrations,
+    prefabs: HashMap<String, SceneObject>,
+}
+
+impl PrefabLibrary {
+    pub fn new() -> PrefabLibrary {
+        Self::default()
+    }
+
+    pub fn with_migrations(migrations: SceneMigrations) -> PrefabLibrary {
+        Self {
+            migrations,
+            prefabs: HashMap::new(),
+        }
+    }
+
+    /// Migrations used for prefab files and for scenes loaded through `Scene::load`.
+    pub fn migrations(&self) -> &SceneMigrations {
+        &self.migrations
+    }
+
+    /// The prefab's root object as written in its file, loading it on first use.
+    /// Fails if the file breaks the rules in `check_child_names`.
+    pub fn get(&mut self, path: &str) -> Result<&SceneObject, Box<dyn Error>> {
+        if !self.prefabs.contains_key(path) {
+            let description = SceneDescription::load(path, &self.migrations)?;
+            let [root] = <[SceneObject; 1]>::try_from(description.objects).map_err(|objects| {
+                format!("Prefab {} must have exactly one root object, found {}", path, objects.len())
+            })?;
+
+            check_child_names(&root).map_err(|e| format!("Invalid prefab {}: {}", path, e))?;
+            self.prefabs.insert(path.to_string(), root);
+        }
+
+        Ok(&self.prefabs[path])
+    }
+
+    /// Use `root` for `path` without reading the file, e.g. for prefabs built in code.
+    pub fn insert(&mut self, path: &str, root: SceneObject) -> Result<(), Box<dyn Error>> {
+        check_child_names(&root).map_err(|e| format!("Invalid prefab {}: {}", path, e))?;
+        self.prefabs.insert(path.to_string(), root);
+        Ok(())
+    }
+
+    /// Write `root` to `path` as a prefab and cache it.
+    pub fn save(&mut self, path: &str, root: SceneObject) -> Result<(), Box<dyn Error>> {
+        check_child_names(&root).map_err(|e| format!("Invalid prefab {}: {}", path, e))?;
+        let mut description = SceneDescription::new(&root.name);
+        description.objects.push(root);
+        description.save(path)?;
+
+        self.prefabs.insert(path.to_string(), description.objects.pop().unwrap());
+        Ok(())
+    }
+
+    /// Read `path` from disk again. Use `Scene::reload_prefab` to update instances as well.
+    pub fn reload(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        self.prefabs.remove(path);
+        self.get(path).map(|_| ())
+    }
+
+    /// `object` with every prefab reference in it, nested ones included, replaced by the prefab's
+    /// contents with the instance's overrides applied. Expanded instance roots keep their `prefab`.
+    pub fn expand(&mut self, object: &SceneObject) -> Result<SceneObject, Box<dyn Error>> {
+        self.expand_object(object, &mut Vec::new())
+    }
+
+    /// The prefab at `path` fully expanded, without any instance overrides.
+    pub fn expand_prefab(&mut self, path: &str) -> Result<SceneObject, Box<dyn Error>> {
+        self.expand_prefab_inner(path, &mut Vec::new())
+    }
+
+    fn expand_prefab_inner(&mut self, path: &str, stack: &mut Vec<String>) -> Result<SceneObject, Box<dyn Error>> {
+        if stack.iter().any(|parent| parent == path) {
+            return Err(format!("Prefab {} contains itself: {} -> {}", path, stack.join(" -> "), path).into());
+        }
+
+        let root = self.get(path)?.clone();
+        stack.push(path.to_string());
+        let expanded = self.expand_object(&root, stack);
+        stack.pop();
+
+        expanded
+    }
+
+    fn expand_object(&mut self, object: &SceneObject, stack: &mut Vec<String>) -> Result<SceneObject, Box<dyn Error>> {
+        let mut expanded = match &object.prefab {
+            Some(instance) => {
+                let base = self.expand_prefab_inner(&instance.path, stack)?;
+                let mut expanded = apply_overrides(&base, instance)?;
+
+                if !object.name.is_empty() {
+                    expanded.name = object.name.clone();
+                }
+                expanded.transform = object.transform;
+                expanded.prefab = Some(instance.clone());
+                expanded
+            },
+            None => SceneObject { children: Vec::new(), ..object.clone() },
+        };
+
+        for child in object.children.iter() {
+            expanded.children.push(self.expand_object(child, stack)?);
+        }
+
+        Ok(expanded)
+    }
+
+    /// Whether `prefab` is `dependency` or has it nested somewhere inside.
+    pub fn depends_on(&mut self, prefab: &str, dependency: &str) -> Result<bool, Box<dyn Error>> {
+        self.depends_on_inner(prefab, dependency, &mut Vec::new())
+    }
+
+    fn depends_on_inner(&mut self, prefab: &str, dependency: &str, stack: &mut Vec<String>) -> Result<bool, Box<dyn Error>> {
+        if prefab == dependency {
+            return Ok(true);
+        }
+        if stack.iter().any(|parent| parent == prefab) {
+            return Ok(false);
+        }
+
+        let mut referenced = Vec::new();
+        let mut objects = vec![self.get(prefab)?];
+        while let Some(object) = objects.pop() {
+            if let Some(instance) = &object.prefab {
+                referenced.push(instance.path.clone());
+            }
+            objects.extend(object.children.iter());
+        }
+
+        stack.push(prefab.to_string());
+        for path in referenced {
+            if self.depends_on_inner(&path, dependency, stack)? {
+                return Ok(true);
+            }
+        }
+        stack.pop();
+
+        Ok(false)
+    }
+}
+
+/// `base` with the instance's overrides written over it. Overrides that no longer match anything
+/// in the prefab, e.g. because the child they point into was removed, are skipped with a warning.
+fn apply_overrides(base: &SceneObject, instance: &PrefabInstance) -> Result<SceneObject, Box<dyn Error>> {
+    let mut value = serde_json::to_value(base)?;
+
+    for (path, override_value) in instance.overrides.iter() {
+        let segments: Vec<&str> = path.split('/').collect();
+        if !apply_override(&mut value, &segments, override_value) {
+            warn!("Override {} no longer matches anything in prefab {}", path, instance.path);
+        }
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Set the property at `segments` to `value`. A null value removes the property, and a whole
+/// child that doesn't exist yet is added.
+fn apply_override(target: &mut SceneValue, segments: &[&str], value: &SceneValue) -> bool {
+    match segments {
+        [] => {
+            *target = value.clone();
+            true
+        },
+        ["children", name, rest @ ..] => {
+            let Some(object) = target.as_object_mut() else {
+                return false;
+            };
+            let Some(children) = object
+                .entry("children")
+                .or_insert_with(|| SceneValue::Array(Vec::new()))
+                .as_array_mut() else {
+                return false;
+            };
+
+            let position = children.iter().position(|child| child_name(child) == Some(name));
+            match position {
+                Some(index) if rest.is_empty() && value.is_null() => {
+                    children.remove(index);
+                    true
+                },
+                Some(index) => apply_override(&mut children[index], rest, value),
+                None if rest.is_empty() => {
+                    if !value.is_null() {
+                        children.push(value.clone());
+                    }
+                    true
+                },
+                None => false,
+            }
+        },
+        [key, rest @ ..] => {
+            if target.is_null() {
+                *target = SceneValue::Object(Map::new());
+            }
+            let Some(object) = target.as_object_mut() else {
+                return false;
+            };
+
+            if rest.is_empty() && value.is_null() {
+                object.remove(*key);
+                return true;
+            }
+
+            apply_override(object.entry(*key).or_insert(SceneValue::Null), rest, value)
+        },
+    }
+}
+
+/// Overrides address children by name, so within a prefab every child needs a name that's unique
+/// among its siblings and has no `/` in it.
+pub fn check_child_names(object: &SceneObject) -> Result<(), Box<dyn Error>> {
+    let mut names = HashSet::new();
+
+    for child in object.children.iter() {
+        if child.name.is_empty() || child.name.contains('/') {
+            return Err(format!("Child {:?} of {} needs a name without '/'", child.name, object.name).into());
+        }
+        if !names.insert(child.name.as_str()) {
+            return Err(format!("{} has more than one child called {}", object.name, child.name).into());
+        }
+
+        check_child_names(child)?;
+    }
+
+    Ok(())
+}
+
+/// The overrides that turn the expanded prefab `base` into `current`, the instance as it is now.
+/// The root's name and transform are left out since the instance stores them itself.
+/// Fails if either breaks the rules in `check_child_names`.
+pub fn diff_overrides(base: &SceneObject, current: &SceneObject) -> Result<BTreeMap<String, SceneValue>, Box<dyn Error>> {
+    check_child_names(base)?;
+    check_child_names(current)?;
+
+    let mut overrides = BTreeMap::new();
+    diff_values(&serde_json::to_value(base)?, &serde_json::to_value(current)?, "", true, &mut overrides);
+    Ok(overrides)
+}
+
+fn diff_values(base: &SceneValue, current: &SceneValue, prefix: &str, is_root: bool, overrides: &mut BTreeMap<String, SceneValue>) {
+    let empty = Map::new();
+    let base_object = base.as_object().unwrap_or(&empty);
+    let current_object = current.as_object().unwrap_or(&empty);
+    let join = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{}/{}", prefix, key) };
+
+    let keys = base_object.keys().chain(current_object.keys().filter(|key| !base_object.contains_key(*key)));
+    for key in keys {
+        // Children are matched by name below; links of nested prefabs are part of the base.
+        let skipped = match key.as_str() {
+            "children" | "prefab" => true,
+            "name" | "transform" => is_root,
+            _ => false,
+        };
+        if skipped {
+            continue;
+        }
+
+        let base_value = base_object.get(key).unwrap_or(&SceneValue::Null);
+        let current_value = current_object.get(key).unwrap_or(&SceneValue::Null);
+
+        let is_map = |value: &SceneValue| value.is_object() || value.is_null();
+        if is_map(base_value) && is_map(current_value) && (base_value.is_object() || current_value.is_object()) {
+            diff_values(base_value, current_value, &join(key), false, overrides);
+        } else if !values_match(base_value, current_value) {
+            overrides.insert(join(key), current_value.clone());
+        }
+    }
+
+    let no_children = Vec::new();
+    let base_children = base.get("children").and_then(|children| children.as_array()).unwrap_or(&no_children);
+    let current_children = current.get("children").and_then(|children| children.as_array()).unwrap_or(&no_children);
+
+    for current_child in current_children {
+        let Some(name) = child_name(current_child) else {
+            continue;
+        };
+        let path = join(&format!("children/{}", name));
+
+        match base_children.iter().find(|base_child| child_name(base_child) == Some(name)) {
+            Some(base_child) => diff_values(base_child, current_child, &path, false, overrides),
+            None => { overrides.insert(path, current_child.clone()); },
+        }
+    }
+
+    for base_child in base_children {
+        let Some(name) = child_name(base_child) else {
+            continue;
+        };
+        if !current_children.iter().any(|current_child| child_name(current_child) == Some(name)) {
+            overrides.insert(join(&format!("children/{}", name)), SceneValue::Null);
+        }
+    }
+}
+
+/// Equality that lets floats drift a little, since transforms are renormalized on the way in.
+fn values_match(a: &SceneValue, b: &SceneValue) -> bool {
+    match (a, b) {
+        (SceneValue::Number(a), SceneValue::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => (a - b).abs() <= 1e-5,
+            _ => a == b,
+        },
+        (SceneValue::Array(a), SceneValue::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| values_match(a, b))
+        },
+        _ => a == b,
+    }
+}
+
+fn child_name(child: &SceneValue) -> Option<&str> {
+    child.get("name").and_then(|name| name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::scene::components::ComponentRegistry;
+    use crate::game::scene::{GameData, Scene};
+    use crate::game::transform::Transform;
+    use serde_json::json;
+
+    fn object(name: &str, children: Vec<SceneObject>) -> SceneObject {
+        SceneObject { name: name.to_string(), children, ..SceneObject::default() }
+    }
+
+    fn with_data(mut object: SceneObject, key: &str, value: SceneValue) -> SceneObject {
+        object.data.insert(key.to_string(), value);
+        object
+    }
+
+    fn instance(name: &str, path: &str, overrides: &[(&str, SceneValue)]) -> SceneObject {
+        let overrides = overrides.iter().map(|(path, value)| (path.to_string(), value.clone())).collect();
+        SceneObject {
+            name: name.to_string(),
+            prefab: Some(PrefabInstance { path: path.to_string(), overrides }),
+            ..SceneObject::default()
+        }
+    }
+
+    fn root_names(scene: &Scene) -> Vec<&str> {
+        scene.graph.roots().iter().map(|root| scene.graph.name(*root).unwrap()).collect()
+    }
+
+    #[test]
+    fn overrides_survive_a_reload_and_instances_keep_their_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crate.ron").to_string_lossy().into_owned();
+        let registry = ComponentRegistry::new();
+        let mut prefabs = PrefabLibrary::new();
+
+        let lid = with_data(object("Lid", vec![]), "color", json!("red"));
+        prefabs.save(&path, with_data(object("Crate", vec![lid]), "weight", json!(10))).unwrap();
+
+        let mut scene = Scene::new("Test");
+        scene.spawn_object("Before", None, Transform::IDENTITY).unwrap();
+        let crate_node = scene.instantiate_prefab(&path, None, Transform::IDENTITY, &registry, &mut prefabs).unwrap();
+        scene.spawn_object("After", None, Transform::IDENTITY).unwrap();
+
+        // Override the lid's color on this instance only.
+        let lid_node = scene.graph.children(crate_node)[0];
+        let lid_entity = scene.entity(lid_node).unwrap();
+        scene.world.get_mut::<GameData>(lid_entity).unwrap().0.insert("color".to_string(), json!("blue"));
+
+        let described = scene.describe(crate_node, &registry, &mut prefabs).unwrap();
+        let overrides = &described.prefab.as_ref().unwrap().overrides;
+        assert_eq!(overrides.get("children/Lid/data/color"), Some(&json!("blue")));
+        assert_eq!(overrides.len(), 1);
+
+        // Change both the overridden and an untouched property in the prefab file.
+        let lid = with_data(object("Lid", vec![]), "color", json!("green"));
+        SceneDescription { objects: vec![with_data(object("Crate", vec![lid]), "weight", json!(20))], ..SceneDescription::new("Crate") }
+            .save(&path)
+            .unwrap();
+
+        let rebuilt = scene.reload_prefab(&path, &registry, &mut prefabs).unwrap();
+        assert_eq!(rebuilt.len(), 1);
+        assert_eq!(root_names(&scene), ["Before", "Crate", "After"]);
+
+        let reloaded = scene.describe_expanded(rebuilt[0].1, &registry).unwrap();
+        assert_eq!(reloaded.data.get("weight"), Some(&json!(20)));
+        assert_eq!(reloaded.children[0].data.get("color"), Some(&json!("blue")));
+    }
+
+    #[test]
+    fn nested_prefabs_expand_with_their_own_overrides() {
+        let mut prefabs = PrefabLibrary::new();
+        prefabs.insert("wheel", with_data(object("Wheel", vec![]), "radius", json!(1.0))).unwrap();
+        prefabs.insert("car", object("Car", vec![
+            instance("Front", "wheel", &[]),
+            instance("Back", "wheel", &[("data/radius", json!(1.5))]),
+        ])).unwrap();
+
+        let car = instance("Red Car", "car", &[("children/Front/data/radius", json!(0.5))]);
+        let expanded = prefabs.expand(&car).unwrap();
+
+        assert_eq!(expanded.name, "Red Car");
+        let radii: Vec<_> = expanded.children.iter().map(|child| child.data["radius"].clone()).collect();
+        assert_eq!(radii, [json!(0.5), json!(1.5)]);
+        assert!(expanded.children.iter().all(|child| child.prefab.as_ref().unwrap().path == "wheel"));
+
+        assert!(prefabs.depends_on("car", "wheel").unwrap());
+        assert!(!prefabs.depends_on("wheel", "car").unwrap());
+
+        prefabs.insert("loop", instance("Loop", "loop", &[])).unwrap();
+        assert!(prefabs.expand_prefab("loop").is_err());
+    }
+
+    #[test]
+    fn child_names_must_be_unique_and_free_of_slashes() {
+        let mut prefabs = PrefabLibrary::new();
+
+        let duplicates = object("Car", vec![object("Wheel", vec![]), object("Wheel", vec![])]);
+        assert!(prefabs.insert("car", duplicates.clone()).is_err());
+
+        let nested_slash = object("Car", vec![object("Body", vec![object("Left/Door", vec![])])]);
+        assert!(prefabs.insert("car", nested_slash).is_err());
+
+        assert!(diff_overrides(&object("Car", vec![]), &duplicates).is_err());
+        assert!(prefabs.insert("car", object("Car", vec![object("Wheel", vec![object("Wheel", vec![])])])).is_ok());
+    }
+}
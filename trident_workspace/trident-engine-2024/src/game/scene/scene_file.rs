@@ -0,0 +1,312 @@
+use crate::game::transform::Transform;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Schema version written to new scene files. Bump it together with a migration in `SceneMigrations`.
+pub const SCENE_SCHEMA_VERSION: u32 = 1;
+
+/// Untyped value for component and custom data, whichever format the file is in.
+pub type SceneValue = serde_json::Value;
+
+/// Upgrades the raw contents of a scene file from one schema version to the next.
+pub type MigrationStep = Box<dyn Fn(&mut SceneValue) -> Result<(), Box<dyn Error>>>;
+
+/// Translation, rotation (as `[x, y, z, w]`) and scale the way they're written in a scene file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformData {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for TransformData {
+    fn default() -> Self {
+        Transform::IDENTITY.into()
+    }
+}
+
+impl From<Transform> for TransformData {
+    fn from(transform: Transform) -> Self {
+        let rotation = transform.rotation.coords;
+
+        Self {
+            translation: transform.translation.into(),
+            rotation: [rotation.x, rotation.y, rotation.z, rotation.w],
+            scale: transform.scale.into(),
+        }
+    }
+}
+
+impl From<TransformData> for Transform {
+    fn from(data: TransformData) -> Self {
+        let [x, y, z, w] = data.rotation;
+
+        Transform::new(
+            data.translation.into(),
+            glm::quat_normalize(&glm::quat(x, y, z, w)),
+            data.scale.into(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShaderReference {
+    pub vertex: String,
+    pub fragment: String,
+}
+
+/// Asset files an object uses. Like every asset path in the engine, `mesh` and `texture` are
+/// relative to the workspace root the engine runs from, e.g. `trident-engine-2024/res/textures/grass.png`.
+/// Shaders are file names inside the shaders directory, see `Shader::load_shader_source`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AssetReferences {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub texture: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shader: Option<ShaderReference>,
+}
+
+impl AssetReferences {
+    pub fn is_empty(&self) -> bool {
+        self.mesh.is_none() && self.texture.is_none() && self.shader.is_none()
+    }
+}
+
+/// Marks an object as an instance of the prefab at `path`. `overrides` maps property paths inside
+/// the prefab, like `"assets/mesh"` or `"children/Wheel/transform/scale"`, to the value this
+/// instance uses instead. Children are addressed by name, so sibling names in a prefab must be
+/// unique and free of `/`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrefabInstance {
+    pub path: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub overrides: BTreeMap<String, SceneValue>,
+}
+
+/// One object in a scene file, with its children nested under it.
+/// When `prefab` is set, the object's name and transform replace the prefab root's, and its
+/// children are added next to the prefab's own.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneObject {
+    pub name: String,
+    pub transform: TransformData,
+    #[serde(skip_serializing_if = "AssetReferences::is_empty")]
+    pub assets: AssetReferences,
+    /// Components by the name they were registered under in `ComponentRegistry`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, SceneValue>,
+    /// Free-form game data the engine doesn't interpret.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub data: BTreeMap<String, SceneValue>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SceneObject>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefab: Option<PrefabInstance>,
+}
+
+/// The on-disk form of a scene. Saved as RON or JSON depending on the file extension.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+    pub version: u32,
+    pub name: String,
+    #[serde(default)]
+    pub objects: Vec<SceneObject>,
+}
+
+impl SceneDescription {
+    pub fn new(name: &str) -> SceneDescription {
+        Self {
+            version: SCENE_SCHEMA_VERSION,
+            name: name.to_string(),
+            objects: Vec::new(),
+        }
+    }
+
+    /// Load a `.ron` or `.json` scene, upgrading older schema versions with `migrations`.
+    pub fn load<P: AsRef<Path>>(path: P, migrations: &SceneMigrations) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        let raw: SceneValue = match path.extension().and_then(|ext| ext.to_str()) {
+            // RON structs only come through untyped as `ron::Value` maps.
+            Some("ron") => serde_json::to_value(ron::from_str::<ron::Value>(&contents)?)?,
+            Some("json") => serde_json::from_str(&contents)?,
+            _ => return Err(format!("Unsupported scene file format: {}", path.display()).into()),
+        };
+
+        Self::from_value(raw, migrations)
+    }
+
+    /// Build a description from untyped file contents, running migrations first.
+    pub fn from_value(mut raw: SceneValue, migrations: &SceneMigrations) -> Result<Self, Box<dyn Error>> {
+        migrations.migrate(&mut raw)?;
+        Ok(serde_json::from_value(raw)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+
+        // Objects are written as RON structs; `load` reads those back as maps, so files stay
+        // readable before migrations have run.
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => {
+                let config = ron::ser::PrettyConfig::default().compact_arrays(true);
+                ron::ser::to_string_pretty(self, config)?
+            },
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => return Err(format!("Unsupported scene file format: {}", path.display()).into()),
+        };
+
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Steps that upgrade scene files written with older schema versions. A step registered for
+/// version `n` receives the raw contents of a version `n` file and must leave it valid for `n + 1`.
+#[derive(Default)]
+pub struct SceneMigrations {
+    steps: BTreeMap<u32, MigrationStep>,
+}
+
+impl SceneMigrations {
+    pub fn new() -> SceneMigrations {
+        Self::default()
+    }
+
+    pub fn register<F>(&mut self, from_version: u32, step: F) -> &mut Self
+    where F: Fn(&mut SceneValue) -> Result<(), Box<dyn Error>> + 'static
+    {
+        self.steps.insert(from_version, Box::new(step));
+        self
+    }
+
+    /// Upgrade `raw` to `SCENE_SCHEMA_VERSION`, updating its `version` field as it goes.
+    pub fn migrate(&self, raw: &mut SceneValue) -> Result<(), Box<dyn Error>> {
+        let mut version = raw
+            .get("version")
+            .and_then(|version| version.as_u64())
+            .ok_or("Scene file has no schema version")? as u32;
+
+        if version > SCENE_SCHEMA_VERSION {
+            return Err(format!(
+                "Scene schema version {} is newer than the supported version {}",
+                version, SCENE_SCHEMA_VERSION
+            ).into());
+        }
+
+        while version < SCENE_SCHEMA_VERSION {
+            let step = self.steps
+                .get(&version)
+                .ok_or_else(|| format!("No migration from scene schema version {}", version))?;
+
+            step(raw)?;
+            version += 1;
+            raw["version"] = SceneValue::from(version);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn description() -> SceneDescription {
+        let mut data = BTreeMap::new();
+        data.insert("spin_speed".to_string(), json!(0.5));
+        data.insert("tags".to_string(), json!(["a", "b"]));
+
+        let mut components = BTreeMap::new();
+        components.insert("Health".to_string(), json!({ "current": 3, "max": 10 }));
+
+        let child = SceneObject {
+            name: "Child".to_string(),
+            transform: TransformData { translation: [0.5, 0.0, -1.25], ..TransformData::default() },
+            data,
+            ..SceneObject::default()
+        };
+
+        let mut overrides = BTreeMap::new();
+        overrides.insert("transform/scale".to_string(), json!([2.0, 2.0, 2.0]));
+
+        let mut description = SceneDescription::new("Round Trip");
+        description.objects.push(SceneObject {
+            name: "Root".to_string(),
+            transform: TransformData { rotation: [0.0, 0.6, 0.0, 0.8], scale: [1.0, 2.0, 3.0], ..TransformData::default() },
+            assets: AssetReferences {
+                mesh: None,
+                texture: Some("trident-engine-2024/res/textures/grass.png".to_string()),
+                shader: Some(ShaderReference { vertex: "main_vertex.glsl".to_string(), fragment: "main_fragment.glsl".to_string() }),
+            },
+            components,
+            children: vec![child],
+            ..SceneObject::default()
+        });
+        description.objects.push(SceneObject {
+            name: "Instance".to_string(),
+            prefab: Some(PrefabInstance { path: "prefabs/crate.ron".to_string(), overrides }),
+            ..SceneObject::default()
+        });
+
+        description
+    }
+
+    #[test]
+    fn round_trips_through_ron_and_json() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for file_name in ["scene.ron", "scene.json"] {
+            let path = dir.path().join(file_name);
+            description().save(&path).unwrap();
+            let loaded = SceneDescription::load(&path, &SceneMigrations::new()).unwrap();
+            assert_eq!(loaded, description(), "{}", file_name);
+        }
+
+        let ron = fs::read_to_string(dir.path().join("scene.ron")).unwrap();
+        assert!(ron.contains("name: \"Round Trip\""), "{}", ron);
+    }
+
+    #[test]
+    fn migrations_upgrade_older_files() {
+        let mut migrations = SceneMigrations::new();
+        migrations.register(SCENE_SCHEMA_VERSION - 1, |raw| {
+            raw["name"] = raw["title"].take();
+            Ok(())
+        });
+
+        let raw = json!({ "version": SCENE_SCHEMA_VERSION - 1, "title": "Old", "objects": [] });
+        let description = SceneDescription::from_value(raw.clone(), &migrations).unwrap();
+        assert_eq!(description, SceneDescription::new("Old"));
+
+        assert!(SceneDescription::from_value(raw, &SceneMigrations::new()).is_err());
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let raw = json!({ "version": SCENE_SCHEMA_VERSION + 1, "name": "Future", "objects": [] });
+        let error = SceneDescription::from_value(raw, &SceneMigrations::new()).unwrap_err();
+        assert!(error.to_string().contains("newer"), "{}", error);
+    }
+
+    #[test]
+    fn demo_scene_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("res/scenes/demo.ron");
+        let description = SceneDescription::load(path, &SceneMigrations::new()).unwrap();
+
+        assert_eq!(description.name, "Demo");
+        assert_eq!(description.objects[0].name, "Quad");
+        assert_eq!(description.objects[0].children[0].name, "Quad Child");
+    }
+}
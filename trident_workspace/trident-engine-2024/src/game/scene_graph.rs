@@ -0,0 +1,424 @@
+use crate::game::transform::{look_rotation, Transform};
+use nalgebra_glm as glm;
+use nalgebra_glm::{Mat4, Quat, Vec3};
+use std::error::Error;
+
+/// Handle to a node in a `SceneGraph`. The generation stops handles to removed nodes from
+/// matching a node that later reuses the slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: usize,
+    generation: u32,
+}
+
+struct Node {
+    name: String,
+    generation: u32,
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world_matrix: Mat4,
+    /// Set when this node or an ancestor moved. A dirty node's descendants are always dirty too.
+    dirty: bool,
+}
+
+/// Parent/child hierarchy of `Transform`s. Local transforms are relative to the parent;
+/// world matrices are recomputed lazily for nodes whose local transform or ancestry changed.
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: Vec<Option<Node>>,
+    generations: Vec<u32>,
+    free_slots: Vec<usize>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    pub fn new() -> SceneGraph {
+        Self::default()
+    }
+
+    fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes
+            .get(id.index)?
+            .as_ref()
+            .filter(|node| node.generation == id.generation)
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes
+            .get_mut(id.index)?
+            .as_mut()
+            .filter(|node| node.generation == id.generation)
+    }
+
+    /// Add a root node.
+    pub fn add_node(&mut self, name: &str, local: Transform) -> NodeId {
+        let node = |generation| Node {
+            name: name.to_string(),
+            generation,
+            local,
+            parent: None,
+            children: Vec::new(),
+            world_matrix: local.matrix(),
+            dirty: true,
+        };
+
+        let id = match self.free_slots.pop() {
+            Some(index) => {
+                let generation = self.generations[index];
+                self.nodes[index] = Some(node(generation));
+                NodeId { index, generation }
+            },
+            None => {
+                self.nodes.push(Some(node(0)));
+                self.generations.push(0);
+                NodeId { index: self.nodes.len() - 1, generation: 0 }
+            },
+        };
+
+        self.roots.push(id);
+        id
+    }
+
+    /// Add a node under `parent`, with `local` relative to it.
+    pub fn add_child(&mut self, parent: NodeId, name: &str, local: Transform) -> Result<NodeId, Box<dyn Error>> {
+        let id = self.add_node(name, local);
+        self.set_parent(id, Some(parent), false)?;
+        Ok(id)
+    }
+
+    /// Remove `id` and everything below it.
+    pub fn remove_node(&mut self, id: NodeId) -> bool {
+        let Some(node) = self.node(id) else {
+            return false;
+        };
+
+        let parent = node.parent;
+        self.detach(id, parent);
+
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            if let Some(node) = self.nodes[current.index].take() {
+                stack.extend(node.children);
+                self.generations[current.index] += 1;
+                self.free_slots.push(current.index);
+            }
+        }
+
+        true
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.node(id).is_some()
+    }
+
+    pub fn name(&self, id: NodeId) -> Option<&str> {
+        self.node(id).map(|node| node.name.as_str())
+    }
+
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes.iter().enumerate().find_map(|(index, node)| {
+            node.as_ref()
+                .filter(|node| node.name == name)
+                .map(|node| NodeId { index, generation: node.generation })
+        })
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id)?.parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        self.node(id).map(|node| node.children.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Where `id` is among its parent's children, or among the roots.
+    pub fn sibling_index(&self, id: NodeId) -> Option<usize> {
+        let siblings = match self.parent(id) {
+            Some(parent) => self.children(parent),
+            None => self.roots(),
+        };
+
+        siblings.iter().position(|sibling| *sibling == id)
+    }
+
+    /// Move `id` to `index` among its siblings, or to the end if `index` is past it.
+    pub fn set_sibling_index(&mut self, id: NodeId, index: usize) {
+        let Some(node) = self.node(id) else {
+            return;
+        };
+
+        let siblings = match node.parent {
+            Some(parent) => &mut self.node_mut(parent).unwrap().children,
+            None => &mut self.roots,
+        };
+
+        siblings.retain(|sibling| *sibling != id);
+        siblings.insert(index.min(siblings.len()), id);
+    }
+
+    pub fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = self.parent(id);
+        while let Some(node) = current {
+            if node == ancestor {
+                return true;
+            }
+            current = self.parent(node);
+        }
+
+        false
+    }
+
+    fn detach(&mut self, id: NodeId, parent: Option<NodeId>) {
+        match parent.and_then(|parent| self.node_mut(parent)) {
+            Some(parent) => parent.children.retain(|child| *child != id),
+            None => self.roots.retain(|root| *root != id),
+        }
+    }
+
+    /// Move `id` under `parent`, or make it a root with `None`. With `keep_world_transform`
+    /// the local transform is adjusted so the node stays where it is in the world.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>, keep_world_transform: bool) -> Result<(), Box<dyn Error>> {
+        let old_parent = self.node(id).ok_or("Node does not exist")?.parent;
+
+        if let Some(parent) = parent {
+            if !self.contains(parent) {
+                return Err("Parent node does not exist".into());
+            }
+            if parent == id || self.is_ancestor(id, parent) {
+                return Err(format!(
+                    "Can't parent {} to {}: it would create a cycle",
+                    self.name(id).unwrap_or_default(),
+                    self.name(parent).unwrap_or_default()
+                ).into());
+            }
+        }
+
+        let world_matrix = self.world_matrix(id);
+
+        self.detach(id, old_parent);
+        match parent.and_then(|parent| self.node_mut(parent)) {
+            Some(parent_node) => parent_node.children.push(id),
+            None => self.roots.push(id),
+        }
+        self.node_mut(id).unwrap().parent = parent;
+
+        if keep_world_transform {
+            let parent_world = parent.map(|parent| self.world_matrix(parent)).unwrap_or_else(Mat4::identity);
+            let local = glm::inverse(&parent_world) * world_matrix;
+            self.node_mut(id).unwrap().local = Transform::from_matrix(&local);
+        }
+
+        self.mark_dirty(id);
+        Ok(())
+    }
+
+    fn mark_dirty(&mut self, id: NodeId) {
+        let mut stack = vec![id];
+
+        while let Some(current) = stack.pop() {
+            if let Some(node) = self.node_mut(current) {
+                node.dirty = true;
+                stack.extend(node.children.iter().copied());
+            }
+        }
+    }
+
+    pub fn local_transform(&self, id: NodeId) -> Option<&Transform> {
+        self.node(id).map(|node| &node.local)
+    }
+
+    pub fn set_local_transform(&mut self, id: NodeId, local: Transform) {
+        if let Some(node) = self.node_mut(id) {
+            node.local = local;
+            self.mark_dirty(id);
+        }
+    }
+
+    /// Change the local transform in place, e.g. `graph.update_local(id, |t| t.translate(&offset))`.
+    pub fn update_local<F: FnOnce(&mut Transform)>(&mut self, id: NodeId, change: F) {
+        if let Some(node) = self.node_mut(id) {
+            change(&mut node.local);
+            self.mark_dirty(id);
+        }
+    }
+
+    /// The node's local-to-world matrix, recomputing it and any dirty ancestors first.
+    pub fn world_matrix(&mut self, id: NodeId) -> Mat4 {
+        let Some(node) = self.node(id) else {
+            return Mat4::identity();
+        };
+
+        if !node.dirty {
+            return node.world_matrix;
+        }
+
+        let parent_world = node.parent.map(|parent| self.world_matrix(parent)).unwrap_or_else(Mat4::identity);
+        let node = self.node_mut(id).unwrap();
+        node.world_matrix = parent_world * node.local.matrix();
+        node.dirty = false;
+        node.world_matrix
+    }
+
+    /// Recompute every dirty world matrix, e.g. once per frame before rendering.
+    pub fn update_world_matrices(&mut self) {
+        let mut stack: Vec<(NodeId, Mat4)> = self.roots.iter().map(|root| (*root, Mat4::identity())).collect();
+
+        while let Some((id, parent_world)) = stack.pop() {
+            let Some(node) = self.node_mut(id) else {
+                continue;
+            };
+
+            if node.dirty {
+                node.world_matrix = parent_world * node.local.matrix();
+                node.dirty = false;
+            }
+
+            let world_matrix = node.world_matrix;
+            stack.extend(node.children.iter().map(|child| (*child, world_matrix)));
+        }
+    }
+
+    /// The world matrix as of the last recomputation, without touching dirty flags.
+    pub fn cached_world_matrix(&self, id: NodeId) -> Option<Mat4> {
+        self.node(id).map(|node| node.world_matrix)
+    }
+
+    pub fn world_transform(&mut self, id: NodeId) -> Transform {
+        Transform::from_matrix(&self.world_matrix(id))
+    }
+
+    pub fn world_position(&mut self, id: NodeId) -> Vec3 {
+        self.local_to_world_point(id, &Vec3::zeros())
+    }
+
+    /// The node's rotation relative to the world, ignoring any shear from non-uniform scale.
+    pub fn world_rotation(&self, id: NodeId) -> Quat {
+        let mut rotation = glm::quat_identity();
+        let mut current = Some(id);
+
+        while let Some(node) = current.and_then(|current| self.node(current)) {
+            rotation = node.local.rotation * rotation;
+            current = node.parent;
+        }
+
+        rotation
+    }
+
+    /// Move the node to `position` in world space, keeping its parent.
+    pub fn set_world_position(&mut self, id: NodeId, position: &Vec3) {
+        let local_position = match self.parent(id) {
+            Some(parent) => self.world_to_local_point(parent, position),
+            None => *position,
+        };
+
+        self.update_local(id, |local| local.translation = local_position);
+    }
+
+    /// Turn the node so its forward (-Z) points at `target`, both in world space.
+    pub fn look_at(&mut self, id: NodeId, target: &Vec3, up: &Vec3) {
+        let direction = target - self.world_position(id);
+        if glm::length2(&direction) <= f32::EPSILON {
+            return;
+        }
+
+        let world_rotation = look_rotation(&direction, up);
+        let parent_rotation = self.parent(id)
+            .map(|parent| self.world_rotation(parent))
+            .unwrap_or_else(glm::quat_identity);
+
+        let local_rotation = glm::quat_inverse(&parent_rotation) * world_rotation;
+        self.update_local(id, |local| local.rotation = glm::quat_normalize(&local_rotation));
+    }
+
+    pub fn local_to_world_point(&mut self, id: NodeId, point: &Vec3) -> Vec3 {
+        let world = self.world_matrix(id) * glm::vec4(point.x, point.y, point.z, 1.0);
+        world.xyz()
+    }
+
+    pub fn world_to_local_point(&mut self, id: NodeId, point: &Vec3) -> Vec3 {
+        let local = glm::inverse(&self.world_matrix(id)) * glm::vec4(point.x, point.y, point.z, 1.0);
+        local.xyz()
+    }
+
+    pub fn local_to_world_direction(&mut self, id: NodeId, direction: &Vec3) -> Vec3 {
+        let world = self.world_matrix(id) * glm::vec4(direction.x, direction.y, direction.z, 0.0);
+        world.xyz()
+    }
+
+    pub fn world_to_local_direction(&mut self, id: NodeId, direction: &Vec3) -> Vec3 {
+        let local = glm::inverse(&self.world_matrix(id)) * glm::vec4(direction.x, direction.y, direction.z, 0.0);
+        local.xyz()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_dirty(graph: &SceneGraph, id: NodeId) -> bool {
+        graph.node(id).unwrap().dirty
+    }
+
+    fn assert_near(actual: &Vec3, expected: &Vec3) {
+        assert!(glm::distance(actual, expected) < 1e-5, "{actual:?} != {expected:?}");
+    }
+
+    #[test]
+    fn world_matrices_compose_down_the_hierarchy() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node("Root", Transform::from_translation(glm::vec3(1.0, 0.0, 0.0)));
+        let child = graph.add_child(root, "Child", Transform::from_scale(glm::vec3(2.0, 2.0, 2.0))).unwrap();
+        let grandchild = graph.add_child(child, "Grandchild", Transform::from_translation(glm::vec3(0.0, 1.0, 0.0))).unwrap();
+
+        graph.update_world_matrices();
+        assert_near(&graph.world_position(grandchild), &glm::vec3(1.0, 2.0, 0.0));
+
+        graph.update_local(root, |local| local.rotate_axis(std::f32::consts::FRAC_PI_2, &glm::vec3(0.0, 0.0, 1.0)));
+        graph.update_world_matrices();
+        assert_near(&graph.world_position(grandchild), &glm::vec3(-1.0, 0.0, 0.0));
+        assert_near(&graph.world_to_local_point(child, &glm::vec3(-1.0, 0.0, 0.0)), &glm::vec3(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn moving_a_node_only_dirties_its_subtree() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add_node("Root", Transform::IDENTITY);
+        let left = graph.add_child(root, "Left", Transform::IDENTITY).unwrap();
+        let left_child = graph.add_child(left, "LeftChild", Transform::IDENTITY).unwrap();
+        let right = graph.add_child(root, "Right", Transform::IDENTITY).unwrap();
+
+        graph.update_world_matrices();
+        assert!([root, left, left_child, right].iter().all(|id| !is_dirty(&graph, *id)));
+
+        graph.set_local_transform(left, Transform::from_translation(glm::vec3(0.0, 3.0, 0.0)));
+        assert!(is_dirty(&graph, left));
+        assert!(is_dirty(&graph, left_child));
+        assert!(!is_dirty(&graph, root));
+        assert!(!is_dirty(&graph, right));
+
+        // The cached matrix is stale until it's recomputed, lazily or for the whole graph.
+        assert_eq!(graph.cached_world_matrix(left_child), Some(Mat4::identity()));
+        assert_near(&graph.world_position(left_child), &glm::vec3(0.0, 3.0, 0.0));
+        assert!(!is_dirty(&graph, left) && !is_dirty(&graph, left_child));
+    }
+
+    #[test]
+    fn reparenting_can_keep_the_world_transform() {
+        let mut graph = SceneGraph::new();
+        let anchor = graph.add_node("Anchor", Transform::from_translation(glm::vec3(5.0, 0.0, 0.0)));
+        let node = graph.add_node("Node", Transform::from_translation(glm::vec3(1.0, 1.0, 0.0)));
+
+        graph.set_parent(node, Some(anchor), true).unwrap();
+        assert_near(&graph.world_position(node), &glm::vec3(1.0, 1.0, 0.0));
+        assert_near(&graph.local_transform(node).unwrap().translation, &glm::vec3(-4.0, 1.0, 0.0));
+
+        graph.set_parent(node, None, false).unwrap();
+        assert_near(&graph.world_position(node), &glm::vec3(-4.0, 1.0, 0.0));
+        assert!(graph.set_parent(anchor, Some(anchor), false).is_err());
+    }
+}
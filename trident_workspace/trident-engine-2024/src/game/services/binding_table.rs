@@ -0,0 +1,169 @@
+use crate::game::services::input_bindings::{AxisSource, Binding};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// The user-editable parts of an `AxisAction`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AxisSettings {
+    pub source: AxisSource,
+    #[serde(default)]
+    pub dead_zone: f32,
+    #[serde(default = "default_sensitivity")]
+    pub sensitivity: f32,
+    #[serde(default)]
+    pub inverted: bool,
+}
+
+fn default_sensitivity() -> f32 {
+    1.0
+}
+
+/// Two or more actions in the same input context that share the same binding.
+/// The same binding in different contexts is not a conflict.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingConflict {
+    pub context: String,
+    pub binding: Binding,
+    pub actions: Vec<String>,
+}
+
+/// Every action and axis binding, keyed by input context and then by `Action::name` /
+/// `AxisAction::name`, so actions with the same name in different contexts keep their own bindings.
+/// This is what gets written to and read from a controls file, e.g. in TOML:
+///
+/// ```toml
+/// [actions.default.Jump]
+/// type = "Button"
+/// button = "Key:Space"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BindingTable {
+    pub actions: BTreeMap<String, BTreeMap<String, Binding>>,
+    pub axes: BTreeMap<String, BTreeMap<String, AxisSettings>>,
+}
+
+impl BindingTable {
+    pub fn insert_action(&mut self, context: &str, name: &str, binding: Binding) {
+        self.actions.entry(context.to_string()).or_default().insert(name.to_string(), binding);
+    }
+
+    pub fn insert_axis(&mut self, context: &str, name: &str, settings: AxisSettings) {
+        self.axes.entry(context.to_string()).or_default().insert(name.to_string(), settings);
+    }
+
+    pub fn action(&self, context: &str, name: &str) -> Option<&Binding> {
+        self.actions.get(context).and_then(|actions| actions.get(name))
+    }
+
+    pub fn axis(&self, context: &str, name: &str) -> Option<&AxisSettings> {
+        self.axes.get(context).and_then(|axes| axes.get(name))
+    }
+
+    /// Load a table from a `.toml` or `.json` file. The format is picked from the file extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Ok(toml::from_str(&contents)?),
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            _ => Err(format!("Unsupported binding file format: {}", path.display()).into()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+
+        let contents = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::to_string_pretty(self)?,
+            Some("json") => serde_json::to_string_pretty(self)?,
+            _ => return Err(format!("Unsupported binding file format: {}", path.display()).into()),
+        };
+
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn duplicates(&self) -> Vec<BindingConflict> {
+        let mut conflicts: Vec<BindingConflict> = Vec::new();
+
+        for (context, actions) in self.actions.iter() {
+            for (name, binding) in actions.iter() {
+                let existing = conflicts
+                    .iter_mut()
+                    .find(|conflict| conflict.context == *context && conflict.binding == *binding);
+
+                match existing {
+                    Some(conflict) => conflict.actions.push(name.clone()),
+                    None => conflicts.push(BindingConflict {
+                        context: context.clone(),
+                        binding: binding.clone(),
+                        actions: vec![name.clone()],
+                    }),
+                }
+            }
+        }
+
+        conflicts.retain(|conflict| conflict.actions.len() > 1);
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::services::input_bindings::InputButton;
+    use sdl2::keyboard::Keycode;
+
+    fn table() -> BindingTable {
+        let mut table = BindingTable::default();
+        table.insert_action("default", "Confirm", Keycode::Space.into());
+        table.insert_action("default", "Jump", Keycode::Space.into());
+        table.insert_action("menu", "Confirm", Keycode::Return.into());
+        table.insert_action("menu", "Back", Keycode::Space.into());
+        table.insert_axis("default", "Strafe", AxisSettings {
+            source: AxisSource::Buttons { negative: InputButton::Key(Keycode::A), positive: InputButton::Key(Keycode::D) },
+            dead_zone: 0.0,
+            sensitivity: 1.0,
+            inverted: false,
+        });
+        table
+    }
+
+    #[test]
+    fn same_name_in_different_contexts_keeps_both_bindings() {
+        let table = table();
+
+        assert_eq!(table.action("default", "Confirm"), Some(&Binding::from(Keycode::Space)));
+        assert_eq!(table.action("menu", "Confirm"), Some(&Binding::from(Keycode::Return)));
+        assert!(table.axis("menu", "Strafe").is_none());
+    }
+
+    #[test]
+    fn duplicates_are_per_context() {
+        let duplicates = table().duplicates();
+
+        assert_eq!(duplicates, [BindingConflict {
+            context: "default".to_string(),
+            binding: Keycode::Space.into(),
+            actions: vec!["Confirm".to_string(), "Jump".to_string()],
+        }]);
+    }
+
+    #[test]
+    fn round_trips_through_toml_and_json() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for file_name in ["controls.toml", "controls.json"] {
+            let path = dir.path().join(file_name);
+            table().save(&path).unwrap();
+            assert_eq!(BindingTable::load(&path).unwrap(), table(), "{}", file_name);
+        }
+
+        assert!(table().save(dir.path().join("controls.ron")).is_err());
+    }
+}
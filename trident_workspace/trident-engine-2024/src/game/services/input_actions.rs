@@ -1,37 +1,713 @@
-use crate::game::services::Service;
-use sdl2::keyboard::Keycode;
-use std::collections::HashMap;
+use crate::game::services::binding_table::{AxisSettings, BindingConflict, BindingTable};
+use crate::game::services::input_bindings::{AxisAction, AxisSource, Binding, InputButton, Modifiers};
+use crate::game::services::input_contexts::{InputContext, DEFAULT_CONTEXT};
+use crate::game::services::input_events::InputEvent;
+use crate::game::services::input_recording::{InputPlayback, InputRecorder, InputRecording};
+use crate::game::services::{DeferredCallback, OwnerId, Service};
+use crate::game_loop::FrameContext;
+use async_std::task;
+use log::warn;
+use std::error::Error;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::future::Future;
+use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
+/// How many recent presses are kept around for matching `Binding::Sequence`.
+const PRESS_HISTORY_LEN: usize = 16;
+
+/// An action whose binding is currently satisfied.
+struct ActiveAction {
+    pressed_at: Duration,
+    timestamp: u32,
+    hold_completed: bool,
+}
+
+impl ActiveAction {
+    /// The event timestamp `held_duration` after the press. SDL timestamps are milliseconds
+    /// in a `u32`, so this wraps around like they do.
+    fn timestamp_after(&self, held_duration: Duration) -> u32 {
+        self.timestamp.wrapping_add(held_duration.as_millis() as u32)
+    }
+}
+
+/// The result of `ActionsService::listen_for_rebind`, once an input has been captured.
+#[derive(Debug, Clone)]
+pub struct RebindOutcome {
+    pub context: String,
+    pub action: String,
+    pub binding: Binding,
+    /// Other actions in the same context that were already using the captured binding.
+    pub conflicts: Vec<String>,
+}
+
+/// Turns input events into action callbacks and axis values.
+/// Events normally arrive from `Application::run` through `ServiceManager::dispatch_events`,
+/// but tests can inject synthetic ones by calling `Service::handle_event` directly.
+///
+/// Callbacks don't run inside `handle_event` or `update`, where the service is locked. They are
+/// queued and handed out by `Service::take_callbacks`, which `Application::run` calls after
+/// releasing the locks; code driving the service by hand can use `run_callbacks`.
 pub struct ActionsService {
-    actions: HashMap<Keycode, Vec<Action>>,
+    actions: Vec<Action>,
+    axes: Vec<AxisAction>,
+    active_actions: HashMap<usize, ActiveAction>,
+    held_buttons: HashSet<InputButton>,
+    modifiers: Modifiers,
+    press_history: VecDeque<(InputButton, Duration)>,
+    /// Context and name of the action waiting for `listen_for_rebind` to capture an input.
+    pending_rebind: Option<(String, String)>,
+    rebind_outcome: Option<RebindOutcome>,
+    contexts: Vec<InputContext>,
+    context_stack: Vec<String>,
+    elapsed: Duration,
+    recorder: Option<InputRecorder>,
+    playback: Option<InputPlayback>,
+    triggered: Vec<DeferredCallback>,
+}
+
+/// Queue `action`'s callback if `event` matches it.
+fn queue_if_matches(triggered: &mut Vec<DeferredCallback>, action: &Action, event: &ActionEvent) {
+    if action.matches(event) {
+        triggered.push(action.deferred(event));
+    }
+}
+
+impl Service for ActionsService {
+    fn handle_event(&mut self, event: &InputEvent) {
+        // Live input is ignored while a recording is driving the service.
+        if self.playback.is_some() {
+            return;
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_event(event);
+        }
+
+        self.apply_event(event);
+    }
+
+    fn update(&mut self, frame: &FrameContext) {
+        let mut delta = frame.delta;
+
+        if self.playback.is_some() {
+            match self.playback.as_mut().and_then(|playback| playback.next_frame()) {
+                Some(recorded) => {
+                    for event in recorded.events.iter() {
+                        self.apply_event(event);
+                    }
+                    delta = recorded.delta;
+                },
+                None => self.playback = None,
+            }
+        }
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.end_frame(frame);
+        }
+
+        self.advance(delta);
+    }
+
+    fn fixed_update(&mut self, frame: &FrameContext) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record_fixed_update(frame);
+        }
+    }
+
+    fn take_callbacks(&mut self) -> Vec<DeferredCallback> {
+        std::mem::take(&mut self.triggered)
+    }
 }
 
-impl Service for ActionsService {}
+impl Default for ActionsService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl ActionsService {
     pub fn new() -> ActionsService {
         Self {
-            actions: HashMap::new(),
+            actions: Vec::new(),
+            axes: Vec::new(),
+            active_actions: HashMap::new(),
+            held_buttons: HashSet::new(),
+            modifiers: Modifiers::NONE,
+            press_history: VecDeque::with_capacity(PRESS_HISTORY_LEN),
+            pending_rebind: None,
+            rebind_outcome: None,
+            contexts: vec![InputContext::new(DEFAULT_CONTEXT, false)],
+            context_stack: vec![DEFAULT_CONTEXT.to_string()],
+            elapsed: Duration::ZERO,
+            recorder: None,
+            playback: None,
+            triggered: Vec::new(),
         }
     }
-    
+
+    /// Run the callbacks queued since the last `take_callbacks`, for code that owns the service
+    /// directly instead of through a `ServiceManager`.
+    pub fn run_callbacks(&mut self) {
+        for callback in self.take_callbacks() {
+            callback();
+        }
+    }
+
+    fn apply_event(&mut self, event: &InputEvent) {
+        match *event {
+            InputEvent::KeyDown { keycode, keymod, repeat, timestamp } => {
+                self.modifiers = Modifiers::from_sdl(keymod);
+                if repeat {
+                    self.repeat_button(InputButton::Key(keycode), timestamp);
+                } else {
+                    self.press_button(InputButton::Key(keycode), timestamp);
+                }
+            },
+            InputEvent::KeyUp { keycode, keymod, timestamp } => {
+                self.modifiers = Modifiers::from_sdl(keymod);
+                self.release_button(InputButton::Key(keycode), timestamp);
+            },
+            InputEvent::MouseButtonDown { button, timestamp, .. } => {
+                self.press_button(InputButton::Mouse(button), timestamp);
+            },
+            InputEvent::MouseButtonUp { button, timestamp, .. } => {
+                self.release_button(InputButton::Mouse(button), timestamp);
+            },
+            InputEvent::ControllerButtonDown { button, timestamp, .. } => {
+                self.press_button(InputButton::Gamepad(button), timestamp);
+            },
+            InputEvent::ControllerButtonUp { button, timestamp, .. } => {
+                self.release_button(InputButton::Gamepad(button), timestamp);
+            },
+            InputEvent::MouseMotion { xrel, yrel, .. } => {
+                self.accumulate_axis(AxisSource::MouseX, xrel as f32);
+                self.accumulate_axis(AxisSource::MouseY, yrel as f32);
+            },
+            InputEvent::MouseWheel { x, y, timestamp } => {
+                self.accumulate_axis(AxisSource::WheelX, x as f32);
+                self.accumulate_axis(AxisSource::WheelY, y as f32);
+
+                let ticks = [
+                    (y > 0, InputButton::WheelUp),
+                    (y < 0, InputButton::WheelDown),
+                    (x < 0, InputButton::WheelLeft),
+                    (x > 0, InputButton::WheelRight),
+                ];
+                for (scrolled, button) in ticks {
+                    if scrolled {
+                        self.press_button(button, timestamp);
+                        self.release_button(button, timestamp);
+                    }
+                }
+            },
+            InputEvent::ControllerAxisMotion { axis, value, .. } => {
+                let normalized = (value as f32 / i16::MAX as f32).clamp(-1.0, 1.0);
+                for axis_action in self.axes.iter_mut() {
+                    if axis_action.source == AxisSource::Gamepad(axis) {
+                        axis_action.raw_value = normalized;
+                    }
+                }
+            },
+            InputEvent::WindowFocusLost { .. } => {
+                self.held_buttons.clear();
+                self.active_actions.clear();
+                self.modifiers = Modifiers::NONE;
+            },
+            _ => {},
+        }
+    }
+
+    fn advance(&mut self, delta: Duration) {
+        self.elapsed += delta;
+
+        for (index, active) in self.active_actions.iter_mut() {
+            let action = &self.actions[*index];
+            let held_duration = self.elapsed - active.pressed_at;
+            let timestamp = active.timestamp_after(held_duration);
+
+            queue_if_matches(&mut self.triggered, action, &ActionEvent::new(ActionPhase::Held, timestamp, held_duration));
+
+            let completed_event = ActionEvent::new(ActionPhase::HoldCompleted, timestamp, held_duration);
+            if !active.hold_completed && action.matches(&completed_event) {
+                active.hold_completed = true;
+                self.triggered.push(action.deferred(&completed_event));
+            }
+        }
+
+        for axis_action in self.axes.iter_mut() {
+            let raw_value = match axis_action.source {
+                AxisSource::Buttons { negative, positive } => {
+                    let negative = if self.held_buttons.contains(&negative) { -1.0 } else { 0.0 };
+                    let positive = if self.held_buttons.contains(&positive) { 1.0 } else { 0.0 };
+                    negative + positive
+                },
+                _ => axis_action.raw_value,
+            };
+
+            axis_action.value = axis_action.shape(raw_value);
+
+            // Mouse and wheel sources are deltas, so they start over every frame.
+            if matches!(axis_action.source, AxisSource::MouseX | AxisSource::MouseY | AxisSource::WheelX | AxisSource::WheelY) {
+                axis_action.raw_value = 0.0;
+            }
+        }
+    }
+
+    /// Start capturing every event this service receives, grouped by frame, along with how many
+    /// fixed updates each frame ran.
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(InputRecorder::new());
+    }
+
+    /// Like `start_recording`, also storing the seed the `RandomService` was given for this run,
+    /// so `InputRecording::replay` can reseed it.
+    pub fn start_recording_with_seed(&mut self, seed: u64) {
+        self.recorder = Some(InputRecorder::with_seed(seed));
+    }
+
+    pub fn stop_recording(&mut self) -> Option<InputRecording> {
+        self.recorder.take().map(InputRecorder::finish)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Drive the service from `recording` instead of live input. Each update consumes one recorded
+    /// frame and uses its delta, so held timings come out the same as when it was recorded.
+    pub fn start_playback(&mut self, recording: InputRecording) {
+        self.playback = Some(InputPlayback::new(recording));
+    }
+
+    pub fn stop_playback(&mut self) {
+        self.playback = None;
+    }
+
+    pub fn is_playing_back(&self) -> bool {
+        self.playback.is_some()
+    }
+
     pub fn register_action(&mut self, action: Action) {
+        self.actions.push(action);
+    }
+
+    /// Remove the action called `name`. Code that registered callbacks from a library that is
+    /// about to be unloaded must call this first, or the callbacks will dangle.
+    pub fn unregister_action(&mut self, name: &str) -> Option<Action> {
+        let index = self.actions.iter().position(|action| action.name == name)?;
+        Some(self.remove_action(index))
+    }
+
+    /// Remove the action called `name` only if `owner` registered it.
+    pub fn unregister_owned_action(&mut self, owner: OwnerId, name: &str) -> Option<Action> {
+        let index = self.actions
+            .iter()
+            .position(|action| action.name == name && action.owner == Some(owner))?;
+        Some(self.remove_action(index))
+    }
+
+    /// Remove every action registered by `owner`, e.g. before unloading the library it came from.
+    pub fn unregister_owner(&mut self, owner: OwnerId) -> Vec<Action> {
+        let mut removed = Vec::new();
+
+        while let Some(index) = self.actions.iter().position(|action| action.owner == Some(owner)) {
+            removed.push(self.remove_action(index));
+        }
+
+        removed
+    }
+
+    fn remove_action(&mut self, index: usize) -> Action {
+        let action = self.actions.remove(index);
+
+        self.active_actions = self.active_actions
+            .drain()
+            .filter(|(active_index, _)| *active_index != index)
+            .map(|(active_index, active)| {
+                let shifted = if active_index > index { active_index - 1 } else { active_index };
+                (shifted, active)
+            })
+            .collect();
+
+        action
+    }
+
+    pub fn register_axis(&mut self, axis_action: AxisAction) {
+        self.axes.push(axis_action);
+    }
+
+    /// The shaped value of the axis action called `name` as of the last frame, or 0 if there is none.
+    /// Axes whose context is hidden by a consuming context read as 0.
+    pub fn axis_value(&self, name: &str) -> f32 {
+        let eligible_contexts = self.eligible_contexts();
+
+        self.axes
+            .iter()
+            .find(|axis_action| axis_action.name == name)
+            .filter(|axis_action| eligible_contexts.contains(&axis_action.context.as_str()))
+            .map(|axis_action| axis_action.value())
+            .unwrap_or(0.0)
+    }
+
+    pub fn register_context(&mut self, context: InputContext) {
+        match self.contexts.iter_mut().find(|existing| existing.name == context.name) {
+            Some(existing) => *existing = context,
+            None => self.contexts.push(context),
+        }
+    }
+
+    /// Make the registered context called `name` the top layer.
+    pub fn push_context(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        if !self.contexts.iter().any(|context| context.name == name) {
+            return Err(format!("No input context named {}", name).into());
+        }
+
+        self.context_stack.push(name.to_string());
+        self.release_ineligible_actions();
+        Ok(())
+    }
+
+    /// Remove the top layer. The default context is never popped.
+    pub fn pop_context(&mut self) -> Option<String> {
+        if self.context_stack.len() <= 1 {
+            return None;
+        }
+
+        let popped = self.context_stack.pop();
+        self.release_ineligible_actions();
+        popped
+    }
+
+    /// The context stack, bottom first.
+    pub fn active_contexts(&self) -> &[String] {
+        &self.context_stack
+    }
+
+    /// The contexts that currently receive input, top first. Stops below the first
+    /// context that consumes input.
+    fn eligible_contexts(&self) -> Vec<&str> {
+        let mut eligible = Vec::new();
+
+        for name in self.context_stack.iter().rev() {
+            eligible.push(name.as_str());
+
+            let consumes = self.contexts
+                .iter()
+                .any(|context| context.name == *name && context.consume_input);
+            if consumes {
+                break;
+            }
+        }
+
+        eligible
+    }
+
+    /// Release actions whose context stopped receiving input after a push or pop, so e.g.
+    /// a held movement key doesn't keep firing under a pause menu.
+    fn release_ineligible_actions(&mut self) {
+        let eligible_contexts = self.eligible_contexts()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+
+        let released = self.active_actions
+            .keys()
+            .copied()
+            .filter(|index| !eligible_contexts.contains(&self.actions[*index].context))
+            .collect::<Vec<usize>>();
+
+        for index in released {
+            let active = self.active_actions.remove(&index).unwrap();
+            let held_duration = self.elapsed - active.pressed_at;
+            let timestamp = active.timestamp_after(held_duration);
+            let released_event = ActionEvent::new(ActionPhase::Released, timestamp, held_duration);
+            queue_if_matches(&mut self.triggered, &self.actions[index], &released_event);
+        }
+    }
+
+    pub fn is_button_held(&self, button: impl Into<InputButton>) -> bool {
+        self.held_buttons.contains(&button.into())
+    }
+
+    pub fn is_action_active(&self, name: &str) -> bool {
+        self.active_actions
+            .keys()
+            .any(|index| self.actions[*index].name == name)
+    }
+
+    fn press_button(&mut self, button: InputButton, timestamp: u32) {
+        self.held_buttons.insert(button);
+
+        if self.pending_rebind.is_some() && !button.is_modifier_key() {
+            self.finish_rebind(Binding::Button { button, modifiers: self.modifiers });
+            return;
+        }
+
+        self.press_history.push_back((button, self.elapsed));
+        if self.press_history.len() > PRESS_HISTORY_LEN {
+            self.press_history.pop_front();
+        }
+
+        self.activate_actions(button, timestamp);
+    }
+
+    fn repeat_button(&mut self, button: InputButton, timestamp: u32) {
+        for (index, active) in self.active_actions.iter() {
+            let action = &self.actions[*index];
+            if action.binding.contains(button) {
+                let held_duration = self.elapsed - active.pressed_at;
+                queue_if_matches(&mut self.triggered, action, &ActionEvent::new(ActionPhase::Repeat, timestamp, held_duration));
+            }
+        }
+    }
+
+    fn release_button(&mut self, button: InputButton, timestamp: u32) {
+        self.held_buttons.remove(&button);
+
+        let released = self.active_actions
+            .keys()
+            .copied()
+            .filter(|index| self.actions[*index].binding.contains(button))
+            .collect::<Vec<usize>>();
+
+        for index in released {
+            let active = self.active_actions.remove(&index).unwrap();
+            let held_duration = self.elapsed - active.pressed_at;
+            let released_event = ActionEvent::new(ActionPhase::Released, timestamp, held_duration);
+            queue_if_matches(&mut self.triggered, &self.actions[index], &released_event);
+        }
+    }
+
+    fn accumulate_axis(&mut self, source: AxisSource, delta: f32) {
+        for axis_action in self.axes.iter_mut() {
+            if axis_action.source == source {
+                axis_action.raw_value += delta;
+            }
+        }
+    }
+
+    /// Activate the actions completed by pressing `button`. Contexts are searched from the top
+    /// of the stack down and the first one with a completed binding handles the press.
+    /// Within that context, only the most specific bindings win, e.g. Ctrl+S suppresses S and
+    /// a combo suppresses the plain press of its last button.
+    fn activate_actions(&mut self, button: InputButton, timestamp: u32) {
+        let mut completed = Vec::new();
+
+        for context in self.eligible_contexts() {
+            completed = self.actions
+                .iter()
+                .enumerate()
+                .filter(|(index, action)| action.context == context && !self.active_actions.contains_key(index))
+                .filter(|(_, action)| action.binding.is_completed_by(
+                    button,
+                    |held| self.held_buttons.contains(&held),
+                    self.modifiers,
+                    &self.press_history))
+                .map(|(index, action)| (index, action.binding.specificity()))
+                .collect::<Vec<(usize, u32)>>();
+
+            if !completed.is_empty() {
+                break;
+            }
+        }
+
+        let Some(best) = completed.iter().map(|(_, specificity)| *specificity).max() else {
+            return;
+        };
+
+        if completed.iter().any(|(index, specificity)| {
+            *specificity == best && matches!(self.actions[*index].binding, Binding::Sequence { .. })
+        }) {
+            self.press_history.clear();
+        }
+
+        let pressed_event = ActionEvent::new(ActionPhase::Pressed, timestamp, Duration::ZERO);
+        for (index, _) in completed.into_iter().filter(|(_, specificity)| *specificity == best) {
+            self.active_actions.insert(index, ActiveAction {
+                pressed_at: self.elapsed,
+                timestamp,
+                hold_completed: false,
+            });
+            queue_if_matches(&mut self.triggered, &self.actions[index], &pressed_event);
+        }
+    }
+
+    /// Point the action called `name` in the input context `context` at a new binding.
+    pub fn rebind(&mut self, context: &str, name: &str, binding: Binding) -> Result<(), Box<dyn Error>> {
+        let index = self.actions
+            .iter()
+            .position(|action| action.context == context && action.name == name)
+            .ok_or_else(|| format!("No action named {} in input context {}", name, context))?;
+
+        self.active_actions.remove(&index);
+        self.actions[index].binding = binding;
+        Ok(())
+    }
+
+    /// Bind the action called `name` in `context` to whatever button is pressed next, together
+    /// with the modifiers held at that moment. The press is swallowed instead of firing actions.
+    /// The result can be picked up with `take_rebind_outcome`.
+    pub fn listen_for_rebind(&mut self, context: &str, name: &str) {
+        self.pending_rebind = Some((context.to_string(), name.to_string()));
+        self.rebind_outcome = None;
+    }
+
+    pub fn cancel_rebind(&mut self) {
+        self.pending_rebind = None;
+    }
+
+    pub fn is_listening_for_rebind(&self) -> bool {
+        self.pending_rebind.is_some()
+    }
+
+    pub fn take_rebind_outcome(&mut self) -> Option<RebindOutcome> {
+        self.rebind_outcome.take()
+    }
+
+    fn finish_rebind(&mut self, binding: Binding) {
+        let Some((context, name)) = self.pending_rebind.take() else {
+            return;
+        };
+
+        let conflicts = self.conflicting_actions(&context, &name, &binding);
+        match self.rebind(&context, &name, binding.clone()) {
+            Ok(()) => self.rebind_outcome = Some(RebindOutcome { context, action: name, binding, conflicts }),
+            Err(e) => warn!("Could not rebind action: {}", e),
+        }
+    }
+
+    /// Names of the actions in `context` other than `name` that use `binding`.
+    pub fn conflicting_actions(&self, context: &str, name: &str, binding: &Binding) -> Vec<String> {
         self.actions
-            .entry(action.assigned_key)
-            .or_insert_with(Vec::new)
-            .push(action);
+            .iter()
+            .filter(|action| action.context == context && action.name != name && action.binding == *binding)
+            .map(|action| action.name.clone())
+            .collect()
+    }
+
+    pub fn duplicate_bindings(&self) -> Vec<BindingConflict> {
+        self.binding_table().duplicates()
+    }
+
+    pub fn binding_table(&self) -> BindingTable {
+        let mut table = BindingTable::default();
+
+        for action in self.actions.iter() {
+            table.insert_action(&action.context, &action.name, action.binding.clone());
+        }
+
+        for axis_action in self.axes.iter() {
+            table.insert_axis(&axis_action.context, &axis_action.name, AxisSettings {
+                source: axis_action.source,
+                dead_zone: axis_action.dead_zone,
+                sensitivity: axis_action.sensitivity,
+                inverted: axis_action.inverted,
+            });
+        }
+
+        table
     }
 
-    pub async fn fire_event(&self, key: Keycode) {
-        if let Some(actions) = self.actions.get(&key) {
-            for action in actions {
-                action.call().await;
+    /// Apply the bindings in `table` to the registered actions and axes with matching contexts
+    /// and names. Entries without a registered action are skipped with a warning.
+    pub fn apply_binding_table(&mut self, table: &BindingTable) {
+        for (context, actions) in table.actions.iter() {
+            for (name, binding) in actions.iter() {
+                if let Err(e) = self.rebind(context, name, binding.clone()) {
+                    warn!("Skipping binding from table: {}", e);
+                }
+            }
+        }
+
+        for (context, axes) in table.axes.iter() {
+            for (name, settings) in axes.iter() {
+                let axis_action = self.axes
+                    .iter_mut()
+                    .find(|axis_action| axis_action.context == *context && axis_action.name == *name);
+
+                match axis_action {
+                    Some(axis_action) => {
+                        axis_action.source = settings.source;
+                        axis_action.dead_zone = settings.dead_zone;
+                        axis_action.sensitivity = settings.sensitivity;
+                        axis_action.inverted = settings.inverted;
+                        axis_action.raw_value = 0.0;
+                    },
+                    None => warn!("Skipping axis binding from table: No axis named {} in input context {}", name, context),
+                }
             }
         }
     }
+
+    pub fn load_bindings<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn Error>> {
+        let table = BindingTable::load(path)?;
+
+        for conflict in table.duplicates() {
+            warn!("Binding {} is shared by actions {:?} in input context {}", conflict.binding, conflict.actions, conflict.context);
+        }
+
+        self.apply_binding_table(&table);
+        Ok(())
+    }
+
+    pub fn save_bindings<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        self.binding_table().save(path)
+    }
+
+    pub async fn fire_event(&self, button: impl Into<InputButton>, event: &ActionEvent) {
+        let button = button.into();
+        for action in self.actions.iter().filter(|action| action.binding.contains(button)) {
+            if action.matches(event) {
+                action.call(event).await;
+            }
+        }
+    }
+
+    /// Like `fire_event`, but async callbacks are spawned on the executor instead of awaited,
+    /// so it can be called from the frame loop.
+    pub fn trigger_event(&self, button: impl Into<InputButton>, event: &ActionEvent) {
+        let button = button.into();
+        for action in self.actions.iter().filter(|action| action.binding.contains(button)) {
+            action.trigger_if_matches(event);
+        }
+    }
+}
+
+/// `repr(C)` because it is passed to the client library; see `hot_reload::ClientActionEvent`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionPhase {
+    /// The binding became satisfied.
+    Pressed,
+    /// One of the binding's buttons went up.
+    Released,
+    /// The OS sent a key repeat while the binding was held.
+    Repeat,
+    /// Fired every frame while the binding is held and has been for at least the hold threshold.
+    Held,
+    /// Fired once per press, on the first frame the hold threshold is reached.
+    HoldCompleted,
+}
+
+/// What an action callback gets told about the input that fired it.
+#[derive(Debug, Clone, Copy)]
+pub struct ActionEvent {
+    pub phase: ActionPhase,
+    pub timestamp: u32,
+    pub held_duration: Duration,
+}
+
+impl ActionEvent {
+    pub fn new(phase: ActionPhase, timestamp: u32, held_duration: Duration) -> Self {
+        Self {
+            phase,
+            timestamp,
+            held_duration,
+        }
+    }
 }
 
 pub enum CallbackType {
@@ -39,31 +715,42 @@ pub enum CallbackType {
     Async,
 }
 
+type BoxedFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
 enum ActionCallback {
-    Sync(Arc<dyn Fn() + Send + Sync>),
-    Async(Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>),
+    Sync(Arc<dyn Fn(&ActionEvent) + Send + Sync>),
+    Async(Arc<dyn Fn(ActionEvent) -> BoxedFuture + Send + Sync>),
 }
 
 pub struct Action {
     pub name: String,
-    pub assigned_key: Keycode,
+    pub context: String,
+    pub binding: Binding,
     pub description: Option<String>,
+    pub phase: ActionPhase,
+    pub hold_threshold: Duration,
+    /// Who registered the action, if it has to be removed along with them. See `owned_by`.
+    pub owner: Option<OwnerId>,
     callback: ActionCallback,
     callback_type: CallbackType,
 }
 
 impl Action {
     pub fn new<F>(
-        name: String, 
-        assigned_key: Keycode, 
+        name: String,
+        binding: impl Into<Binding>,
         description: Option<String>,
-        callback: F) -> Action 
-    where F: Fn() + Send + Sync + 'static
+        callback: F) -> Action
+    where F: Fn(&ActionEvent) + Send + Sync + 'static
     {
         Self {
             name,
-            assigned_key,
+            context: DEFAULT_CONTEXT.to_string(),
+            binding: binding.into(),
             description,
+            phase: ActionPhase::Pressed,
+            hold_threshold: Duration::ZERO,
+            owner: None,
             callback: ActionCallback::Sync(Arc::new(callback)),
             callback_type: CallbackType::Sync,
         }
@@ -71,32 +758,387 @@ impl Action {
 
     pub fn new_async<F, Fut>(
         name: String,
-        assigned_key: Keycode,
+        binding: impl Into<Binding>,
         description: Option<String>,
         callback: F) -> Action
     where
-        F: Fn() -> Fut + Send + Sync + 'static,
+        F: Fn(ActionEvent) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = ()> + Send + 'static
     {
         Self {
             name,
-            assigned_key,
+            context: DEFAULT_CONTEXT.to_string(),
+            binding: binding.into(),
             description,
-            callback: ActionCallback::Async(Arc::new(move || {
-                Box::pin(callback()) as Pin<Box<dyn Future<Output = ()> + Send>>
+            phase: ActionPhase::Pressed,
+            hold_threshold: Duration::ZERO,
+            owner: None,
+            callback: ActionCallback::Async(Arc::new(move |event| {
+                Box::pin(callback(event)) as BoxedFuture
             })),
             callback_type: CallbackType::Async,
         }
     }
 
+    /// Put the action in the input context called `context` instead of the default one.
+    pub fn in_context(mut self, context: &str) -> Self {
+        self.context = context.to_string();
+        self
+    }
+
+    /// Mark the action as registered by `owner`, so `ActionsService::unregister_owner` removes it.
+    pub fn owned_by(mut self, owner: OwnerId) -> Self {
+        self.owner = Some(owner);
+        self
+    }
+
+    /// Fire on `phase` instead of the default `ActionPhase::Pressed`.
+    pub fn on_phase(mut self, phase: ActionPhase) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    /// Only fire once the binding has been held for `threshold`. Used with `Held`, `HoldCompleted`
+    /// and `Released` (a release after a long press).
+    pub fn with_hold_threshold(mut self, threshold: Duration) -> Self {
+        self.hold_threshold = threshold;
+        self
+    }
+
+    pub fn matches(&self, event: &ActionEvent) -> bool {
+        self.phase == event.phase && event.held_duration >= self.hold_threshold
+    }
+
     pub fn check_callback_type(&self) -> &CallbackType {
         &self.callback_type
     }
-    
-    pub async fn call(&self) {
+
+    pub async fn call(&self, event: &ActionEvent) {
+        match &self.callback {
+            ActionCallback::Sync(callback) => callback(event),
+            ActionCallback::Async(callback) => callback(*event).await
+        }
+    }
+
+    pub fn trigger_if_matches(&self, event: &ActionEvent) {
+        if self.matches(event) {
+            self.trigger(event);
+        }
+    }
+
+    /// The callback bound to `event`, to run later: a sync callback runs when the returned
+    /// closure is called, an async one is spawned as a detached task then.
+    pub fn deferred(&self, event: &ActionEvent) -> DeferredCallback {
+        let event = *event;
         match &self.callback {
-            ActionCallback::Sync(callback) => callback(),
-            ActionCallback::Async(callback) => callback().await
+            ActionCallback::Sync(callback) => {
+                let callback = callback.clone();
+                Box::new(move || callback(&event))
+            },
+            ActionCallback::Async(callback) => {
+                let callback = callback.clone();
+                Box::new(move || { task::spawn(callback(event)); })
+            },
+        }
+    }
+
+    /// Run a sync callback in place, or spawn an async one as a detached task.
+    pub fn trigger(&self, event: &ActionEvent) {
+        match &self.callback {
+            ActionCallback::Sync(callback) => callback(event),
+            ActionCallback::Async(callback) => { task::spawn(callback(*event)); },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::services::input_bindings::Modifiers;
+    use sdl2::controller::Axis;
+    use sdl2::keyboard::{Keycode, Mod};
+    use std::sync::Mutex;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn logging_action(log: &Log, name: &str, binding: impl Into<Binding>) -> Action {
+        let log = log.clone();
+        let entry = name.to_string();
+        Action::new(name.to_string(), binding, None, move |_| log.lock().unwrap().push(entry.clone()))
+    }
+
+    fn key_down(keycode: Keycode, keymod: Mod) -> InputEvent {
+        InputEvent::KeyDown { keycode, keymod, repeat: false, timestamp: 0 }
+    }
+
+    fn key_up(keycode: Keycode) -> InputEvent {
+        InputEvent::KeyUp { keycode, keymod: Mod::NOMOD, timestamp: 0 }
+    }
+
+    fn frame(millis: u64) -> FrameContext {
+        FrameContext {
+            delta: Duration::from_millis(millis),
+            total_time: Duration::ZERO,
+            frame_index: 0,
+            alpha: 0.0,
         }
     }
-}
\ No newline at end of file
+
+    fn send(actions: &mut ActionsService, event: InputEvent) {
+        actions.handle_event(&event);
+        actions.run_callbacks();
+    }
+
+    fn step(actions: &mut ActionsService, millis: u64) {
+        actions.update(&frame(millis));
+        actions.run_callbacks();
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        std::mem::take(&mut *log.lock().unwrap())
+    }
+
+    #[test]
+    fn press_and_release_fire_their_phases() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        actions.register_action(logging_action(&log, "Jump", Keycode::Space));
+        actions.register_action(logging_action(&log, "Land", Keycode::Space).on_phase(ActionPhase::Released));
+
+        send(&mut actions, key_down(Keycode::Space, Mod::NOMOD));
+        assert_eq!(take(&log), ["Jump"]);
+        assert!(actions.is_action_active("Jump"));
+
+        send(&mut actions, key_up(Keycode::Space));
+        assert_eq!(take(&log), ["Land"]);
+        assert!(!actions.is_button_held(Keycode::Space));
+    }
+
+    #[test]
+    fn callbacks_wait_until_they_are_taken() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        actions.register_action(logging_action(&log, "Jump", Keycode::Space));
+
+        actions.handle_event(&key_down(Keycode::Space, Mod::NOMOD));
+        assert!(take(&log).is_empty());
+
+        let callbacks = actions.take_callbacks();
+        assert_eq!(callbacks.len(), 1);
+        callbacks.into_iter().for_each(|callback| callback());
+        assert_eq!(take(&log), ["Jump"]);
+    }
+
+    #[test]
+    fn more_specific_bindings_win() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        actions.register_action(logging_action(&log, "Save", Binding::key_with(Keycode::S, Modifiers::CTRL)));
+        actions.register_action(logging_action(&log, "Back", Keycode::S));
+
+        send(&mut actions, key_down(Keycode::S, Mod::LCTRLMOD));
+        assert_eq!(take(&log), ["Save"]);
+
+        send(&mut actions, key_up(Keycode::S));
+        send(&mut actions, key_down(Keycode::S, Mod::NOMOD));
+        assert_eq!(take(&log), ["Back"]);
+    }
+
+    #[test]
+    fn chords_fire_once_every_button_is_held() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        let chord = Binding::chord(&[InputButton::Key(Keycode::A), InputButton::Key(Keycode::S)], Modifiers::NONE);
+        actions.register_action(logging_action(&log, "Spin", chord));
+        actions.register_action(logging_action(&log, "Dash", Keycode::S));
+
+        send(&mut actions, key_down(Keycode::S, Mod::NOMOD));
+        assert_eq!(take(&log), ["Dash"]);
+
+        send(&mut actions, key_up(Keycode::S));
+        send(&mut actions, key_down(Keycode::A, Mod::NOMOD));
+        assert!(take(&log).is_empty());
+
+        send(&mut actions, key_down(Keycode::S, Mod::NOMOD));
+        assert_eq!(take(&log), ["Spin"]);
+        assert!(actions.is_action_active("Spin"));
+
+        send(&mut actions, key_up(Keycode::A));
+        assert!(!actions.is_action_active("Spin"));
+    }
+
+    #[test]
+    fn sequences_fire_only_within_the_max_gap() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        let buttons = [Keycode::Down, Keycode::Right, Keycode::J].map(InputButton::Key);
+        actions.register_action(logging_action(&log, "Fireball", Binding::sequence(&buttons, Duration::from_millis(200))));
+        actions.register_action(logging_action(&log, "Punch", Keycode::J));
+
+        let tap = |actions: &mut ActionsService, keycode: Keycode, wait: u64| {
+            step(actions, wait);
+            send(actions, key_down(keycode, Mod::NOMOD));
+            send(actions, key_up(keycode));
+        };
+
+        tap(&mut actions, Keycode::Down, 0);
+        tap(&mut actions, Keycode::Right, 150);
+        tap(&mut actions, Keycode::J, 150);
+        assert_eq!(take(&log), ["Fireball"]);
+
+        tap(&mut actions, Keycode::Down, 500);
+        tap(&mut actions, Keycode::Right, 150);
+        tap(&mut actions, Keycode::J, 250);
+        assert_eq!(take(&log), ["Punch"]);
+    }
+
+    #[test]
+    fn hold_completes_once_after_the_threshold() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        let charge = logging_action(&log, "Charge", Keycode::E)
+            .on_phase(ActionPhase::HoldCompleted)
+            .with_hold_threshold(Duration::from_millis(500));
+        actions.register_action(charge);
+
+        send(&mut actions, key_down(Keycode::E, Mod::NOMOD));
+        step(&mut actions, 300);
+        assert!(take(&log).is_empty());
+
+        step(&mut actions, 300);
+        assert_eq!(take(&log), ["Charge"]);
+
+        step(&mut actions, 300);
+        assert!(take(&log).is_empty());
+    }
+
+    #[test]
+    fn held_timestamps_wrap_around_like_sdl_timestamps() {
+        let timestamps = Arc::new(Mutex::new(Vec::new()));
+        let mut actions = ActionsService::new();
+        let recorded = timestamps.clone();
+        let charge = Action::new("Charge".to_string(), Keycode::E, None, move |event| recorded.lock().unwrap().push(event.timestamp))
+            .on_phase(ActionPhase::Held);
+        actions.register_action(charge);
+
+        send(&mut actions, InputEvent::KeyDown { keycode: Keycode::E, keymod: Mod::NOMOD, repeat: false, timestamp: u32::MAX - 10 });
+        step(&mut actions, 10);
+        step(&mut actions, 100);
+
+        assert_eq!(*timestamps.lock().unwrap(), [u32::MAX, 99]);
+    }
+
+    #[test]
+    fn axes_are_shaped_and_mouse_deltas_reset_every_frame() {
+        let mut actions = ActionsService::new();
+        actions.register_axis(AxisAction::new("LookX", AxisSource::MouseX).with_sensitivity(0.5));
+        actions.register_axis(AxisAction::new("MoveX", AxisSource::Gamepad(Axis::LeftX)).with_dead_zone(0.5));
+        actions.register_axis(AxisAction::new("Strafe", AxisSource::Buttons {
+            negative: InputButton::Key(Keycode::A),
+            positive: InputButton::Key(Keycode::D),
+        }));
+
+        send(&mut actions, InputEvent::MouseMotion { x: 0, y: 0, xrel: 6, yrel: 0, timestamp: 0 });
+        send(&mut actions, InputEvent::MouseMotion { x: 0, y: 0, xrel: 4, yrel: 0, timestamp: 0 });
+        send(&mut actions, InputEvent::ControllerAxisMotion { axis: Axis::LeftX, value: -i16::MAX, which: 0, timestamp: 0 });
+        send(&mut actions, key_down(Keycode::D, Mod::NOMOD));
+        step(&mut actions, 16);
+
+        assert_eq!(actions.axis_value("LookX"), 5.0);
+        assert_eq!(actions.axis_value("MoveX"), -1.0);
+        assert_eq!(actions.axis_value("Strafe"), 1.0);
+
+        send(&mut actions, InputEvent::ControllerAxisMotion { axis: Axis::LeftX, value: i16::MAX / 4, which: 0, timestamp: 0 });
+        step(&mut actions, 16);
+
+        assert_eq!(actions.axis_value("LookX"), 0.0);
+        assert_eq!(actions.axis_value("MoveX"), 0.0);
+        assert_eq!(actions.axis_value("Missing"), 0.0);
+    }
+
+    #[test]
+    fn consuming_context_hides_the_default_context() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        actions.register_context(InputContext::new("Menu", true));
+        actions.register_action(logging_action(&log, "Jump", Keycode::Space));
+        actions.register_action(logging_action(&log, "Confirm", Keycode::Return).in_context("Menu"));
+
+        actions.push_context("Menu").unwrap();
+        send(&mut actions, key_down(Keycode::Space, Mod::NOMOD));
+        send(&mut actions, key_down(Keycode::Return, Mod::NOMOD));
+        assert_eq!(take(&log), ["Confirm"]);
+
+        send(&mut actions, key_up(Keycode::Space));
+        assert_eq!(actions.pop_context().as_deref(), Some("Menu"));
+        send(&mut actions, key_down(Keycode::Space, Mod::NOMOD));
+        assert_eq!(take(&log), ["Jump"]);
+        assert!(actions.pop_context().is_none());
+    }
+
+    #[test]
+    fn wheel_ticks_press_and_release() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        actions.register_action(logging_action(&log, "NextWeapon", InputButton::WheelUp));
+        actions.register_action(logging_action(&log, "Zoomed", InputButton::WheelUp).on_phase(ActionPhase::Released));
+
+        send(&mut actions, InputEvent::MouseWheel { x: 0, y: 1, timestamp: 0 });
+        assert_eq!(take(&log), ["NextWeapon", "Zoomed"]);
+        assert!(!actions.is_button_held(InputButton::WheelUp));
+    }
+
+    #[test]
+    fn rebinding_only_touches_the_action_in_that_context() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        actions.register_context(InputContext::new("Menu", false));
+        actions.register_action(logging_action(&log, "Confirm", Keycode::Space));
+        actions.register_action(logging_action(&log, "Confirm", Keycode::Return).in_context("Menu"));
+
+        actions.rebind("Menu", "Confirm", Keycode::E.into()).unwrap();
+        assert!(actions.rebind("Menu", "Jump", Keycode::E.into()).is_err());
+
+        let table = actions.binding_table();
+        assert_eq!(table.action(DEFAULT_CONTEXT, "Confirm"), Some(&Binding::from(Keycode::Space)));
+        assert_eq!(table.action("Menu", "Confirm"), Some(&Binding::from(Keycode::E)));
+
+        let mut restored = table.clone();
+        restored.insert_action("Menu", "Confirm", Keycode::Return.into());
+        actions.apply_binding_table(&restored);
+        assert_eq!(actions.binding_table(), restored);
+    }
+
+    #[test]
+    fn listen_for_rebind_captures_the_next_press() {
+        let log = Log::default();
+        let mut actions = ActionsService::new();
+        actions.register_action(logging_action(&log, "Jump", Keycode::Space));
+        actions.register_action(logging_action(&log, "Crouch", Keycode::C));
+
+        actions.listen_for_rebind(DEFAULT_CONTEXT, "Jump");
+        send(&mut actions, key_down(Keycode::LCtrl, Mod::LCTRLMOD));
+        send(&mut actions, key_down(Keycode::C, Mod::LCTRLMOD));
+        assert!(take(&log).is_empty());
+
+        let outcome = actions.take_rebind_outcome().unwrap();
+        assert_eq!(outcome.context, DEFAULT_CONTEXT);
+        assert_eq!(outcome.action, "Jump");
+        assert_eq!(outcome.binding, Binding::key_with(Keycode::C, Modifiers::CTRL));
+        assert!(outcome.conflicts.is_empty());
+        assert!(!actions.is_listening_for_rebind());
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut actions = ActionsService::new();
+        actions.register_action(Action::new("Run".to_string(), Keycode::LShift, None, |_| {}));
+
+        send(&mut actions, key_down(Keycode::LShift, Mod::LSHIFTMOD));
+        assert!(actions.is_action_active("Run"));
+
+        send(&mut actions, InputEvent::WindowFocusLost { timestamp: 0 });
+        assert!(!actions.is_action_active("Run"));
+        assert!(!actions.is_button_held(Keycode::LShift));
+    }
+}
@@ -0,0 +1,447 @@
+use crate::game::services::input_contexts::DEFAULT_CONTEXT;
+use crate::game::services::sdl_serde::{duration_millis, gamepad_axis};
+use serde::{Deserialize, Serialize};
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Ctrl/Shift/Alt/GUI state, without the left/right distinction SDL makes.
+/// Stored in binding files as e.g. `"Ctrl+Shift"`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Modifiers(u8);
+
+const MODIFIER_NAMES: [(Modifiers, &str); 4] = [
+    (Modifiers::CTRL, "Ctrl"),
+    (Modifiers::SHIFT, "Shift"),
+    (Modifiers::ALT, "Alt"),
+    (Modifiers::GUI, "Gui"),
+];
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers(0);
+    pub const CTRL: Modifiers = Modifiers(1);
+    pub const SHIFT: Modifiers = Modifiers(1 << 1);
+    pub const ALT: Modifiers = Modifiers(1 << 2);
+    pub const GUI: Modifiers = Modifiers(1 << 3);
+
+    pub fn from_sdl(keymod: Mod) -> Self {
+        let mut modifiers = Modifiers::NONE;
+
+        if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+            modifiers = modifiers | Modifiers::CTRL;
+        }
+        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+            modifiers = modifiers | Modifiers::SHIFT;
+        }
+        if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) {
+            modifiers = modifiers | Modifiers::ALT;
+        }
+        if keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD) {
+            modifiers = modifiers | Modifiers::GUI;
+        }
+
+        modifiers
+    }
+
+    pub fn contains(&self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn count(&self) -> u32 {
+        self.0.count_ones()
+    }
+}
+
+impl Display for Modifiers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = MODIFIER_NAMES
+            .iter()
+            .filter(|(modifier, _)| self.contains(*modifier))
+            .map(|(_, name)| *name)
+            .collect::<Vec<&str>>();
+
+        write!(f, "{}", names.join("+"))
+    }
+}
+
+impl FromStr for Modifiers {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = Modifiers::NONE;
+
+        for name in s.split('+').map(str::trim).filter(|name| !name.is_empty()) {
+            match MODIFIER_NAMES.iter().find(|(_, modifier_name)| modifier_name.eq_ignore_ascii_case(name)) {
+                Some((modifier, _)) => modifiers = modifiers | *modifier,
+                None => return Err(format!("Unknown modifier: {}", name)),
+            }
+        }
+
+        Ok(modifiers)
+    }
+}
+
+impl TryFrom<String> for Modifiers {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Modifiers> for String {
+    fn from(modifiers: Modifiers) -> Self {
+        modifiers.to_string()
+    }
+}
+
+impl std::ops::BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, rhs: Modifiers) -> Modifiers {
+        Modifiers(self.0 | rhs.0)
+    }
+}
+
+/// Anything that can be pressed and released. Wheel ticks are treated as an instant
+/// press and release.
+/// Stored in binding files as `"Key:Space"`, `"Mouse:Left"`, `"Gamepad:a"` or `"Wheel:Up"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum InputButton {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Gamepad(Button),
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+}
+
+impl InputButton {
+    /// Ctrl/Shift/Alt/GUI keys, which are read as modifiers rather than bound on their own
+    /// when listening for a rebind.
+    pub fn is_modifier_key(&self) -> bool {
+        matches!(self, InputButton::Key(
+            Keycode::LCtrl | Keycode::RCtrl | Keycode::LShift | Keycode::RShift |
+            Keycode::LAlt | Keycode::RAlt | Keycode::LGui | Keycode::RGui))
+    }
+}
+
+impl From<Keycode> for InputButton {
+    fn from(key: Keycode) -> Self {
+        InputButton::Key(key)
+    }
+}
+
+impl From<MouseButton> for InputButton {
+    fn from(button: MouseButton) -> Self {
+        InputButton::Mouse(button)
+    }
+}
+
+impl From<Button> for InputButton {
+    fn from(button: Button) -> Self {
+        InputButton::Gamepad(button)
+    }
+}
+
+impl Display for InputButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputButton::Key(key) => write!(f, "Key:{}", key.name()),
+            InputButton::Mouse(button) => write!(f, "Mouse:{}", mouse_button_name(*button)),
+            InputButton::Gamepad(button) => write!(f, "Gamepad:{}", button.string()),
+            InputButton::WheelUp => write!(f, "Wheel:Up"),
+            InputButton::WheelDown => write!(f, "Wheel:Down"),
+            InputButton::WheelLeft => write!(f, "Wheel:Left"),
+            InputButton::WheelRight => write!(f, "Wheel:Right"),
+        }
+    }
+}
+
+impl FromStr for InputButton {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (device, name) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Input button is missing a device prefix: {}", s))?;
+
+        let button = match device {
+            "Key" => Keycode::from_name(name).map(InputButton::Key),
+            "Mouse" => mouse_button_from_name(name).map(InputButton::Mouse),
+            "Gamepad" => Button::from_string(name).map(InputButton::Gamepad),
+            "Wheel" => match name {
+                "Up" => Some(InputButton::WheelUp),
+                "Down" => Some(InputButton::WheelDown),
+                "Left" => Some(InputButton::WheelLeft),
+                "Right" => Some(InputButton::WheelRight),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        button.ok_or_else(|| format!("Unknown input button: {}", s))
+    }
+}
+
+impl TryFrom<String> for InputButton {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<InputButton> for String {
+    fn from(button: InputButton) -> Self {
+        button.to_string()
+    }
+}
+
+fn mouse_button_name(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "Left",
+        MouseButton::Middle => "Middle",
+        MouseButton::Right => "Right",
+        MouseButton::X1 => "X1",
+        MouseButton::X2 => "X2",
+        MouseButton::Unknown => "Unknown",
+    }
+}
+
+fn mouse_button_from_name(name: &str) -> Option<MouseButton> {
+    match name {
+        "Left" => Some(MouseButton::Left),
+        "Middle" => Some(MouseButton::Middle),
+        "Right" => Some(MouseButton::Right),
+        "X1" => Some(MouseButton::X1),
+        "X2" => Some(MouseButton::X2),
+        _ => None,
+    }
+}
+
+/// The input an `Action` listens for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Binding {
+    /// A single button, optionally with modifiers held.
+    Button {
+        button: InputButton,
+        #[serde(default)]
+        modifiers: Modifiers,
+    },
+    /// Several buttons held down together, in any order.
+    Chord {
+        buttons: Vec<InputButton>,
+        #[serde(default)]
+        modifiers: Modifiers,
+    },
+    /// Buttons pressed one after another, each within `max_gap` of the previous one.
+    Sequence {
+        buttons: Vec<InputButton>,
+        #[serde(rename = "max_gap_ms", with = "duration_millis")]
+        max_gap: Duration,
+    },
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let buttons = self.buttons()
+            .iter()
+            .map(|button| button.to_string())
+            .collect::<Vec<String>>();
+
+        let joined = match self {
+            Binding::Sequence { .. } => buttons.join(", "),
+            _ => buttons.join(" + "),
+        };
+
+        if self.modifiers() == Modifiers::NONE {
+            write!(f, "{}", joined)
+        } else {
+            write!(f, "{}+{}", self.modifiers(), joined)
+        }
+    }
+}
+
+impl<T: Into<InputButton>> From<T> for Binding {
+    fn from(button: T) -> Self {
+        Binding::Button { button: button.into(), modifiers: Modifiers::NONE }
+    }
+}
+
+impl Binding {
+    pub fn key_with(key: Keycode, modifiers: Modifiers) -> Self {
+        Binding::Button { button: InputButton::Key(key), modifiers }
+    }
+
+    pub fn chord(buttons: &[InputButton], modifiers: Modifiers) -> Self {
+        Binding::Chord { buttons: buttons.to_vec(), modifiers }
+    }
+
+    pub fn sequence(buttons: &[InputButton], max_gap: Duration) -> Self {
+        Binding::Sequence { buttons: buttons.to_vec(), max_gap }
+    }
+
+    pub fn buttons(&self) -> &[InputButton] {
+        match self {
+            Binding::Button { button, .. } => std::slice::from_ref(button),
+            Binding::Chord { buttons, .. } | Binding::Sequence { buttons, .. } => buttons,
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        match self {
+            Binding::Button { modifiers, .. } | Binding::Chord { modifiers, .. } => *modifiers,
+            Binding::Sequence { .. } => Modifiers::NONE,
+        }
+    }
+
+    pub fn contains(&self, button: InputButton) -> bool {
+        self.buttons().contains(&button)
+    }
+
+    /// Used to resolve conflicts between bindings satisfied by the same press:
+    /// only the bindings with the highest specificity fire, so Ctrl+S beats S.
+    pub fn specificity(&self) -> u32 {
+        self.buttons().len() as u32 + self.modifiers().count()
+    }
+
+    /// Whether pressing `pressed` completes this binding, given the buttons and modifiers held
+    /// and the recent press history (oldest first, `pressed` included as the last entry).
+    pub fn is_completed_by(
+        &self,
+        pressed: InputButton,
+        is_held: impl Fn(InputButton) -> bool,
+        modifiers: Modifiers,
+        history: &VecDeque<(InputButton, Duration)>) -> bool
+    {
+        match self {
+            Binding::Button { button, modifiers: required } => {
+                *button == pressed && modifiers.contains(*required)
+            },
+            Binding::Chord { buttons, modifiers: required } => {
+                buttons.contains(&pressed)
+                    && buttons.iter().all(|button| is_held(*button))
+                    && modifiers.contains(*required)
+            },
+            Binding::Sequence { buttons, max_gap } => {
+                if buttons.last() != Some(&pressed) || history.len() < buttons.len() {
+                    return false;
+                }
+
+                let recent = history.iter().skip(history.len() - buttons.len());
+                let mut last_time: Option<Duration> = None;
+
+                for ((button, time), expected) in recent.zip(buttons.iter()) {
+                    if button != expected {
+                        return false;
+                    }
+                    if let Some(last_time) = last_time {
+                        if *time - last_time > *max_gap {
+                            return false;
+                        }
+                    }
+                    last_time = Some(*time);
+                }
+
+                true
+            },
+        }
+    }
+}
+
+/// Where an `AxisAction` reads its value from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AxisSource {
+    /// A controller stick or trigger, normalized to `[-1, 1]` (`[0, 1]` for triggers).
+    Gamepad(#[serde(with = "gamepad_axis")] Axis),
+    /// Mouse movement in pixels since the last frame.
+    MouseX,
+    MouseY,
+    /// Wheel ticks since the last frame.
+    WheelX,
+    WheelY,
+    /// Two buttons acting as a digital axis, e.g. A/D for strafing.
+    Buttons { negative: InputButton, positive: InputButton },
+}
+
+/// An analog input with its shaping applied. The current value is read back through
+/// `ActionsService::axis_value`.
+#[derive(Debug, Clone)]
+pub struct AxisAction {
+    pub name: String,
+    pub context: String,
+    pub source: AxisSource,
+    pub dead_zone: f32,
+    pub sensitivity: f32,
+    pub inverted: bool,
+    pub(crate) raw_value: f32,
+    pub(crate) value: f32,
+}
+
+impl AxisAction {
+    pub fn new(name: &str, source: AxisSource) -> Self {
+        Self {
+            name: name.to_string(),
+            context: DEFAULT_CONTEXT.to_string(),
+            source,
+            dead_zone: 0.0,
+            sensitivity: 1.0,
+            inverted: false,
+            raw_value: 0.0,
+            value: 0.0,
+        }
+    }
+
+    /// Put the axis in the input context called `context` instead of the default one.
+    pub fn in_context(mut self, context: &str) -> Self {
+        self.context = context.to_string();
+        self
+    }
+
+    pub fn with_dead_zone(mut self, dead_zone: f32) -> Self {
+        self.dead_zone = dead_zone;
+        self
+    }
+
+    pub fn with_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    pub fn inverted(mut self, inverted: bool) -> Self {
+        self.inverted = inverted;
+        self
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Apply dead zone, sensitivity and inversion to a raw reading. Normalized sources are
+    /// rescaled so the output still starts at zero right outside the dead zone.
+    pub fn shape(&self, raw: f32) -> f32 {
+        let magnitude = raw.abs();
+        if magnitude <= self.dead_zone {
+            return 0.0;
+        }
+
+        let magnitude = match self.source {
+            AxisSource::Gamepad(_) if self.dead_zone < 1.0 => {
+                (magnitude - self.dead_zone) / (1.0 - self.dead_zone)
+            },
+            _ => magnitude,
+        };
+
+        let value = magnitude.copysign(raw) * self.sensitivity;
+        if self.inverted { -value } else { value }
+    }
+}
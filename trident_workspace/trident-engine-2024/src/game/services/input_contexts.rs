@@ -0,0 +1,22 @@
+/// Name of the context every action and axis belongs to unless told otherwise.
+/// It always sits at the bottom of the context stack and can't be popped.
+pub const DEFAULT_CONTEXT: &str = "default";
+
+/// A named layer of bindings, e.g. "gameplay", "menu" or "vehicle".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputContext {
+    pub name: String,
+    /// When set, layers below this one see no input at all while it is active, which is
+    /// what a pause menu wants. Otherwise only the presses this layer handles are kept from
+    /// the layers below.
+    pub consume_input: bool,
+}
+
+impl InputContext {
+    pub fn new(name: &str, consume_input: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            consume_input,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use sdl2::controller::{Axis, Button};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use serde::{Deserialize, Serialize};
+use crate::game::services::sdl_serde::{gamepad_axis, gamepad_button, keycode, keymod, mouse_button};
+
+/// Engine-side copy of the SDL events that services care about.
+/// Timestamps are SDL ticks in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum InputEvent {
+    KeyDown {
+        #[serde(with = "keycode")] keycode: Keycode,
+        #[serde(with = "keymod")] keymod: Mod,
+        repeat: bool,
+        timestamp: u32,
+    },
+    KeyUp {
+        #[serde(with = "keycode")] keycode: Keycode,
+        #[serde(with = "keymod")] keymod: Mod,
+        timestamp: u32,
+    },
+    MouseButtonDown { #[serde(with = "mouse_button")] button: MouseButton, x: i32, y: i32, timestamp: u32 },
+    MouseButtonUp { #[serde(with = "mouse_button")] button: MouseButton, x: i32, y: i32, timestamp: u32 },
+    MouseMotion { x: i32, y: i32, xrel: i32, yrel: i32, timestamp: u32 },
+    MouseWheel { x: i32, y: i32, timestamp: u32 },
+    ControllerButtonDown { #[serde(with = "gamepad_button")] button: Button, which: u32, timestamp: u32 },
+    ControllerButtonUp { #[serde(with = "gamepad_button")] button: Button, which: u32, timestamp: u32 },
+    ControllerAxisMotion { #[serde(with = "gamepad_axis")] axis: Axis, value: i16, which: u32, timestamp: u32 },
+    WindowResized { width: i32, height: i32, timestamp: u32 },
+    WindowFocusGained { timestamp: u32 },
+    WindowFocusLost { timestamp: u32 },
+    Quit { timestamp: u32 },
+}
+
+impl InputEvent {
+    /// Translate an SDL event. Returns `None` for events the engine doesn't forward.
+    pub fn from_sdl(event: &Event) -> Option<Self> {
+        let input_event = match *event {
+            Event::KeyDown { keycode: Some(keycode), keymod, repeat, timestamp, .. } => {
+                InputEvent::KeyDown { keycode, keymod, repeat, timestamp }
+            },
+            Event::KeyUp { keycode: Some(keycode), keymod, timestamp, .. } => {
+                InputEvent::KeyUp { keycode, keymod, timestamp }
+            },
+            Event::MouseButtonDown { mouse_btn, x, y, timestamp, .. } => {
+                InputEvent::MouseButtonDown { button: mouse_btn, x, y, timestamp }
+            },
+            Event::MouseButtonUp { mouse_btn, x, y, timestamp, .. } => {
+                InputEvent::MouseButtonUp { button: mouse_btn, x, y, timestamp }
+            },
+            Event::MouseMotion { x, y, xrel, yrel, timestamp, .. } => {
+                InputEvent::MouseMotion { x, y, xrel, yrel, timestamp }
+            },
+            Event::MouseWheel { x, y, timestamp, .. } => {
+                InputEvent::MouseWheel { x, y, timestamp }
+            },
+            Event::ControllerButtonDown { button, which, timestamp } => {
+                InputEvent::ControllerButtonDown { button, which, timestamp }
+            },
+            Event::ControllerButtonUp { button, which, timestamp } => {
+                InputEvent::ControllerButtonUp { button, which, timestamp }
+            },
+            Event::ControllerAxisMotion { axis, value, which, timestamp } => {
+                InputEvent::ControllerAxisMotion { axis, value, which, timestamp }
+            },
+            Event::Window { win_event, timestamp, .. } => match win_event {
+                WindowEvent::Resized(width, height) => InputEvent::WindowResized { width, height, timestamp },
+                WindowEvent::FocusGained => InputEvent::WindowFocusGained { timestamp },
+                WindowEvent::FocusLost => InputEvent::WindowFocusLost { timestamp },
+                WindowEvent::Close => InputEvent::Quit { timestamp },
+                _ => return None,
+            },
+            Event::Quit { timestamp } => InputEvent::Quit { timestamp },
+            _ => return None,
+        };
+
+        Some(input_event)
+    }
+
+    pub fn timestamp(&self) -> u32 {
+        match *self {
+            InputEvent::KeyDown { timestamp, .. }
+            | InputEvent::KeyUp { timestamp, .. }
+            | InputEvent::MouseButtonDown { timestamp, .. }
+            | InputEvent::MouseButtonUp { timestamp, .. }
+            | InputEvent::MouseMotion { timestamp, .. }
+            | InputEvent::MouseWheel { timestamp, .. }
+            | InputEvent::ControllerButtonDown { timestamp, .. }
+            | InputEvent::ControllerButtonUp { timestamp, .. }
+            | InputEvent::ControllerAxisMotion { timestamp, .. }
+            | InputEvent::WindowResized { timestamp, .. }
+            | InputEvent::WindowFocusGained { timestamp }
+            | InputEvent::WindowFocusLost { timestamp }
+            | InputEvent::Quit { timestamp } => timestamp,
+        }
+    }
+}
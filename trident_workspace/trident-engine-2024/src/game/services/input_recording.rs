@@ -0,0 +1,353 @@
+use crate::game::services::input_actions::ActionsService;
+use crate::game::services::input_events::InputEvent;
+use crate::game::services::random::RandomService;
+use crate::game::services::sdl_serde::duration_micros;
+use crate::game::services::{Service, ServiceManager};
+use crate::game_loop::FrameContext;
+use async_std::sync::RwLock;
+use async_std::task;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Bumped whenever the layout of `InputRecording` changes.
+pub const RECORDING_VERSION: u32 = 2;
+
+/// Every input event that arrived during one frame, plus the frame's timing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub frame_index: u64,
+    #[serde(with = "duration_micros")]
+    pub delta: Duration,
+    /// How many fixed updates ran during the frame.
+    pub fixed_steps: u32,
+    pub events: Vec<InputEvent>,
+}
+
+/// A timestamped stream of input events, grouped by frame, together with what else a replay
+/// needs to take the same path: the fixed timestep, how many fixed updates each frame ran and
+/// the `RandomService` seed. Saved as MessagePack to keep long recordings small.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputRecording {
+    pub version: u32,
+    pub seed: Option<u64>,
+    #[serde(with = "duration_micros")]
+    pub fixed_timestep: Duration,
+    pub frames: Vec<RecordedFrame>,
+}
+
+/// One step of `InputRecording::replay`.
+pub enum ReplayStep<'a> {
+    /// A fixed update, after the services' `fixed_update`. `GameLoop::update` goes here.
+    FixedUpdate(&'a FrameContext),
+    /// The end of a recorded frame, after the services' `update`. Rendering goes here.
+    Frame(&'a FrameContext),
+}
+
+impl Default for InputRecording {
+    fn default() -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            seed: None,
+            fixed_timestep: Duration::ZERO,
+            frames: Vec::new(),
+        }
+    }
+}
+
+impl InputRecording {
+    pub fn new() -> InputRecording {
+        Self::default()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let bytes = fs::read(path.as_ref())?;
+        let recording: InputRecording = rmp_serde::from_slice(&bytes)?;
+
+        if recording.version != RECORDING_VERSION {
+            return Err(format!(
+                "Unsupported input recording version {} (expected {})",
+                recording.version, RECORDING_VERSION
+            ).into());
+        }
+
+        Ok(recording)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let bytes = rmp_serde::to_vec_named(self)?;
+        fs::write(path.as_ref(), bytes)?;
+        Ok(())
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.delta).sum()
+    }
+
+    /// Replay the recorded run through `services` without a window: reseed the `RandomService`,
+    /// then for every frame dispatch its events, run as many fixed updates as it ran when recorded
+    /// and finish with the per-frame update. `step` is called after each fixed update and frame,
+    /// and deferred callbacks run in between, just like in `Application::run`.
+    pub fn replay(&self, services: &Arc<RwLock<ServiceManager>>, mut step: impl FnMut(ReplayStep)) {
+        if let Some(seed) = self.seed {
+            let random = task::block_on(services.read()).get::<RandomService>();
+            if let Some(random) = random {
+                task::block_on(random.write()).reseed(seed);
+            }
+        }
+
+        let mut total_time = Duration::ZERO;
+        for frame in self.frames.iter() {
+            task::block_on(async {
+                services.read().await.dispatch_events(&frame.events).await;
+            });
+            ServiceManager::run_callbacks(services);
+
+            for _ in 0..frame.fixed_steps {
+                total_time += self.fixed_timestep;
+                let fixed_frame = FrameContext {
+                    delta: self.fixed_timestep,
+                    total_time,
+                    frame_index: frame.frame_index,
+                    alpha: 0.0,
+                };
+
+                task::block_on(async {
+                    services.read().await.fixed_update_services(&fixed_frame).await;
+                });
+                ServiceManager::run_callbacks(services);
+                step(ReplayStep::FixedUpdate(&fixed_frame));
+            }
+
+            let render_frame = FrameContext {
+                delta: frame.delta,
+                total_time,
+                frame_index: frame.frame_index,
+                alpha: 0.0,
+            };
+
+            task::block_on(async {
+                services.read().await.update_services(&render_frame).await;
+            });
+            ServiceManager::run_callbacks(services);
+            step(ReplayStep::Frame(&render_frame));
+        }
+    }
+
+    /// Run just the events and frame deltas through `actions`, one `Service::update` per recorded
+    /// frame. Enough for testing bindings; use `replay` to reproduce a whole run.
+    pub fn replay_into(&self, actions: &mut ActionsService) {
+        let mut total_time = Duration::ZERO;
+
+        for frame in self.frames.iter() {
+            for event in frame.events.iter() {
+                actions.handle_event(event);
+            }
+
+            total_time += frame.delta;
+            actions.update(&FrameContext {
+                delta: frame.delta,
+                total_time,
+                frame_index: frame.frame_index,
+                alpha: 0.0,
+            });
+            actions.run_callbacks();
+        }
+    }
+}
+
+/// Collects events and fixed updates as they arrive and closes off a `RecordedFrame` on every update.
+#[derive(Default)]
+pub struct InputRecorder {
+    recording: InputRecording,
+    pending_events: Vec<InputEvent>,
+    pending_fixed_steps: u32,
+}
+
+impl InputRecorder {
+    pub fn new() -> InputRecorder {
+        Self::default()
+    }
+
+    /// Start a recording of a run whose `RandomService` was seeded with `seed`.
+    pub fn with_seed(seed: u64) -> InputRecorder {
+        let mut recorder = Self::default();
+        recorder.recording.seed = Some(seed);
+        recorder
+    }
+
+    pub fn record_event(&mut self, event: &InputEvent) {
+        self.pending_events.push(event.clone());
+    }
+
+    pub fn record_fixed_update(&mut self, frame: &FrameContext) {
+        self.recording.fixed_timestep = frame.delta;
+        self.pending_fixed_steps += 1;
+    }
+
+    pub fn end_frame(&mut self, frame: &FrameContext) {
+        self.recording.frames.push(RecordedFrame {
+            frame_index: frame.frame_index,
+            delta: frame.delta,
+            fixed_steps: std::mem::take(&mut self.pending_fixed_steps),
+            events: std::mem::take(&mut self.pending_events),
+        });
+    }
+
+    /// Stop recording. Events that arrived after the last update are kept in a final frame.
+    pub fn finish(mut self) -> InputRecording {
+        if !self.pending_events.is_empty() {
+            let frame_index = self.recording.frames.last().map_or(0, |frame| frame.frame_index + 1);
+            self.recording.frames.push(RecordedFrame {
+                frame_index,
+                delta: Duration::ZERO,
+                fixed_steps: self.pending_fixed_steps,
+                events: self.pending_events,
+            });
+        }
+
+        self.recording
+    }
+}
+
+/// Hands out recorded frames one at a time during playback.
+pub struct InputPlayback {
+    frames: VecDeque<RecordedFrame>,
+}
+
+impl InputPlayback {
+    pub fn new(recording: InputRecording) -> InputPlayback {
+        Self {
+            frames: recording.frames.into(),
+        }
+    }
+
+    pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+        self.frames.pop_front()
+    }
+
+    pub fn remaining_frames(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::services::input_actions::Action;
+    use sdl2::keyboard::{Keycode, Mod};
+    use std::sync::Mutex;
+
+    const FIXED_TIMESTEP: Duration = Duration::from_millis(10);
+
+    type Rolls = Arc<Mutex<Vec<f32>>>;
+
+    /// Services with a "Roll" action on Space that draws from the `RandomService`.
+    fn services(seed: u64, rolls: &Rolls) -> Arc<RwLock<ServiceManager>> {
+        let services = ServiceManager::new_shared();
+        let mut manager = task::block_on(services.write());
+        let random = manager.add_service("RandomService", RandomService::new(seed));
+        let actions = manager.add_service("ActionsService", ActionsService::new());
+
+        let rolls = rolls.clone();
+        task::block_on(actions.write()).register_action(Action::new("Roll".to_string(), Keycode::Space, None, move |_| {
+            let roll = task::block_on(random.write()).next_f32();
+            rolls.lock().unwrap().push(roll);
+        }));
+
+        task::block_on(manager.init_services()).unwrap();
+        drop(manager);
+        services
+    }
+
+    fn key_down(timestamp: u32) -> InputEvent {
+        InputEvent::KeyDown { keycode: Keycode::Space, keymod: Mod::NOMOD, repeat: false, timestamp }
+    }
+
+    fn key_up(timestamp: u32) -> InputEvent {
+        InputEvent::KeyUp { keycode: Keycode::Space, keymod: Mod::NOMOD, timestamp }
+    }
+
+    /// Drive `services` like `Application::run` would, with a varying number of fixed updates per
+    /// frame. Returns how many fixed updates ran.
+    fn run_live(services: &Arc<RwLock<ServiceManager>>, frames: &[(Vec<InputEvent>, u32)]) -> u32 {
+        let mut total_time = Duration::ZERO;
+        let mut fixed_updates = 0;
+
+        for (frame_index, (events, fixed_steps)) in frames.iter().enumerate() {
+            task::block_on(async { services.read().await.dispatch_events(events).await });
+            ServiceManager::run_callbacks(services);
+
+            for _ in 0..*fixed_steps {
+                total_time += FIXED_TIMESTEP;
+                let frame = FrameContext { delta: FIXED_TIMESTEP, total_time, frame_index: frame_index as u64, alpha: 0.0 };
+                task::block_on(async { services.read().await.fixed_update_services(&frame).await });
+                ServiceManager::run_callbacks(services);
+                fixed_updates += 1;
+            }
+
+            let delta = FIXED_TIMESTEP * *fixed_steps + Duration::from_millis(3);
+            let frame = FrameContext { delta, total_time, frame_index: frame_index as u64, alpha: 0.0 };
+            task::block_on(async { services.read().await.update_services(&frame).await });
+            ServiceManager::run_callbacks(services);
+        }
+
+        fixed_updates
+    }
+
+    #[test]
+    fn replay_reproduces_fixed_steps_and_random_numbers() {
+        let live_rolls = Rolls::default();
+        let live = services(42, &live_rolls);
+        let actions = task::block_on(live.read()).get::<ActionsService>().unwrap();
+        task::block_on(actions.write()).start_recording_with_seed(42);
+
+        let live_fixed_updates = run_live(&live, &[
+            (vec![key_down(1)], 1),
+            (vec![key_up(2)], 0),
+            (vec![], 3),
+            (vec![key_down(4), key_up(5)], 2),
+            (vec![key_down(6)], 1),
+        ]);
+        let recording = task::block_on(actions.write()).stop_recording().unwrap();
+
+        assert_eq!(recording.seed, Some(42));
+        assert_eq!(recording.fixed_timestep, FIXED_TIMESTEP);
+        assert_eq!(live_rolls.lock().unwrap().len(), 3);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run.tridentrec");
+        recording.save(&path).unwrap();
+        let loaded = InputRecording::load(&path).unwrap();
+        assert_eq!(loaded, recording);
+
+        let replay_rolls = Rolls::default();
+        let replayed = services(7, &replay_rolls);
+        let (mut fixed_updates, mut frames) = (0, 0);
+        loaded.replay(&replayed, |step| match step {
+            ReplayStep::FixedUpdate(frame) => {
+                assert_eq!(frame.delta, FIXED_TIMESTEP);
+                fixed_updates += 1;
+            },
+            ReplayStep::Frame(_) => frames += 1,
+        });
+
+        assert_eq!(fixed_updates, live_fixed_updates);
+        assert_eq!(frames, 5);
+        assert_eq!(*replay_rolls.lock().unwrap(), *live_rolls.lock().unwrap());
+    }
+
+    #[test]
+    fn load_rejects_other_versions() {
+        let recording = InputRecording { version: RECORDING_VERSION - 1, ..InputRecording::new() };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("old.tridentrec");
+        recording.save(&path).unwrap();
+
+        assert!(InputRecording::load(&path).is_err());
+    }
+}
@@ -1,41 +1,513 @@
-use std::collections::HashMap;
+use std::any::{type_name, Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, LazyLock};
 use async_std::sync::RwLock;
+use async_std::task;
+use input_events::InputEvent;
+use crate::game_loop::FrameContext;
 
+pub mod binding_table;
 pub mod input_actions;
+pub mod input_bindings;
+pub mod input_contexts;
+pub mod input_events;
+pub mod input_recording;
+pub mod random;
+pub mod sdl_serde;
 
 type SendSyncService = dyn Service + Send + Sync;
 
+/// Work a service wants done once no service locks are held, like running action callbacks that
+/// may themselves reach for services. See `Service::take_callbacks`.
+pub type DeferredCallback = Box<dyn FnOnce() + Send + Sync>;
+
+/// Identifies a service type. Returned from `Service::dependencies`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ServiceId {
+    type_id: TypeId,
+    name: &'static str,
+}
+
+impl ServiceId {
+    pub fn of<T: 'static>() -> ServiceId {
+        Self {
+            type_id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+/// Identifies whoever registered something, like the client library or a plugin, so everything
+/// it registered can be removed again without touching registrations that only share a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OwnerId(u64);
+
+impl OwnerId {
+    /// A new id, different from every other one handed out in this process.
+    pub fn unique() -> OwnerId {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Display for OwnerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// One registered service, reachable both as its concrete type and as `dyn Service`.
+/// Both handles point at the same lock.
+struct ServiceEntry {
+    id: ServiceId,
+    dependencies: Vec<ServiceId>,
+    initialized: bool,
+    service: Arc<RwLock<SendSyncService>>,
+    typed: Arc<dyn Any + Send + Sync>,
+}
+
 pub struct ServiceManager {
-    active_services: HashMap<String, Arc<RwLock<SendSyncService>>>
+    active_services: HashMap<TypeId, ServiceEntry>,
+    aliases: HashMap<String, TypeId>,
+    /// Every service in the order it was added. Breaks ties when sorting by dependencies.
+    registration_order: Vec<TypeId>,
+    /// Dependencies before dependents. Rebuilt by `init_services`.
+    update_order: Vec<TypeId>,
 }
 
 static SM_INSTANCE: LazyLock<Arc<RwLock<ServiceManager>>> = LazyLock::new(|| {
     Arc::new(RwLock::new(ServiceManager::new()))
 });
 
+impl Default for ServiceManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ServiceManager {
     pub fn new() -> ServiceManager {
         Self {
-            active_services: HashMap::new()
+            active_services: HashMap::new(),
+            aliases: HashMap::new(),
+            registration_order: Vec::new(),
+            update_order: Vec::new(),
         }
     }
 
+    /// A fresh, independent manager ready to be handed to an `Application` or shared between tasks.
+    pub fn new_shared() -> Arc<RwLock<Self>> {
+        Arc::new(RwLock::new(Self::new()))
+    }
+
+    /// The process-wide manager. Only a convenience for code that has no manager passed to it;
+    /// prefer `Application::services` or an explicitly created manager so worlds don't share state.
     pub fn get_service_manager() -> Arc<RwLock<Self>> {
         SM_INSTANCE.clone()
     }
 
-    pub fn add_service(&mut self, name: &str, service: Arc<RwLock<SendSyncService>>) {
-        let name_string = name.to_string();
-        self.active_services.insert(name_string, service);
+    /// Register `service` under its type, replacing any earlier service of the same type.
+    /// It starts receiving events and updates after the next `init_services`.
+    pub fn add<T: Service + Send + Sync + 'static>(&mut self, service: T) -> Arc<RwLock<T>> {
+        let id = ServiceId::of::<T>();
+        let typed = Arc::new(RwLock::new(service));
+
+        let replaced = self.active_services.insert(id.type_id, ServiceEntry {
+            id,
+            dependencies: T::dependencies(),
+            initialized: false,
+            service: typed.clone(),
+            typed: typed.clone(),
+        });
+
+        if replaced.is_none() {
+            self.registration_order.push(id.type_id);
+        }
+        self.update_order.retain(|type_id| *type_id != id.type_id);
+
+        typed
+    }
+
+    /// Register `service` under its type and also under the string alias `name`.
+    pub fn add_service<T: Service + Send + Sync + 'static>(&mut self, name: &str, service: T) -> Arc<RwLock<T>> {
+        let typed = self.add(service);
+        self.alias::<T>(name);
+        typed
+    }
+
+    /// Make the service of type `T` reachable through `get_service(name)`.
+    pub fn alias<T: 'static>(&mut self, name: &str) {
+        self.aliases.insert(name.to_string(), TypeId::of::<T>());
     }
 
+    pub fn get<T: Service + Send + Sync + 'static>(&self) -> Option<Arc<RwLock<T>>> {
+        self.active_services
+            .get(&TypeId::of::<T>())
+            .and_then(|entry| entry.typed.clone().downcast::<RwLock<T>>().ok())
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.active_services.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Remove the service of type `T` without calling its `shutdown`.
+    pub fn remove<T: 'static>(&mut self) -> bool {
+        self.remove_service(ServiceId::of::<T>())
+    }
+
+    /// Like `remove`, for code that only has the `ServiceId`, e.g. when unloading a plugin.
+    pub fn remove_service(&mut self, id: ServiceId) -> bool {
+        let type_id = id.type_id;
+        self.aliases.retain(|_, aliased| *aliased != type_id);
+        self.registration_order.retain(|registered| *registered != type_id);
+        self.update_order.retain(|ordered| *ordered != type_id);
+        self.active_services.remove(&type_id).is_some()
+    }
+
+    /// Look a service up by alias. Use `get` instead when the concrete type is known.
     pub fn get_service(&self, name: &str) -> Option<Arc<RwLock<SendSyncService>>> {
-        match self.active_services.get(name) {
-            Some(service) => Some(service.clone()),
-            None => None
+        self.aliases
+            .get(name)
+            .and_then(|type_id| self.active_services.get(type_id))
+            .map(|entry| entry.service.clone())
+    }
+
+    /// Sort services so every service comes after its dependencies.
+    /// Fails if a dependency was never added or if dependencies form a cycle.
+    fn resolve_order(&self) -> Result<Vec<TypeId>, Box<dyn Error>> {
+        let mut remaining_dependencies: HashMap<TypeId, usize> = HashMap::new();
+        let mut dependents: HashMap<TypeId, Vec<TypeId>> = HashMap::new();
+
+        for type_id in self.registration_order.iter() {
+            let entry = &self.active_services[type_id];
+
+            for dependency in entry.dependencies.iter() {
+                if !self.active_services.contains_key(&dependency.type_id) {
+                    return Err(format!(
+                        "Service {} depends on {}, which has not been added",
+                        entry.id.name, dependency.name
+                    ).into());
+                }
+
+                dependents.entry(dependency.type_id).or_default().push(*type_id);
+            }
+
+            remaining_dependencies.insert(*type_id, entry.dependencies.len());
+        }
+
+        let mut order = Vec::with_capacity(self.registration_order.len());
+        let mut ready: VecDeque<TypeId> = self.registration_order
+            .iter()
+            .filter(|type_id| remaining_dependencies[*type_id] == 0)
+            .copied()
+            .collect();
+
+        while let Some(type_id) = ready.pop_front() {
+            order.push(type_id);
+
+            for dependent in dependents.get(&type_id).into_iter().flatten() {
+                let count = remaining_dependencies.get_mut(dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(*dependent);
+                }
+            }
+        }
+
+        if order.len() < self.registration_order.len() {
+            let cycle = self.registration_order
+                .iter()
+                .filter(|type_id| !order.contains(type_id))
+                .map(|type_id| self.active_services[type_id].id.name)
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            return Err(format!("Service dependencies form a cycle between: {}", cycle).into());
+        }
+
+        Ok(order)
+    }
+
+    /// Work out the update order and call `init` on every service that hasn't been initialized yet,
+    /// dependencies first. Call again after adding services at runtime.
+    pub async fn init_services(&mut self) -> Result<(), Box<dyn Error>> {
+        self.update_order = self.resolve_order()?;
+
+        for type_id in self.update_order.iter() {
+            let entry = self.active_services.get_mut(type_id).unwrap();
+            if entry.initialized {
+                continue;
+            }
+
+            entry.service.write().await.init()?;
+            entry.initialized = true;
+        }
+
+        Ok(())
+    }
+
+    /// Call `shutdown` on every initialized service, dependents before their dependencies.
+    pub async fn shutdown_services(&mut self) {
+        for type_id in self.update_order.iter().rev() {
+            let entry = self.active_services.get_mut(type_id).unwrap();
+            if entry.initialized {
+                entry.service.write().await.shutdown();
+                entry.initialized = false;
+            }
+        }
+    }
+
+    /// Forward an input event to every active service.
+    pub async fn dispatch_event(&self, event: &InputEvent) {
+        for type_id in self.update_order.iter() {
+            self.active_services[type_id].service.write().await.handle_event(event);
         }
     }
+
+    /// Forward a frame's worth of input events to every active service, in order.
+    pub async fn dispatch_events(&self, events: &[InputEvent]) {
+        for event in events {
+            self.dispatch_event(event).await;
+        }
+    }
+
+    /// Take the callbacks every active service has deferred and run them once the lock on
+    /// `services` is released again.
+    pub fn run_callbacks(services: &Arc<RwLock<ServiceManager>>) {
+        let callbacks = task::block_on(async {
+            services.read().await.take_callbacks().await
+        });
+
+        for callback in callbacks {
+            callback();
+        }
+    }
+
+    /// Collect the callbacks every active service has deferred. Run them after releasing the lock
+    /// on this manager, so they are free to use it.
+    pub async fn take_callbacks(&self) -> Vec<DeferredCallback> {
+        let mut callbacks = Vec::new();
+        for type_id in self.update_order.iter() {
+            callbacks.extend(self.active_services[type_id].service.write().await.take_callbacks());
+        }
+
+        callbacks
+    }
+
+    /// Give every active service its once-per-frame update.
+    pub async fn update_services(&self, frame: &FrameContext) {
+        for type_id in self.update_order.iter() {
+            self.active_services[type_id].service.write().await.update(frame);
+        }
+    }
+
+    /// Give every active service one fixed timestep.
+    pub async fn fixed_update_services(&self, frame: &FrameContext) {
+        for type_id in self.update_order.iter() {
+            self.active_services[type_id].service.write().await.fixed_update(frame);
+        }
+    }
+}
+
+/// Something that lives in a `ServiceManager` for the whole run, like input, audio or physics.
+/// `ServiceManager` drives the lifecycle: `init` once, then `handle_event`, `fixed_update` and
+/// `update` every frame, then `shutdown`.
+pub trait Service {
+    /// Services that have to be initialized and updated before this one.
+    fn dependencies() -> Vec<ServiceId> where Self: Sized {
+        Vec::new()
+    }
+
+    fn init(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn handle_event(&mut self, _event: &InputEvent) {}
+
+    /// Called once per frame with the variable frame delta.
+    fn update(&mut self, _frame: &FrameContext) {}
+
+    /// Called once per fixed timestep, possibly several times in one frame.
+    fn fixed_update(&mut self, _frame: &FrameContext) {}
+
+    fn shutdown(&mut self) {}
+
+    /// Hand over the callbacks queued since the last call. They are run by whoever drives the
+    /// services once no lock is held, so a callback can use any service, including this one.
+    fn take_callbacks(&mut self) -> Vec<DeferredCallback> {
+        Vec::new()
+    }
 }
 
-pub trait Service {}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input_actions::{Action, ActionsService};
+    use input_bindings::InputButton;
+    use sdl2::keyboard::{Keycode, Mod};
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn deferred_callbacks_run_without_service_locks_held() {
+        let services = ServiceManager::new_shared();
+        let actions = task::block_on(services.write()).add(ActionsService::new());
+        let fired = Arc::new(AtomicBool::new(false));
+
+        let callback_services = services.clone();
+        let callback_fired = fired.clone();
+        let jump = Action::new("Jump".to_string(), InputButton::Key(Keycode::Space), None, move |_| {
+            // Both locks are free by the time the callback runs.
+            let manager = task::block_on(callback_services.write());
+            let actions = manager.get::<ActionsService>().unwrap();
+            drop(task::block_on(actions.write()));
+            callback_fired.store(true, Ordering::SeqCst);
+        });
+        task::block_on(actions.write()).register_action(jump);
+
+        let key_down = InputEvent::KeyDown { keycode: Keycode::Space, keymod: Mod::NOMOD, repeat: false, timestamp: 0 };
+        let callbacks = task::block_on(async {
+            services.write().await.init_services().await.unwrap();
+            let manager = services.read().await;
+            manager.dispatch_events(&[key_down]).await;
+            manager.take_callbacks().await
+        });
+
+        assert!(!fired.load(Ordering::SeqCst));
+        assert_eq!(callbacks.len(), 1);
+
+        for callback in callbacks {
+            callback();
+        }
+        assert!(fired.load(Ordering::SeqCst));
+    }
+
+    type Log = Arc<std::sync::Mutex<Vec<String>>>;
+
+    /// Defines a service that logs its lifecycle calls and depends on the given services.
+    macro_rules! logged_service {
+        ($name:ident $(, $dependency:ident)*) => {
+            struct $name(Log);
+
+            impl $name {
+                fn push(&self, call: &str) {
+                    self.0.lock().unwrap().push(format!("{} {}", call, stringify!($name)));
+                }
+            }
+
+            impl Service for $name {
+                fn dependencies() -> Vec<ServiceId> {
+                    vec![$(ServiceId::of::<$dependency>()),*]
+                }
+
+                fn init(&mut self) -> Result<(), Box<dyn Error>> {
+                    self.push("init");
+                    Ok(())
+                }
+
+                fn update(&mut self, _frame: &FrameContext) {
+                    self.push("update");
+                }
+
+                fn shutdown(&mut self) {
+                    self.push("shutdown");
+                }
+            }
+        };
+    }
+
+    logged_service!(Input);
+    logged_service!(Physics, Input);
+    logged_service!(Audio, Physics, Input);
+    logged_service!(Animation, Physics);
+    logged_service!(CycleA, CycleB);
+    logged_service!(CycleB, CycleA);
+
+    fn frame() -> FrameContext {
+        FrameContext {
+            delta: std::time::Duration::from_millis(16),
+            total_time: std::time::Duration::from_millis(16),
+            frame_index: 0,
+            alpha: 0.0,
+        }
+    }
+
+    #[test]
+    fn services_run_after_their_dependencies() {
+        let log = Log::default();
+        let mut services = ServiceManager::new();
+        services.add(Audio(log.clone()));
+        services.add(Animation(log.clone()));
+        services.add(Physics(log.clone()));
+        services.add(Input(log.clone()));
+
+        task::block_on(async {
+            services.init_services().await.unwrap();
+            services.update_services(&frame()).await;
+            services.shutdown_services().await;
+        });
+
+        assert_eq!(*log.lock().unwrap(), vec![
+            "init Input", "init Physics", "init Audio", "init Animation",
+            "update Input", "update Physics", "update Audio", "update Animation",
+            "shutdown Animation", "shutdown Audio", "shutdown Physics", "shutdown Input",
+        ]);
+    }
+
+    #[test]
+    fn dependency_cycles_are_an_error() {
+        let log = Log::default();
+        let mut services = ServiceManager::new();
+        services.add(Input(log.clone()));
+        services.add(CycleA(log.clone()));
+        services.add(CycleB(log.clone()));
+
+        let error = task::block_on(services.init_services()).unwrap_err().to_string();
+
+        assert!(error.starts_with("Service dependencies form a cycle between: "), "{}", error);
+        assert!(error.contains("CycleA") && error.contains("CycleB") && !error.contains("Input"), "{}", error);
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_dependencies_are_an_error() {
+        let log = Log::default();
+        let mut services = ServiceManager::new();
+        services.add(Physics(log.clone()));
+
+        let error = task::block_on(services.init_services()).unwrap_err().to_string();
+
+        assert_eq!(error, format!(
+            "Service {} depends on {}, which has not been added",
+            type_name::<Physics>(), type_name::<Input>()));
+        assert!(log.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn services_are_found_by_type_and_alias() {
+        let log = Log::default();
+        let mut services = ServiceManager::new();
+        let input = services.add_service("Input", Input(log.clone()));
+        services.alias::<Input>("Keyboard");
+
+        let same_lock = |service: Arc<RwLock<SendSyncService>>| {
+            Arc::as_ptr(&service) as *const () == Arc::as_ptr(&input) as *const ()
+        };
+        assert!(Arc::ptr_eq(&services.get::<Input>().unwrap(), &input));
+        assert!(same_lock(services.get_service("Input").unwrap()));
+        assert!(same_lock(services.get_service("Keyboard").unwrap()));
+        assert!(services.get::<Physics>().is_none());
+        assert!(services.get_service("Physics").is_none());
+
+        assert!(services.remove::<Input>());
+        assert!(services.get::<Input>().is_none());
+        assert!(services.get_service("Input").is_none());
+        assert!(services.get_service("Keyboard").is_none());
+    }
+}
@@ -0,0 +1,103 @@
+//! `#[serde(with = "...")]` adapters for the SDL and std types used in binding files and
+//! input recordings.
+
+use serde::{Deserialize, Deserializer, Serializer};
+use serde::de::Error;
+use sdl2::controller::{Axis, Button};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use std::time::Duration;
+
+/// Serializes a `Duration` as whole milliseconds, which is what people write by hand.
+pub mod duration_millis {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_millis(u64::deserialize(deserializer)?))
+    }
+}
+
+/// Serializes a `Duration` as whole microseconds, for frame times.
+pub mod duration_micros {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_micros() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_micros(u64::deserialize(deserializer)?))
+    }
+}
+
+/// Serializes a controller `Axis` with SDL's mapping names, e.g. `"leftx"`.
+pub mod gamepad_axis {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(axis: &Axis, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&axis.string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Axis, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Axis::from_string(&name).ok_or_else(|| D::Error::custom(format!("Unknown gamepad axis: {}", name)))
+    }
+}
+
+/// Serializes a controller `Button` with SDL's mapping names, e.g. `"a"`.
+pub mod gamepad_button {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(button: &Button, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&button.string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Button, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Button::from_string(&name).ok_or_else(|| D::Error::custom(format!("Unknown gamepad button: {}", name)))
+    }
+}
+
+/// Serializes a `Keycode` as its raw SDL value.
+pub mod keycode {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(keycode: &Keycode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(keycode.into_i32())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Keycode, D::Error> {
+        let raw = i32::deserialize(deserializer)?;
+        Keycode::from_i32(raw).ok_or_else(|| D::Error::custom(format!("Unknown keycode: {}", raw)))
+    }
+}
+
+/// Serializes a `Mod` as its raw SDL bits.
+pub mod keymod {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(keymod: &Mod, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(keymod.bits())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Mod, D::Error> {
+        Ok(Mod::from_bits_truncate(u16::deserialize(deserializer)?))
+    }
+}
+
+/// Serializes a `MouseButton` as its raw SDL value.
+pub mod mouse_button {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(button: &MouseButton, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(*button as u8)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MouseButton, D::Error> {
+        Ok(MouseButton::from_ll(u8::deserialize(deserializer)?))
+    }
+}
@@ -0,0 +1,153 @@
+use nalgebra_glm as glm;
+use nalgebra_glm::{Mat4, Quat, Vec3};
+
+/// Translation, rotation and scale relative to a parent (or to the world for roots).
+/// Applied as scale first, then rotation, then translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::new(0.0, 0.0, 0.0),
+        rotation: Quat::new(1.0, 0.0, 0.0, 0.0),
+        scale: Vec3::new(1.0, 1.0, 1.0),
+    };
+
+    pub fn new(translation: Vec3, rotation: Quat, scale: Vec3) -> Transform {
+        Self { translation, rotation, scale }
+    }
+
+    pub fn from_translation(translation: Vec3) -> Transform {
+        Self { translation, ..Self::IDENTITY }
+    }
+
+    pub fn from_rotation(rotation: Quat) -> Transform {
+        Self { rotation, ..Self::IDENTITY }
+    }
+
+    pub fn from_scale(scale: Vec3) -> Transform {
+        Self { scale, ..Self::IDENTITY }
+    }
+
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Quat) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        glm::translation(&self.translation) * glm::quat_to_mat4(&self.rotation) * glm::scaling(&self.scale)
+    }
+
+    /// Split an affine matrix back into translation, rotation and scale. Shear is lost.
+    pub fn from_matrix(matrix: &Mat4) -> Transform {
+        let translation = glm::vec3(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]);
+
+        let x_axis = glm::vec3(matrix[(0, 0)], matrix[(1, 0)], matrix[(2, 0)]);
+        let y_axis = glm::vec3(matrix[(0, 1)], matrix[(1, 1)], matrix[(2, 1)]);
+        let z_axis = glm::vec3(matrix[(0, 2)], matrix[(1, 2)], matrix[(2, 2)]);
+        let mut scale = glm::vec3(glm::length(&x_axis), glm::length(&y_axis), glm::length(&z_axis));
+
+        // A negative determinant means one axis is mirrored; put the flip in the scale.
+        if glm::determinant(&glm::mat4_to_mat3(matrix)) < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        let rotation_matrix = glm::mat3(
+            x_axis.x / scale.x, y_axis.x / scale.y, z_axis.x / scale.z,
+            x_axis.y / scale.x, y_axis.y / scale.y, z_axis.y / scale.z,
+            x_axis.z / scale.x, y_axis.z / scale.y, z_axis.z / scale.z,
+        );
+
+        Self {
+            translation,
+            rotation: glm::mat3_to_quat(&rotation_matrix),
+            scale,
+        }
+    }
+
+    pub fn translate(&mut self, offset: &Vec3) {
+        self.translation += offset;
+    }
+
+    /// Rotate by `rotation` on top of the current rotation, in the parent's space.
+    pub fn rotate(&mut self, rotation: &Quat) {
+        self.rotation = glm::quat_normalize(&(rotation * self.rotation));
+    }
+
+    /// Rotate `angle` radians around `axis`, in the parent's space.
+    pub fn rotate_axis(&mut self, angle: f32, axis: &Vec3) {
+        self.rotate(&glm::quat_angle_axis(angle, &glm::normalize(axis)));
+    }
+
+    /// The direction the transform faces. By OpenGL convention that's local -Z.
+    pub fn forward(&self) -> Vec3 {
+        glm::quat_rotate_vec3(&self.rotation, &glm::vec3(0.0, 0.0, -1.0))
+    }
+
+    pub fn right(&self) -> Vec3 {
+        glm::quat_rotate_vec3(&self.rotation, &glm::vec3(1.0, 0.0, 0.0))
+    }
+
+    pub fn up(&self) -> Vec3 {
+        glm::quat_rotate_vec3(&self.rotation, &glm::vec3(0.0, 1.0, 0.0))
+    }
+
+    /// Turn so that `forward` points at `target`, both in the parent's space.
+    pub fn look_at(&mut self, target: &Vec3, up: &Vec3) {
+        let direction = target - self.translation;
+        if glm::length2(&direction) > f32::EPSILON {
+            self.rotation = look_rotation(&direction, up);
+        }
+    }
+
+    pub fn transform_point(&self, point: &Vec3) -> Vec3 {
+        glm::quat_rotate_vec3(&self.rotation, &point.component_mul(&self.scale)) + self.translation
+    }
+
+    pub fn transform_vector(&self, vector: &Vec3) -> Vec3 {
+        glm::quat_rotate_vec3(&self.rotation, &vector.component_mul(&self.scale))
+    }
+}
+
+/// The rotation whose forward (-Z) points along `direction`.
+pub fn look_rotation(direction: &Vec3, up: &Vec3) -> Quat {
+    let forward = glm::normalize(direction);
+
+    // Fall back to another up axis when looking straight along it.
+    let up = if glm::length2(&glm::cross(&forward, up)) < 1e-6 {
+        glm::vec3(0.0, 0.0, 1.0)
+    } else {
+        *up
+    };
+
+    let right = glm::normalize(&glm::cross(&forward, &up));
+    let corrected_up = glm::cross(&right, &forward);
+
+    let rotation_matrix = glm::mat3(
+        right.x, corrected_up.x, -forward.x,
+        right.y, corrected_up.y, -forward.y,
+        right.z, corrected_up.z, -forward.z,
+    );
+
+    glm::mat3_to_quat(&rotation_matrix)
+}
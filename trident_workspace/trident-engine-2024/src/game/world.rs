@@ -0,0 +1,373 @@
+use crate::game::services::{OwnerId, Service, ServiceManager};
+use crate::game::{GameObject, GameObjectRegistry};
+use crate::game_loop::FrameContext;
+use async_std::sync::RwLock;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+
+/// Stable handle to a `GameObject` in a `World`. Never reused within one world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GameObjectId(u64);
+
+impl Display for GameObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// What a `GameObject` gets to see of the world while one of its lifecycle methods runs.
+/// Spawns and despawns requested here are applied once the current pass over the objects is done.
+pub struct WorldContext<'a> {
+    object_id: GameObjectId,
+    next_id: &'a mut u64,
+    services: &'a Arc<RwLock<ServiceManager>>,
+    pending_spawns: &'a mut Vec<(GameObjectId, Box<dyn GameObject>)>,
+    pending_despawns: &'a mut Vec<GameObjectId>,
+}
+
+impl WorldContext<'_> {
+    /// The object whose method is currently running.
+    pub fn object_id(&self) -> GameObjectId {
+        self.object_id
+    }
+
+    pub fn spawn(&mut self, object: Box<dyn GameObject>) -> GameObjectId {
+        let id = GameObjectId(*self.next_id);
+        *self.next_id += 1;
+        self.pending_spawns.push((id, object));
+        id
+    }
+
+    pub fn despawn(&mut self, id: GameObjectId) {
+        if !self.pending_despawns.contains(&id) {
+            self.pending_despawns.push(id);
+        }
+    }
+
+    /// Despawn the object whose method is currently running.
+    pub fn despawn_self(&mut self) {
+        self.despawn(self.object_id);
+    }
+
+    pub fn services(&self) -> Arc<RwLock<ServiceManager>> {
+        self.services.clone()
+    }
+
+    /// Look up a service without waiting for the `ServiceManager` lock. Returns `None` while the
+    /// manager is locked for writing, e.g. by a service being added from this same thread, where
+    /// waiting would deadlock. Services are all in place before the game loop starts, so this only
+    /// matters for objects updated from inside a `ServiceManager::write` guard.
+    pub fn service<T: Service + Send + Sync + 'static>(&self) -> Option<Arc<RwLock<T>>> {
+        self.services.try_read()?.get::<T>()
+    }
+}
+
+struct WorldObject {
+    id: GameObjectId,
+    object: Box<dyn GameObject>,
+    started: bool,
+}
+
+/// Owns a scene's game objects and drives their lifecycle:
+/// `start` before the first update, then `update` and `late_update` every frame, and `on_destroy`
+/// when despawned. Objects spawned or despawned mid-frame take effect at the end of the pass.
+pub struct World {
+    objects: Vec<WorldObject>,
+    next_id: u64,
+    services: Arc<RwLock<ServiceManager>>,
+    pending_spawns: Vec<(GameObjectId, Box<dyn GameObject>)>,
+    pending_despawns: Vec<GameObjectId>,
+    /// Who registered the type of each object spawned with `spawn_named`, if anyone.
+    owners: HashMap<GameObjectId, OwnerId>,
+}
+
+impl World {
+    pub fn new(services: Arc<RwLock<ServiceManager>>) -> World {
+        Self {
+            objects: Vec::new(),
+            next_id: 0,
+            services,
+            pending_spawns: Vec::new(),
+            pending_despawns: Vec::new(),
+            owners: HashMap::new(),
+        }
+    }
+
+    /// Queue `object` to join the world. Its `start` runs at the beginning of the next update.
+    pub fn spawn(&mut self, object: Box<dyn GameObject>) -> GameObjectId {
+        let id = GameObjectId(self.next_id);
+        self.next_id += 1;
+        self.pending_spawns.push((id, object));
+        id
+    }
+
+    /// Spawn a `GameObject` type registered under `type_name`, e.g. by a plugin.
+    pub fn spawn_named(&mut self, registry: &GameObjectRegistry, type_name: &str) -> Option<GameObjectId> {
+        let id = self.spawn(registry.create(type_name)?);
+        if let Some(owner) = registry.owner(type_name) {
+            self.owners.insert(id, owner);
+        }
+
+        Some(id)
+    }
+
+    /// Despawn everything spawned from types `owner` registered, running `on_destroy` right away
+    /// rather than at the end of the next update. Call before unloading the code behind them.
+    pub fn despawn_owned(&mut self, owner: OwnerId) {
+        let owned: Vec<GameObjectId> = self.owners
+            .iter()
+            .filter(|(_, object_owner)| **object_owner == owner)
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in owned {
+            self.despawn(id);
+        }
+        self.apply_despawns();
+    }
+
+    /// Queue the object for removal. Its `on_destroy` runs at the end of the next update.
+    pub fn despawn(&mut self, id: GameObjectId) {
+        if !self.pending_despawns.contains(&id) {
+            self.pending_despawns.push(id);
+        }
+    }
+
+    /// Whether `id` is in the world or queued to join it, and not queued for despawn.
+    pub fn is_alive(&self, id: GameObjectId) -> bool {
+        if self.pending_despawns.contains(&id) {
+            return false;
+        }
+
+        self.objects.iter().any(|world_object| world_object.id == id)
+            || self.pending_spawns.iter().any(|(pending_id, _)| *pending_id == id)
+    }
+
+    pub fn get(&self, id: GameObjectId) -> Option<&dyn GameObject> {
+        self.objects
+            .iter()
+            .find(|world_object| world_object.id == id)
+            .map(|world_object| world_object.object.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: GameObjectId) -> Option<&mut (dyn GameObject + 'static)> {
+        self.objects
+            .iter_mut()
+            .find(|world_object| world_object.id == id)
+            .map(|world_object| world_object.object.as_mut())
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (GameObjectId, &dyn GameObject)> {
+        self.objects
+            .iter()
+            .map(|world_object| (world_object.id, world_object.object.as_ref()))
+    }
+
+    /// Run one frame: start new objects, `update` everything, `late_update` everything,
+    /// then apply despawns.
+    pub fn update(&mut self, frame: &FrameContext) {
+        let dt = frame.delta_secs();
+
+        self.apply_spawns();
+
+        for index in 0..self.objects.len() {
+            let (object, mut context) = self.split_for(index);
+            object.update(dt, &mut context);
+        }
+
+        for index in 0..self.objects.len() {
+            let (object, mut context) = self.split_for(index);
+            object.late_update(dt, &mut context);
+        }
+
+        self.apply_despawns();
+    }
+
+    /// Despawn every object, running `on_destroy` on each.
+    pub fn clear(&mut self) {
+        self.pending_spawns.clear();
+        self.pending_despawns = self.objects.iter().map(|world_object| world_object.id).collect();
+        self.apply_despawns();
+    }
+
+    fn split_for(&mut self, index: usize) -> (&mut dyn GameObject, WorldContext<'_>) {
+        let world_object = &mut self.objects[index];
+
+        let context = WorldContext {
+            object_id: world_object.id,
+            next_id: &mut self.next_id,
+            services: &self.services,
+            pending_spawns: &mut self.pending_spawns,
+            pending_despawns: &mut self.pending_despawns,
+        };
+
+        (world_object.object.as_mut(), context)
+    }
+
+    /// Move pending spawns into the world and start them. Objects spawned from `start` are
+    /// started in the same call.
+    fn apply_spawns(&mut self) {
+        while !self.pending_spawns.is_empty() {
+            let first_new = self.objects.len();
+
+            for (id, object) in self.pending_spawns.drain(..) {
+                self.objects.push(WorldObject { id, object, started: false });
+            }
+
+            for index in first_new..self.objects.len() {
+                if self.objects[index].started {
+                    continue;
+                }
+
+                self.objects[index].started = true;
+                let (object, mut context) = self.split_for(index);
+                object.start(&mut context);
+            }
+        }
+    }
+
+    /// Remove despawned objects, running `on_destroy` first. Objects despawned from `on_destroy`
+    /// are removed in the same call.
+    fn apply_despawns(&mut self) {
+        while !self.pending_despawns.is_empty() {
+            let despawned = std::mem::take(&mut self.pending_despawns);
+
+            for id in despawned {
+                // Spawned and despawned in the same frame: never started, so never destroyed.
+                self.pending_spawns.retain(|(pending_id, _)| *pending_id != id);
+                self.owners.remove(&id);
+
+                let Some(index) = self.objects.iter().position(|world_object| world_object.id == id) else {
+                    continue;
+                };
+
+                let (object, mut context) = self.split_for(index);
+                object.on_destroy(&mut context);
+                self.objects.remove(index);
+            }
+        }
+    }
+}
+
+impl Drop for World {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::services::random::RandomService;
+    use async_std::task;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts how often objects sharing the counter are destroyed.
+    struct Counted(Arc<AtomicUsize>);
+
+    impl GameObject for Counted {
+        fn update(&mut self, _dt: f32, _world: &mut WorldContext) {}
+
+        fn on_destroy(&mut self, _world: &mut WorldContext) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    struct Idle;
+
+    impl GameObject for Idle {
+        fn update(&mut self, _dt: f32, _world: &mut WorldContext) {}
+    }
+
+    fn frame() -> FrameContext {
+        FrameContext {
+            delta: std::time::Duration::from_millis(16),
+            total_time: std::time::Duration::ZERO,
+            frame_index: 0,
+            alpha: 0.0,
+        }
+    }
+
+    #[test]
+    fn despawn_owned_only_removes_objects_of_that_owner() {
+        let owner = OwnerId::unique();
+        let destroyed = Arc::new(AtomicUsize::new(0));
+        let (owned_destroyed, unowned_destroyed) = (destroyed.clone(), destroyed.clone());
+        let mut registry = GameObjectRegistry::new();
+        registry.register_owned(owner, "Owned", move || Box::new(Counted(owned_destroyed.clone())));
+        registry.register("Unowned", move || Box::new(Counted(unowned_destroyed.clone())));
+
+        let mut world = World::new(ServiceManager::new_shared());
+        let owned = world.spawn_named(&registry, "Owned").unwrap();
+        let unowned = world.spawn_named(&registry, "Unowned").unwrap();
+        world.update(&frame());
+
+        world.despawn_owned(owner);
+
+        assert!(!world.is_alive(owned));
+        assert!(world.is_alive(unowned));
+        assert_eq!(destroyed.load(Ordering::SeqCst), 1);
+
+        assert_eq!(registry.unregister_owner(owner), 1);
+        assert!(registry.create("Owned").is_none());
+        assert!(registry.create("Unowned").is_some());
+    }
+
+    #[test]
+    fn queued_despawns_are_not_alive() {
+        let mut world = World::new(ServiceManager::new_shared());
+        let spawned = world.spawn(Box::new(Idle));
+        let started = world.spawn(Box::new(Idle));
+        world.update(&frame());
+
+        let queued = world.spawn(Box::new(Idle));
+        assert!(world.is_alive(queued));
+
+        world.despawn(started);
+        world.despawn(queued);
+        assert!(world.is_alive(spawned));
+        assert!(!world.is_alive(started));
+        assert!(!world.is_alive(queued));
+
+        world.update(&frame());
+        assert!(!world.is_alive(started));
+        assert!(!world.is_alive(queued));
+    }
+
+    struct FindsRandom {
+        found: Arc<std::sync::Mutex<Vec<bool>>>,
+    }
+
+    impl GameObject for FindsRandom {
+        fn update(&mut self, _dt: f32, world: &mut WorldContext) {
+            let found = world.service::<RandomService>().is_some();
+            self.found.lock().unwrap().push(found);
+        }
+    }
+
+    #[test]
+    fn service_does_not_wait_for_a_write_lock() {
+        let services = ServiceManager::new_shared();
+        task::block_on(services.write()).add_service("RandomService", RandomService::new(1));
+
+        let found = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut world = World::new(services.clone());
+        world.spawn(Box::new(FindsRandom { found: found.clone() }));
+        world.update(&frame());
+
+        let guard = task::block_on(services.write());
+        world.update(&frame());
+        drop(guard);
+
+        assert_eq!(*found.lock().unwrap(), [true, false]);
+    }
+}
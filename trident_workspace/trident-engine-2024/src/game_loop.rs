@@ -0,0 +1,163 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+use sdl2::video::Window;
+
+/// Timing information handed to `GameLoop::update` and `GameLoop::render`.
+/// During `update` the delta is always the fixed timestep, during `render` it is the real frame time.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameContext {
+    pub delta: Duration,
+    pub total_time: Duration,
+    pub frame_index: u64,
+    /// How far the current frame is between the last fixed update and the next one, in `[0, 1)`.
+    /// Renderers use it to interpolate between the previous and current simulation state.
+    pub alpha: f32,
+}
+
+impl FrameContext {
+    pub fn delta_secs(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn total_secs(&self) -> f64 {
+        self.total_time.as_secs_f64()
+    }
+}
+
+pub trait GameLoop {
+    fn update(&mut self, _frame: &FrameContext) {}
+    fn render(&mut self, frame: &FrameContext, window: &mut Window);
+}
+
+/// Fixed-timestep accumulator driving `Application::run`.
+pub struct FrameClock {
+    fixed_timestep: Duration,
+    max_frame_time: Duration,
+    target_frame_time: Option<Duration>,
+    accumulator: Duration,
+    total_time: Duration,
+    frame_index: u64,
+    last_frame: Instant,
+    frame_start: Instant,
+    frame_time: Duration,
+}
+
+/// The length of one fixed update at `fixed_update_rate` Hz. Fails unless the rate is a positive,
+/// finite number low enough for a timestep of at least a nanosecond.
+pub fn fixed_timestep(fixed_update_rate: f64) -> Result<Duration, Box<dyn Error>> {
+    if !(fixed_update_rate.is_finite() && fixed_update_rate > 0.0) {
+        return Err(format!("The fixed update rate must be a positive number of Hz, got {}", fixed_update_rate).into());
+    }
+
+    let timestep = Duration::try_from_secs_f64(1.0 / fixed_update_rate)?;
+    if timestep.is_zero() {
+        return Err(format!("The fixed update rate of {} Hz is too high for a timestep of at least 1ns", fixed_update_rate).into());
+    }
+
+    Ok(timestep)
+}
+
+/// `max_frame_time` seconds as a `Duration`. Fails unless it's a finite number of at least 0.
+pub fn max_frame_duration(max_frame_time: f64) -> Result<Duration, Box<dyn Error>> {
+    Duration::try_from_secs_f64(max_frame_time)
+        .map_err(|_| format!("The max frame time must be a finite number of seconds of at least 0, got {}", max_frame_time).into())
+}
+
+impl FrameClock {
+    pub fn new(fixed_update_rate: f64, max_frame_time: f64, target_fps: Option<u32>) -> Result<Self, Box<dyn Error>> {
+        let now = Instant::now();
+
+        Ok(Self {
+            fixed_timestep: fixed_timestep(fixed_update_rate)?,
+            max_frame_time: max_frame_duration(max_frame_time)?,
+            target_frame_time: target_fps
+                .filter(|fps| *fps > 0)
+                .map(|fps| Duration::from_secs_f64(1.0 / fps as f64)),
+            accumulator: Duration::ZERO,
+            total_time: Duration::ZERO,
+            frame_index: 0,
+            last_frame: now,
+            frame_start: now,
+            frame_time: Duration::ZERO,
+        })
+    }
+
+    /// Start a new frame and add the elapsed time to the accumulator.
+    /// The elapsed time is clamped to `max_frame_time` so a long stall (debugger, window drag)
+    /// can't make the loop spend the next frames catching up forever.
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        self.frame_time = (now - self.last_frame).min(self.max_frame_time);
+        self.last_frame = now;
+        self.frame_start = now;
+        self.accumulator += self.frame_time;
+    }
+
+    /// Consume one fixed timestep from the accumulator, if there is enough time left in it.
+    pub fn next_update(&mut self) -> Option<FrameContext> {
+        if self.accumulator < self.fixed_timestep {
+            return None;
+        }
+
+        self.accumulator -= self.fixed_timestep;
+        self.total_time += self.fixed_timestep;
+
+        Some(FrameContext {
+            delta: self.fixed_timestep,
+            total_time: self.total_time,
+            frame_index: self.frame_index,
+            alpha: 0.0,
+        })
+    }
+
+    pub fn render_context(&self) -> FrameContext {
+        FrameContext {
+            delta: self.frame_time,
+            total_time: self.total_time,
+            frame_index: self.frame_index,
+            alpha: self.accumulator.as_secs_f32() / self.fixed_timestep.as_secs_f32(),
+        }
+    }
+
+    /// Finish the frame, sleeping for the rest of it when a target FPS is set.
+    pub fn end_frame(&mut self) {
+        self.frame_index += 1;
+
+        if let Some(target_frame_time) = self.target_frame_time {
+            let elapsed = self.frame_start.elapsed();
+            if elapsed < target_frame_time {
+                std::thread::sleep(target_frame_time - elapsed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_clock_rejects_invalid_rates() {
+        assert!(FrameClock::new(60.0, 0.25, Some(60)).is_ok());
+        assert!(FrameClock::new(60.0, 0.0, None).is_ok());
+
+        for rate in [0.0, -60.0, f64::NAN, f64::INFINITY, 1e-300, 1e10] {
+            assert!(FrameClock::new(rate, 0.25, None).is_err(), "fixed update rate {}", rate);
+        }
+
+        for max_frame_time in [-0.25, f64::NAN, f64::INFINITY] {
+            assert!(FrameClock::new(60.0, max_frame_time, None).is_err(), "max frame time {}", max_frame_time);
+        }
+    }
+
+    #[test]
+    fn next_update_consumes_whole_timesteps() {
+        let mut clock = FrameClock::new(10.0, 1.0, None).unwrap();
+        clock.accumulator = Duration::from_millis(250);
+
+        assert_eq!(clock.next_update().unwrap().delta, Duration::from_millis(100));
+        assert!(clock.next_update().is_some());
+        assert!(clock.next_update().is_none());
+        assert!((clock.render_context().alpha - 0.5).abs() < 1e-4);
+    }
+}
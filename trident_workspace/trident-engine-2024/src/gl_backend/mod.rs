@@ -0,0 +1,96 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use gl::types::{GLbitfield, GLboolean, GLenum, GLsizei};
+use crate::shader_management::UniformValue;
+
+pub mod native;
+#[cfg(any(test, feature = "gl-recording"))]
+pub mod recording;
+
+use native::NativeGl;
+
+/// The OpenGL calls the resource wrappers (`BufferObject`, `VertexArrayObject`, `Shader`,
+/// `ShaderProgram` and `Texture`) go through. `NativeGl` forwards to the driver; `RecordingGl`
+/// records the calls and simulates object names and errors so the wrappers can be tested
+/// without a GL context. `RecordingGl` is only built for this crate's tests and with the
+/// `gl-recording` feature, for tests in crates that use the engine.
+///
+/// Calls that take or return arrays of names here work on one object at a time, since that's
+/// all the wrappers need.
+pub trait GlBackend {
+    fn get_error(&self) -> GLenum;
+    fn clear(&self, mask: GLbitfield);
+
+    fn gen_buffer(&self) -> u32;
+    fn bind_buffer(&self, target: GLenum, buffer: u32);
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum);
+    fn delete_buffer(&self, buffer: u32);
+
+    fn gen_vertex_array(&self) -> u32;
+    fn bind_vertex_array(&self, array: u32);
+    fn delete_vertex_array(&self, array: u32);
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, data_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize);
+    fn enable_vertex_attrib_array(&self, index: u32);
+    /// Draw `count` indices from the bound element array buffer, starting `offset` bytes in.
+    fn draw_elements(&self, mode: GLenum, count: i32, index_type: GLenum, offset: usize);
+
+    /// Returns 0 on failure, like `glCreateShader`.
+    fn create_shader(&self, shader_type: GLenum) -> u32;
+    fn shader_source(&self, shader: u32, source: &str);
+    fn compile_shader(&self, shader: u32);
+    fn get_shader_iv(&self, shader: u32, parameter: GLenum) -> i32;
+    fn get_shader_info_log(&self, shader: u32) -> String;
+    fn delete_shader(&self, shader: u32);
+
+    /// Returns 0 on failure, like `glCreateProgram`.
+    fn create_program(&self) -> u32;
+    fn attach_shader(&self, program: u32, shader: u32);
+    fn detach_shader(&self, program: u32, shader: u32);
+    fn link_program(&self, program: u32);
+    fn get_program_iv(&self, program: u32, parameter: GLenum) -> i32;
+    fn get_program_info_log(&self, program: u32) -> String;
+    fn use_program(&self, program: u32);
+    fn delete_program(&self, program: u32);
+    fn get_uniform_location(&self, program: u32, name: &str) -> i32;
+    fn uniform(&self, location: i32, value: &UniformValue);
+
+    fn gen_texture(&self) -> u32;
+    fn active_texture(&self, unit: GLenum);
+    fn bind_texture(&self, target: GLenum, texture: u32);
+    fn tex_parameter_i(&self, target: GLenum, parameter: GLenum, value: i32);
+    #[allow(clippy::too_many_arguments)]
+    fn tex_image_2d(&self, target: GLenum, level: i32, internal_format: i32, width: i32, height: i32, format: GLenum, data_type: GLenum, pixels: &[u8]);
+    fn generate_mipmap(&self, target: GLenum);
+    fn delete_texture(&self, texture: u32);
+}
+
+thread_local! {
+    // GL contexts are bound to a thread, so the backend is too.
+    static CURRENT: RefCell<Rc<dyn GlBackend>> = RefCell::new(Rc::new(NativeGl));
+}
+
+/// The backend GL calls on this thread go through. `NativeGl` unless replaced.
+pub fn current() -> Rc<dyn GlBackend> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Route this thread's GL calls through `backend`. Returns the previous backend.
+pub fn set_backend(backend: Rc<dyn GlBackend>) -> Rc<dyn GlBackend> {
+    CURRENT.with(|current| current.replace(backend))
+}
+
+/// Run `f` with `backend` installed, putting the previous backend back afterwards even if `f` panics.
+pub fn with_backend<R>(backend: Rc<dyn GlBackend>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Rc<dyn GlBackend>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(previous) = self.0.take() {
+                set_backend(previous);
+            }
+        }
+    }
+
+    let _restore = Restore(Some(set_backend(backend)));
+    f()
+}
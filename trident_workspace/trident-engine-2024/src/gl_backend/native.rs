@@ -0,0 +1,199 @@
+use std::ffi::{c_void, CString};
+use std::ptr;
+use gl::types::{GLbitfield, GLboolean, GLchar, GLenum, GLsizei};
+use crate::gl_backend::GlBackend;
+use crate::shader_management::UniformValue;
+
+/// Calls straight into the OpenGL functions loaded by `gl::load_with`.
+pub struct NativeGl;
+
+fn info_log(log_length: i32, read: impl FnOnce(GLsizei, *mut GLsizei, *mut GLchar)) -> String {
+    let mut buffer = vec![0u8; log_length.max(1) as usize];
+    let mut written_len = 0;
+    read(buffer.len() as GLsizei, &mut written_len, buffer.as_mut_ptr() as *mut GLchar);
+
+    buffer.truncate(written_len.max(0) as usize);
+    String::from_utf8_lossy(&buffer).into_owned()
+}
+
+impl GlBackend for NativeGl {
+    fn get_error(&self) -> GLenum {
+        unsafe { gl::GetError() }
+    }
+
+    fn clear(&self, mask: GLbitfield) {
+        unsafe { gl::Clear(mask) };
+    }
+
+    fn gen_buffer(&self) -> u32 {
+        let mut id = 0;
+        unsafe { gl::GenBuffers(1, &mut id) };
+        id
+    }
+
+    fn bind_buffer(&self, target: GLenum, buffer: u32) {
+        unsafe { gl::BindBuffer(target, buffer) };
+    }
+
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum) {
+        unsafe { gl::BufferData(target, data.len() as isize, data.as_ptr() as *const c_void, usage) };
+    }
+
+    fn delete_buffer(&self, buffer: u32) {
+        unsafe { gl::DeleteBuffers(1, &buffer) };
+    }
+
+    fn gen_vertex_array(&self) -> u32 {
+        let mut id = 0;
+        unsafe { gl::GenVertexArrays(1, &mut id) };
+        id
+    }
+
+    fn bind_vertex_array(&self, array: u32) {
+        unsafe { gl::BindVertexArray(array) };
+    }
+
+    fn delete_vertex_array(&self, array: u32) {
+        unsafe { gl::DeleteVertexArrays(1, &array) };
+    }
+
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, data_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize) {
+        unsafe { gl::VertexAttribPointer(index, size, data_type, normalized, stride, offset as *const c_void) };
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        unsafe { gl::EnableVertexAttribArray(index) };
+    }
+
+    fn draw_elements(&self, mode: GLenum, count: i32, index_type: GLenum, offset: usize) {
+        unsafe { gl::DrawElements(mode, count, index_type, offset as *const c_void) };
+    }
+
+    fn create_shader(&self, shader_type: GLenum) -> u32 {
+        unsafe { gl::CreateShader(shader_type) }
+    }
+
+    fn shader_source(&self, shader: u32, source: &str) {
+        let source = CString::new(source.as_bytes()).unwrap_or_default();
+        unsafe { gl::ShaderSource(shader, 1, &source.as_ptr(), ptr::null()) };
+    }
+
+    fn compile_shader(&self, shader: u32) {
+        unsafe { gl::CompileShader(shader) };
+    }
+
+    fn get_shader_iv(&self, shader: u32, parameter: GLenum) -> i32 {
+        let mut value = 0;
+        unsafe { gl::GetShaderiv(shader, parameter, &mut value) };
+        value
+    }
+
+    fn get_shader_info_log(&self, shader: u32) -> String {
+        let log_length = self.get_shader_iv(shader, gl::INFO_LOG_LENGTH);
+        info_log(log_length, |length, written, buffer| unsafe {
+            gl::GetShaderInfoLog(shader, length, written, buffer)
+        })
+    }
+
+    fn delete_shader(&self, shader: u32) {
+        unsafe { gl::DeleteShader(shader) };
+    }
+
+    fn create_program(&self) -> u32 {
+        unsafe { gl::CreateProgram() }
+    }
+
+    fn attach_shader(&self, program: u32, shader: u32) {
+        unsafe { gl::AttachShader(program, shader) };
+    }
+
+    fn detach_shader(&self, program: u32, shader: u32) {
+        unsafe { gl::DetachShader(program, shader) };
+    }
+
+    fn link_program(&self, program: u32) {
+        unsafe { gl::LinkProgram(program) };
+    }
+
+    fn get_program_iv(&self, program: u32, parameter: GLenum) -> i32 {
+        let mut value = 0;
+        unsafe { gl::GetProgramiv(program, parameter, &mut value) };
+        value
+    }
+
+    fn get_program_info_log(&self, program: u32) -> String {
+        let log_length = self.get_program_iv(program, gl::INFO_LOG_LENGTH);
+        info_log(log_length, |length, written, buffer| unsafe {
+            gl::GetProgramInfoLog(program, length, written, buffer)
+        })
+    }
+
+    fn use_program(&self, program: u32) {
+        unsafe { gl::UseProgram(program) };
+    }
+
+    fn delete_program(&self, program: u32) {
+        unsafe { gl::DeleteProgram(program) };
+    }
+
+    fn get_uniform_location(&self, program: u32, name: &str) -> i32 {
+        let Ok(name) = CString::new(name.as_bytes()) else {
+            return -1;
+        };
+        unsafe { gl::GetUniformLocation(program, name.as_ptr()) }
+    }
+
+    fn uniform(&self, location: i32, value: &UniformValue) {
+        unsafe {
+            match value {
+                UniformValue::Int(value) => gl::Uniform1i(location, *value),
+                UniformValue::Float(value) => gl::Uniform1f(location, *value),
+                UniformValue::Vec2(vector) => gl::Uniform2f(location, vector.x, vector.y),
+                UniformValue::Vec3(vector) => gl::Uniform3f(location, vector.x, vector.y, vector.z),
+                UniformValue::Vec4(vector) => gl::Uniform4f(location, vector.x, vector.y, vector.z, vector.w),
+                UniformValue::Mat4(matrix) => gl::UniformMatrix4fv(location, 1, gl::FALSE, matrix.as_ptr()),
+            }
+        }
+    }
+
+    fn gen_texture(&self) -> u32 {
+        let mut id = 0;
+        unsafe { gl::GenTextures(1, &mut id) };
+        id
+    }
+
+    fn active_texture(&self, unit: GLenum) {
+        unsafe { gl::ActiveTexture(unit) };
+    }
+
+    fn bind_texture(&self, target: GLenum, texture: u32) {
+        unsafe { gl::BindTexture(target, texture) };
+    }
+
+    fn tex_parameter_i(&self, target: GLenum, parameter: GLenum, value: i32) {
+        unsafe { gl::TexParameteri(target, parameter, value) };
+    }
+
+    fn tex_image_2d(&self, target: GLenum, level: i32, internal_format: i32, width: i32, height: i32, format: GLenum, data_type: GLenum, pixels: &[u8]) {
+        unsafe {
+            gl::TexImage2D(
+                target,
+                level,
+                internal_format,
+                width,
+                height,
+                0,
+                format,
+                data_type,
+                pixels.as_ptr() as *const c_void);
+        }
+    }
+
+    fn generate_mipmap(&self, target: GLenum) {
+        unsafe { gl::GenerateMipmap(target) };
+    }
+
+    fn delete_texture(&self, texture: u32) {
+        unsafe { gl::DeleteTextures(1, &texture) };
+    }
+}
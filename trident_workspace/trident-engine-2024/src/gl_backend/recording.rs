@@ -0,0 +1,403 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::rc::Rc;
+use gl::types::{GLbitfield, GLboolean, GLenum, GLsizei};
+use crate::gl_backend::{set_backend, GlBackend};
+use crate::shader_management::UniformValue;
+
+/// A state-changing call made through `RecordingGl`. Queries like `get_error` aren't recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlCall {
+    Clear(GLbitfield),
+    GenBuffer(u32),
+    BindBuffer { target: GLenum, buffer: u32 },
+    BufferData { target: GLenum, size: usize, usage: GLenum },
+    DeleteBuffer(u32),
+    GenVertexArray(u32),
+    BindVertexArray(u32),
+    DeleteVertexArray(u32),
+    VertexAttribPointer { index: u32, size: i32, data_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize },
+    EnableVertexAttribArray(u32),
+    DrawElements { mode: GLenum, count: i32, index_type: GLenum, offset: usize },
+    CreateShader { shader_type: GLenum, shader: u32 },
+    ShaderSource { shader: u32, source: String },
+    CompileShader(u32),
+    DeleteShader(u32),
+    CreateProgram(u32),
+    AttachShader { program: u32, shader: u32 },
+    DetachShader { program: u32, shader: u32 },
+    LinkProgram(u32),
+    UseProgram(u32),
+    DeleteProgram(u32),
+    Uniform { location: i32, value: UniformValue },
+    GenTexture(u32),
+    ActiveTexture(GLenum),
+    BindTexture { target: GLenum, texture: u32 },
+    TexParameter { target: GLenum, parameter: GLenum, value: i32 },
+    TexImage2D { target: GLenum, level: i32, internal_format: i32, width: i32, height: i32, format: GLenum, data_type: GLenum, size: usize },
+    GenerateMipmap(GLenum),
+    DeleteTexture(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GlObjectKind {
+    Buffer,
+    VertexArray,
+    Shader,
+    Program,
+    Texture,
+}
+
+#[derive(Default)]
+struct MockState {
+    calls: Vec<GlCall>,
+    last_name: u32,
+    live: HashMap<GlObjectKind, BTreeSet<u32>>,
+    errors: VecDeque<GLenum>,
+    fail_create_shader: bool,
+    fail_create_program: bool,
+    compile_error: Option<String>,
+    link_error: Option<String>,
+    compiled: HashMap<u32, Result<(), String>>,
+    linked: HashMap<u32, Result<(), String>>,
+    uniform_locations: HashMap<(u32, String), i32>,
+    missing_uniforms: BTreeSet<String>,
+}
+
+impl MockState {
+    fn create(&mut self, kind: GlObjectKind) -> u32 {
+        self.last_name += 1;
+        self.live.entry(kind).or_default().insert(self.last_name);
+        self.last_name
+    }
+
+    fn is_live(&self, kind: GlObjectKind, name: u32) -> bool {
+        self.live.get(&kind).is_some_and(|names| names.contains(&name))
+    }
+
+    /// Binding or using a name that was never created (or was deleted) is an error; 0 unbinds.
+    fn check_name(&mut self, kind: GlObjectKind, name: u32) {
+        if name != 0 && !self.is_live(kind, name) {
+            self.errors.push_back(gl::INVALID_OPERATION);
+        }
+    }
+
+    /// Deleting 0 or an unknown name is silently ignored, as in OpenGL.
+    fn delete(&mut self, kind: GlObjectKind, name: u32) {
+        if let Some(names) = self.live.get_mut(&kind) {
+            names.remove(&name);
+        }
+    }
+}
+
+/// A `GlBackend` for tests. It records every call, hands out object names starting at 1,
+/// tracks which objects are still alive, and can be told to fail in the ways a driver can.
+///
+/// ```ignore
+/// let gl = RecordingGl::install();
+/// let buffer = BufferObject::new(vec![1u32, 2, 3], BufferType::ElementArrayBuffer);
+/// drop(buffer);
+/// assert_eq!(gl.count(|call| matches!(call, GlCall::DeleteBuffer(_))), 1);
+/// ```
+#[derive(Default)]
+pub struct RecordingGl {
+    state: RefCell<MockState>,
+}
+
+impl RecordingGl {
+    pub fn new() -> Rc<RecordingGl> {
+        Rc::new(Self::default())
+    }
+
+    /// Create a mock and make it this thread's backend.
+    pub fn install() -> Rc<RecordingGl> {
+        let mock = Self::new();
+        set_backend(mock.clone());
+        mock
+    }
+
+    pub fn calls(&self) -> Vec<GlCall> {
+        self.state.borrow().calls.clone()
+    }
+
+    pub fn take_calls(&self) -> Vec<GlCall> {
+        std::mem::take(&mut self.state.borrow_mut().calls)
+    }
+
+    /// How many recorded calls match `predicate`.
+    pub fn count(&self, predicate: impl Fn(&GlCall) -> bool) -> usize {
+        self.state.borrow().calls.iter().filter(|call| predicate(call)).count()
+    }
+
+    pub fn is_live(&self, kind: GlObjectKind, name: u32) -> bool {
+        self.state.borrow().is_live(kind, name)
+    }
+
+    /// Names of the objects of `kind` that were created and not deleted yet, in ascending order.
+    pub fn live_objects(&self, kind: GlObjectKind) -> Vec<u32> {
+        self.state.borrow().live.get(&kind).map(|names| names.iter().copied().collect()).unwrap_or_default()
+    }
+
+    /// Make the next `get_error` calls return `error`, after any errors already queued.
+    pub fn push_error(&self, error: GLenum) {
+        self.state.borrow_mut().errors.push_back(error);
+    }
+
+    /// Make `create_shader` return 0.
+    pub fn fail_create_shader(&self, fail: bool) {
+        self.state.borrow_mut().fail_create_shader = fail;
+    }
+
+    /// Make `create_program` return 0.
+    pub fn fail_create_program(&self, fail: bool) {
+        self.state.borrow_mut().fail_create_program = fail;
+    }
+
+    /// Make compilation fail with `info_log` as the driver's message, or succeed again with `None`.
+    pub fn fail_compilation(&self, info_log: Option<&str>) {
+        self.state.borrow_mut().compile_error = info_log.map(str::to_string);
+    }
+
+    /// Make linking fail with `info_log` as the driver's message, or succeed again with `None`.
+    pub fn fail_linking(&self, info_log: Option<&str>) {
+        self.state.borrow_mut().link_error = info_log.map(str::to_string);
+    }
+
+    /// Make `get_uniform_location` return -1 for `name`, as if the shader didn't use it.
+    pub fn remove_uniform(&self, name: &str) {
+        self.state.borrow_mut().missing_uniforms.insert(name.to_string());
+    }
+
+    fn record(&self, call: GlCall) {
+        self.state.borrow_mut().calls.push(call);
+    }
+}
+
+impl GlBackend for RecordingGl {
+    fn get_error(&self) -> GLenum {
+        self.state.borrow_mut().errors.pop_front().unwrap_or(gl::NO_ERROR)
+    }
+
+    fn clear(&self, mask: GLbitfield) {
+        self.record(GlCall::Clear(mask));
+    }
+
+    fn gen_buffer(&self) -> u32 {
+        let buffer = self.state.borrow_mut().create(GlObjectKind::Buffer);
+        self.record(GlCall::GenBuffer(buffer));
+        buffer
+    }
+
+    fn bind_buffer(&self, target: GLenum, buffer: u32) {
+        self.state.borrow_mut().check_name(GlObjectKind::Buffer, buffer);
+        self.record(GlCall::BindBuffer { target, buffer });
+    }
+
+    fn buffer_data(&self, target: GLenum, data: &[u8], usage: GLenum) {
+        self.record(GlCall::BufferData { target, size: data.len(), usage });
+    }
+
+    fn delete_buffer(&self, buffer: u32) {
+        self.state.borrow_mut().delete(GlObjectKind::Buffer, buffer);
+        self.record(GlCall::DeleteBuffer(buffer));
+    }
+
+    fn gen_vertex_array(&self) -> u32 {
+        let array = self.state.borrow_mut().create(GlObjectKind::VertexArray);
+        self.record(GlCall::GenVertexArray(array));
+        array
+    }
+
+    fn bind_vertex_array(&self, array: u32) {
+        self.state.borrow_mut().check_name(GlObjectKind::VertexArray, array);
+        self.record(GlCall::BindVertexArray(array));
+    }
+
+    fn delete_vertex_array(&self, array: u32) {
+        self.state.borrow_mut().delete(GlObjectKind::VertexArray, array);
+        self.record(GlCall::DeleteVertexArray(array));
+    }
+
+    fn vertex_attrib_pointer(&self, index: u32, size: i32, data_type: GLenum, normalized: GLboolean, stride: GLsizei, offset: usize) {
+        self.record(GlCall::VertexAttribPointer { index, size, data_type, normalized, stride, offset });
+    }
+
+    fn enable_vertex_attrib_array(&self, index: u32) {
+        self.record(GlCall::EnableVertexAttribArray(index));
+    }
+
+    fn draw_elements(&self, mode: GLenum, count: i32, index_type: GLenum, offset: usize) {
+        self.record(GlCall::DrawElements { mode, count, index_type, offset });
+    }
+
+    fn create_shader(&self, shader_type: GLenum) -> u32 {
+        let shader = {
+            let mut state = self.state.borrow_mut();
+            if state.fail_create_shader { 0 } else { state.create(GlObjectKind::Shader) }
+        };
+
+        self.record(GlCall::CreateShader { shader_type, shader });
+        shader
+    }
+
+    fn shader_source(&self, shader: u32, source: &str) {
+        self.record(GlCall::ShaderSource { shader, source: source.to_string() });
+    }
+
+    fn compile_shader(&self, shader: u32) {
+        {
+            let mut state = self.state.borrow_mut();
+            let result = state.compile_error.clone().map_or(Ok(()), Err);
+            state.compiled.insert(shader, result);
+        }
+
+        self.record(GlCall::CompileShader(shader));
+    }
+
+    fn get_shader_iv(&self, shader: u32, parameter: GLenum) -> i32 {
+        let state = self.state.borrow();
+        let result = state.compiled.get(&shader);
+
+        match parameter {
+            gl::COMPILE_STATUS => matches!(result, Some(Ok(()))) as i32,
+            gl::INFO_LOG_LENGTH => match result {
+                Some(Err(log)) => log.len() as i32 + 1,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    fn get_shader_info_log(&self, shader: u32) -> String {
+        match self.state.borrow().compiled.get(&shader) {
+            Some(Err(log)) => log.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn delete_shader(&self, shader: u32) {
+        self.state.borrow_mut().delete(GlObjectKind::Shader, shader);
+        self.record(GlCall::DeleteShader(shader));
+    }
+
+    fn create_program(&self) -> u32 {
+        let program = {
+            let mut state = self.state.borrow_mut();
+            if state.fail_create_program { 0 } else { state.create(GlObjectKind::Program) }
+        };
+
+        self.record(GlCall::CreateProgram(program));
+        program
+    }
+
+    fn attach_shader(&self, program: u32, shader: u32) {
+        self.record(GlCall::AttachShader { program, shader });
+    }
+
+    fn detach_shader(&self, program: u32, shader: u32) {
+        self.record(GlCall::DetachShader { program, shader });
+    }
+
+    fn link_program(&self, program: u32) {
+        {
+            let mut state = self.state.borrow_mut();
+            let result = state.link_error.clone().map_or(Ok(()), Err);
+            state.linked.insert(program, result);
+        }
+
+        self.record(GlCall::LinkProgram(program));
+    }
+
+    fn get_program_iv(&self, program: u32, parameter: GLenum) -> i32 {
+        let state = self.state.borrow();
+        let result = state.linked.get(&program);
+
+        match parameter {
+            gl::LINK_STATUS => matches!(result, Some(Ok(()))) as i32,
+            gl::INFO_LOG_LENGTH => match result {
+                Some(Err(log)) => log.len() as i32 + 1,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    fn get_program_info_log(&self, program: u32) -> String {
+        match self.state.borrow().linked.get(&program) {
+            Some(Err(log)) => log.clone(),
+            _ => String::new(),
+        }
+    }
+
+    fn use_program(&self, program: u32) {
+        self.state.borrow_mut().check_name(GlObjectKind::Program, program);
+        self.record(GlCall::UseProgram(program));
+    }
+
+    fn delete_program(&self, program: u32) {
+        self.state.borrow_mut().delete(GlObjectKind::Program, program);
+        self.record(GlCall::DeleteProgram(program));
+    }
+
+    fn get_uniform_location(&self, program: u32, name: &str) -> i32 {
+        let mut state = self.state.borrow_mut();
+        if state.missing_uniforms.contains(name) {
+            return -1;
+        }
+
+        let next_location = state.uniform_locations.keys().filter(|(owner, _)| *owner == program).count() as i32;
+        *state.uniform_locations.entry((program, name.to_string())).or_insert(next_location)
+    }
+
+    fn uniform(&self, location: i32, value: &UniformValue) {
+        self.record(GlCall::Uniform { location, value: *value });
+    }
+
+    fn gen_texture(&self) -> u32 {
+        let texture = self.state.borrow_mut().create(GlObjectKind::Texture);
+        self.record(GlCall::GenTexture(texture));
+        texture
+    }
+
+    fn active_texture(&self, unit: GLenum) {
+        self.record(GlCall::ActiveTexture(unit));
+    }
+
+    fn bind_texture(&self, target: GLenum, texture: u32) {
+        self.state.borrow_mut().check_name(GlObjectKind::Texture, texture);
+        self.record(GlCall::BindTexture { target, texture });
+    }
+
+    fn tex_parameter_i(&self, target: GLenum, parameter: GLenum, value: i32) {
+        self.record(GlCall::TexParameter { target, parameter, value });
+    }
+
+    fn tex_image_2d(&self, target: GLenum, level: i32, internal_format: i32, width: i32, height: i32, format: GLenum, data_type: GLenum, pixels: &[u8]) {
+        self.record(GlCall::TexImage2D { target, level, internal_format, width, height, format, data_type, size: pixels.len() });
+    }
+
+    fn generate_mipmap(&self, target: GLenum) {
+        self.record(GlCall::GenerateMipmap(target));
+    }
+
+    fn delete_texture(&self, texture: u32) {
+        self.state.borrow_mut().delete(GlObjectKind::Texture, texture);
+        self.record(GlCall::DeleteTexture(texture));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_reported_in_the_order_they_happened() {
+        let gl = RecordingGl::new();
+        gl.push_error(gl::OUT_OF_MEMORY);
+        gl.bind_texture(gl::TEXTURE_2D, 42);
+
+        assert_eq!(gl.get_error(), gl::OUT_OF_MEMORY);
+        assert_eq!(gl.get_error(), gl::INVALID_OPERATION);
+        assert_eq!(gl.get_error(), gl::NO_ERROR);
+    }
+}
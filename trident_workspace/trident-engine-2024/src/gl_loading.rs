@@ -1,9 +1,8 @@
 use gl::types::{GLboolean, GLenum, GLsizei};
 use std::any::Any;
-use std::ops::Deref;
-use std::os::raw::c_void;
 use std::sync::Arc;
 use log::error;
+use crate::gl_backend;
 use crate::opengl_utils::check_opengl_error;
 
 pub enum BufferType {
@@ -11,6 +10,15 @@ pub enum BufferType {
     ElementArrayBuffer,
 }
 
+impl BufferType {
+    fn target(&self) -> GLenum {
+        match self {
+            BufferType::ArrayBuffer => gl::ARRAY_BUFFER,
+            BufferType::ElementArrayBuffer => gl::ELEMENT_ARRAY_BUFFER,
+        }
+    }
+}
+
 pub struct BufferObject<T>
 where
     T: Sized + 'static + Clone
@@ -28,39 +36,26 @@ impl<'a, T: 'static + Clone> BufferObject<T> {
         let data = data.as_ref();
         assert!(!data.is_empty());
         
-        let mut id = 0;
-
-        unsafe {
-            match buffer_type {
-                BufferType::ArrayBuffer => {
-                    gl::GenBuffers(1, &mut id);
-                    gl::BindBuffer(gl::ARRAY_BUFFER, id);
-                    gl::BufferData(
-                        gl::ARRAY_BUFFER,
-                        size_of_val(&data) as isize,
-                        data.as_ptr() as *const c_void,
-                        gl::STATIC_DRAW);
-                },
-                BufferType::ElementArrayBuffer => {
-                    gl::GenBuffers(1, &mut id);
-                    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, id);
-
-                    let any_value = &data[0] as &dyn Any;
-                    if any_value.is::<f32>() {
-                        panic!("Element array buffer cannot contain f32 values");
-                    }
-
-                    gl::BufferData(
-                        gl::ELEMENT_ARRAY_BUFFER,
-                        size_of_val(&data) as isize,
-                        data.as_ptr() as *const c_void,
-                        gl::STATIC_DRAW);
-                },
-            }
+        let backend = gl_backend::current();
+        let target = buffer_type.target();
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 55);
+        if let BufferType::ElementArrayBuffer = buffer_type {
+            let any_value = &data[0] as &dyn Any;
+            if any_value.is::<f32>() {
+                panic!("Element array buffer cannot contain f32 values");
+            }
         }
+
+        // The buffer only sees the bytes, whatever `T` is.
+        let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data)) };
+
+        let id = backend.gen_buffer();
+        backend.bind_buffer(target, id);
+        backend.buffer_data(target, bytes, gl::STATIC_DRAW);
+
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
+
         Self {
             id,
             data: Arc::new(data.into()),
@@ -68,28 +63,22 @@ impl<'a, T: 'static + Clone> BufferObject<T> {
         }
     }
 
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
     pub fn bind(&self) {
-        unsafe {
-            match self.buffer_type {
-                BufferType::ArrayBuffer => gl::BindBuffer(gl::ARRAY_BUFFER, self.id),
-                BufferType::ElementArrayBuffer => gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id),
-            }
+        gl_backend::current().bind_buffer(self.buffer_type.target(), self.id);
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 69);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
     }
 
     pub fn unbind(&self) {
-        unsafe {
-            match self.buffer_type {
-                BufferType::ArrayBuffer => gl::BindBuffer(gl::ARRAY_BUFFER, 0),
-                BufferType::ElementArrayBuffer => gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0),
-            }
+        gl_backend::current().bind_buffer(self.buffer_type.target(), 0);
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 81);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
     }
 
     pub fn get_data(&'a self) -> &'a Vec<T> {
@@ -108,13 +97,10 @@ where
     fn drop(&mut self) {
 
         self.unbind();
+        gl_backend::current().delete_buffer(self.id);
 
-        unsafe {
-            gl::DeleteBuffers(1, &self.id);
-
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 109);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
     }
 }
 
@@ -125,19 +111,20 @@ pub struct VertexArrayObject {
 
 impl VertexArrayObject {
     pub fn new(attrib_pointers: Vec<VertexAttributePointer>) -> Self {
-        let mut id = 0;
-        unsafe {
-            gl::GenVertexArrays(1, &mut id);
+        let id = gl_backend::current().gen_vertex_array();
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 126);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
 
         Self {
             id, attrib_pointers: Arc::new(attrib_pointers)
         }
     }
 
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
     pub fn set_attrib_pointer(&self, index: usize) {
         let pointer =  match self.attrib_pointers.get(index) {
             Some(pointer) => pointer,
@@ -157,34 +144,27 @@ impl VertexArrayObject {
     }
 
     pub fn bind(&self) {
-        unsafe {
-            gl::BindVertexArray(self.id);
+        gl_backend::current().bind_vertex_array(self.id);
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 142);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
     }
 
     pub fn unbind(&self) {
-        unsafe {
-            gl::BindVertexArray(0);
+        gl_backend::current().bind_vertex_array(0);
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 151);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
     }
 }
 
 impl Drop for VertexArrayObject {
     fn drop(&mut self) {
         self.unbind();
+        gl_backend::current().delete_vertex_array(self.id);
 
-        unsafe {
-            gl::DeleteVertexArrays(1, &self.id);
-
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 164);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
     }
 }
 
@@ -217,23 +197,55 @@ impl VertexAttributePointer {
     }
 
     pub fn set_attrib_pointer(&self) {
-        unsafe {
-            gl::VertexAttribPointer(
-                self.index,
-                self.size,
-                self.data_type,
-                self.normalized,
-                self.stride,
-                self.offset as *const c_void);
-        }
+        gl_backend::current().vertex_attrib_pointer(
+            self.index,
+            self.size,
+            self.data_type,
+            self.normalized,
+            self.stride,
+            self.offset);
     }
 
     pub fn enable_vertex_attrib_ptr(&self) {
-        unsafe {
-            gl::EnableVertexAttribArray(self.index);
+        gl_backend::current().enable_vertex_attrib_array(self.index);
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("gl_loading", 214);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("gl_loading", line!());
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_backend::recording::{GlCall, GlObjectKind, RecordingGl};
+
+    #[test]
+    fn dropping_a_buffer_unbinds_and_deletes_it() {
+        let gl = RecordingGl::install();
+        let buffer = BufferObject::new(vec![0u32, 1, 2], BufferType::ElementArrayBuffer);
+        let id = buffer.id();
+        assert!(gl.is_live(GlObjectKind::Buffer, id));
+
+        gl.take_calls();
+        drop(buffer);
+
+        assert_eq!(gl.calls(), vec![
+            GlCall::BindBuffer { target: gl::ELEMENT_ARRAY_BUFFER, buffer: 0 },
+            GlCall::DeleteBuffer(id),
+        ]);
+        assert!(gl.live_objects(GlObjectKind::Buffer).is_empty());
+    }
+
+    #[test]
+    fn dropping_a_vertex_array_unbinds_and_deletes_it() {
+        let gl = RecordingGl::install();
+        let vao = VertexArrayObject::new(Vec::new());
+        let id = vao.id();
+        assert!(gl.is_live(GlObjectKind::VertexArray, id));
+
+        gl.take_calls();
+        drop(vao);
+
+        assert_eq!(gl.calls(), vec![GlCall::BindVertexArray(0), GlCall::DeleteVertexArray(id)]);
+        assert!(gl.live_objects(GlObjectKind::VertexArray).is_empty());
     }
-}
\ No newline at end of file
+}
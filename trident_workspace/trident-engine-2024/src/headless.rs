@@ -0,0 +1,331 @@
+use std::env;
+use std::error::Error;
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+use sdl2::image::{LoadSurface, SaveSurface};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface;
+use crate::opengl_utils::check_opengl_error;
+
+/// Set to write the current output over the golden images instead of comparing against them.
+pub const UPDATE_GOLDENS_VAR: &str = "TRIDENT_UPDATE_GOLDENS";
+
+/// Tightly packed 8-bit RGBA pixels, top row first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn new(width: u32, height: u32) -> RgbaImage {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let start = ((y * self.width + x) * 4) as usize;
+        self.pixels[start..start + 4].try_into().unwrap()
+    }
+
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let surface = Surface::from_file(path.as_ref())?;
+        let surface = surface.convert_format(PixelFormatEnum::RGBA32)?;
+        let (width, height) = surface.size();
+        let pitch = surface.pitch() as usize;
+
+        let data = surface.without_lock().ok_or("Surface must be locked to read its pixels")?;
+        let row_len = width as usize * 4;
+        let mut image = Self::new(width, height);
+
+        for row in 0..height as usize {
+            image.pixels[row * row_len..(row + 1) * row_len]
+                .copy_from_slice(&data[row * pitch..row * pitch + row_len]);
+        }
+
+        Ok(image)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let mut pixels = self.pixels.clone();
+        let surface = Surface::from_data(&mut pixels, self.width, self.height, self.width * 4, PixelFormatEnum::RGBA32)?;
+        surface.save(path.as_ref())?;
+        Ok(())
+    }
+}
+
+/// A framebuffer with an RGBA color buffer and a depth/stencil buffer, for rendering without
+/// showing anything on screen.
+pub struct OffscreenTarget {
+    framebuffer: u32,
+    color_buffer: u32,
+    depth_buffer: u32,
+    width: u32,
+    height: u32,
+}
+
+impl OffscreenTarget {
+    pub fn new(width: u32, height: u32) -> Result<Self, Box<dyn Error>> {
+        let mut target = Self {
+            framebuffer: 0,
+            color_buffer: 0,
+            depth_buffer: 0,
+            width,
+            height,
+        };
+
+        unsafe {
+            gl::GenFramebuffers(1, &mut target.framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target.framebuffer);
+
+            gl::GenRenderbuffers(1, &mut target.color_buffer);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, target.color_buffer);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as i32, height as i32);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, target.color_buffer);
+
+            gl::GenRenderbuffers(1, &mut target.depth_buffer);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, target.depth_buffer);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, target.depth_buffer);
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            #[cfg(debug_assertions)]
+            check_opengl_error("headless", line!());
+
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(format!("Offscreen framebuffer is incomplete (status 0x{:X})", status).into());
+            }
+        }
+
+        Ok(target)
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Render into this target, and size the viewport to it, until `unbind` is called.
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        }
+    }
+
+    pub fn unbind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    /// Read the color buffer back, flipped so the top row comes first.
+    pub fn read_pixels(&self) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width, self.height);
+
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                self.width as i32,
+                self.height as i32,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                image.pixels.as_mut_ptr() as *mut c_void);
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+
+            #[cfg(debug_assertions)]
+            check_opengl_error("headless", line!());
+        }
+
+        // OpenGL's first row is the bottom one.
+        let row_len = self.width as usize * 4;
+        let flipped: Vec<u8> = image.pixels.chunks_exact(row_len).rev().flatten().copied().collect();
+        image.pixels = flipped;
+
+        image
+    }
+}
+
+impl Drop for OffscreenTarget {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(1, &self.color_buffer);
+            gl::DeleteRenderbuffers(1, &self.depth_buffer);
+        }
+    }
+}
+
+/// How far a rendered image may stray from its golden image and still pass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Largest difference allowed in any one channel of a pixel.
+    pub channel: u8,
+    /// Fraction of pixels, in `[0, 1]`, allowed to go over `channel`.
+    pub mismatched_pixels: f32,
+}
+
+impl Default for Tolerance {
+    /// Enough slack for rounding differences between software and hardware rasterizers.
+    fn default() -> Self {
+        Self {
+            channel: 2,
+            mismatched_pixels: 0.0,
+        }
+    }
+}
+
+/// The result of `compare_images`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageComparison {
+    pub mismatched_pixels: usize,
+    pub max_channel_difference: u8,
+    /// White where pixels differ by more than the tolerance, black elsewhere.
+    pub diff: RgbaImage,
+}
+
+/// Compare two images of the same size pixel by pixel.
+pub fn compare_images(actual: &RgbaImage, expected: &RgbaImage, tolerance: Tolerance) -> Result<ImageComparison, Box<dyn Error>> {
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        return Err(format!(
+            "Image is {}x{}, expected {}x{}",
+            actual.width, actual.height, expected.width, expected.height
+        ).into());
+    }
+
+    let mut diff = RgbaImage::new(actual.width, actual.height);
+    let mut mismatched_pixels = 0;
+    let mut max_channel_difference = 0;
+
+    let pixels = actual.pixels.chunks_exact(4).zip(expected.pixels.chunks_exact(4));
+    for (index, (actual_pixel, expected_pixel)) in pixels.enumerate() {
+        let difference = actual_pixel
+            .iter()
+            .zip(expected_pixel.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap_or(0);
+
+        max_channel_difference = max_channel_difference.max(difference);
+        let value = if difference > tolerance.channel {
+            mismatched_pixels += 1;
+            255
+        } else {
+            0
+        };
+
+        diff.pixels[index * 4..index * 4 + 4].copy_from_slice(&[value, value, value, 255]);
+    }
+
+    Ok(ImageComparison {
+        mismatched_pixels,
+        max_channel_difference,
+        diff,
+    })
+}
+
+fn sibling_path(golden_path: &Path, suffix: &str) -> PathBuf {
+    let stem = golden_path.file_stem().unwrap_or_default().to_string_lossy();
+    golden_path.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+/// Compare `actual` against the PNG at `golden_path`. On a mismatch, `<name>.actual.png` and
+/// `<name>.diff.png` are written next to the golden image and an error describes the difference.
+/// With `TRIDENT_UPDATE_GOLDENS` set, the golden image is overwritten with `actual` instead.
+pub fn check_golden<P: AsRef<Path>>(actual: &RgbaImage, golden_path: P, tolerance: Tolerance) -> Result<(), Box<dyn Error>> {
+    let golden_path = golden_path.as_ref();
+
+    if env::var_os(UPDATE_GOLDENS_VAR).is_some() {
+        return actual.save_png(golden_path);
+    }
+
+    let expected = RgbaImage::load_png(golden_path).map_err(|e| format!(
+        "Failed to load golden image {} ({}). Run with {}=1 to create it",
+        golden_path.display(), e, UPDATE_GOLDENS_VAR
+    ))?;
+
+    let comparison = compare_images(actual, &expected, tolerance)?;
+    let pixel_count = (actual.width * actual.height).max(1) as f32;
+    if comparison.mismatched_pixels as f32 / pixel_count <= tolerance.mismatched_pixels {
+        return Ok(());
+    }
+
+    let actual_path = sibling_path(golden_path, "actual");
+    let diff_path = sibling_path(golden_path, "diff");
+    actual.save_png(&actual_path)?;
+    comparison.diff.save_png(&diff_path)?;
+
+    Err(format!(
+        "{} of {} pixels differ from {} (largest channel difference {}). See {} and {}",
+        comparison.mismatched_pixels,
+        actual.width * actual.height,
+        golden_path.display(),
+        comparison.max_channel_difference,
+        actual_path.display(),
+        diff_path.display()
+    ).into())
+}
+
+/// Panicking form of `check_golden` for use in tests.
+pub fn assert_golden<P: AsRef<Path>>(actual: &RgbaImage, golden_path: P, tolerance: Tolerance) {
+    if let Err(e) = check_golden(actual, golden_path, tolerance) {
+        panic!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 image whose red channels are `reds`, in row order.
+    fn image(reds: [u8; 4]) -> RgbaImage {
+        RgbaImage {
+            width: 2,
+            height: 2,
+            pixels: reds.iter().flat_map(|red| [*red, 0, 0, 255]).collect(),
+        }
+    }
+
+    #[test]
+    fn compare_images_marks_pixels_over_the_channel_tolerance() {
+        let comparison = compare_images(&image([10, 12, 13, 210]), &image([10; 4]), Tolerance::default()).unwrap();
+
+        assert_eq!(comparison.mismatched_pixels, 2);
+        assert_eq!(comparison.max_channel_difference, 200);
+        let diff: Vec<[u8; 4]> = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(x, y)| comparison.diff.pixel(x, y)).to_vec();
+        assert_eq!(diff, [[0, 0, 0, 255], [0, 0, 0, 255], [255, 255, 255, 255], [255, 255, 255, 255]]);
+    }
+
+    #[test]
+    fn compare_images_rejects_other_sizes() {
+        let error = compare_images(&RgbaImage::new(2, 2), &RgbaImage::new(2, 3), Tolerance::default()).unwrap_err();
+        assert_eq!(error.to_string(), "Image is 2x2, expected 2x3");
+    }
+
+    #[test]
+    fn check_golden_allows_the_mismatched_fraction() {
+        let directory = tempfile::tempdir().unwrap();
+        let golden_path = directory.path().join("golden.png");
+        image([10; 4]).save_png(&golden_path).unwrap();
+
+        let actual = image([10, 10, 10, 50]);
+        let quarter = Tolerance { channel: 2, mismatched_pixels: 0.25 };
+        assert!(check_golden(&actual, &golden_path, quarter).is_ok());
+        assert!(!directory.path().join("golden.diff.png").exists());
+
+        let fifth = Tolerance { channel: 2, mismatched_pixels: 0.2 };
+        let error = check_golden(&actual, &golden_path, fifth).unwrap_err();
+        assert!(error.to_string().starts_with("1 of 4 pixels differ"), "{}", error);
+        assert_eq!(RgbaImage::load_png(directory.path().join("golden.actual.png")).unwrap(), actual);
+        assert_eq!(RgbaImage::load_png(directory.path().join("golden.diff.png")).unwrap().pixel(1, 1), [255, 255, 255, 255]);
+    }
+}
@@ -0,0 +1,534 @@
+use std::error::Error;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+use async_std::sync::RwLock;
+use async_std::task;
+use libloading::{library_filename, Library};
+use log::{error, info, warn, Level};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use crate::game::services::input_actions::{Action, ActionEvent, ActionPhase, ActionsService};
+use crate::game::services::input_bindings::InputButton;
+use crate::game::services::{OwnerId, ServiceManager};
+use crate::game_loop::FrameContext;
+
+/// Bumped whenever the signature of any client entry point, or any `repr(C)` type below, changes.
+/// A library built against a different version is refused instead of being called.
+pub const CLIENT_ABI_VERSION: u32 = 2;
+
+/// How long the library file has to stay untouched before it is reloaded,
+/// so a half-written build output is never loaded.
+const RELOAD_SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// The engine side of a loaded client. Opaque to the client, which only ever passes it back to
+/// the functions in `ClientHost`.
+#[repr(C)]
+pub struct HostHandle {
+    _private: [u8; 0],
+}
+
+/// `FrameContext` as plain numbers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientFrame {
+    pub delta_secs: f64,
+    pub total_secs: f64,
+    pub frame_index: u64,
+    pub alpha: f32,
+}
+
+impl From<&FrameContext> for ClientFrame {
+    fn from(frame: &FrameContext) -> Self {
+        Self {
+            delta_secs: frame.delta.as_secs_f64(),
+            total_secs: frame.total_time.as_secs_f64(),
+            frame_index: frame.frame_index,
+            alpha: frame.alpha,
+        }
+    }
+}
+
+/// `ActionEvent` as plain numbers.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientActionEvent {
+    pub phase: ActionPhase,
+    pub timestamp: u32,
+    pub held_secs: f64,
+}
+
+impl From<&ClientFrame> for FrameContext {
+    fn from(frame: &ClientFrame) -> Self {
+        Self {
+            delta: Duration::from_secs_f64(frame.delta_secs),
+            total_time: Duration::from_secs_f64(frame.total_secs),
+            frame_index: frame.frame_index,
+            alpha: frame.alpha,
+        }
+    }
+}
+
+impl From<&ActionEvent> for ClientActionEvent {
+    fn from(event: &ActionEvent) -> Self {
+        Self {
+            phase: event.phase,
+            timestamp: event.timestamp,
+            held_secs: event.held_duration.as_secs_f64(),
+        }
+    }
+}
+
+/// Called when an action registered through `ClientHost::register_action` fires.
+/// `user_data` is the pointer given at registration.
+pub type ActionCallbackFn = extern "C" fn(user_data: *mut c_void, event: *const ClientActionEvent);
+/// Appends `len` bytes at `data` to the state being saved. `writer` is the pointer handed to
+/// `SaveStateFn`.
+pub type StateWriterFn = extern "C" fn(writer: *mut c_void, data: *const u8, len: usize);
+
+/// What the engine offers the client. Strings are NUL-terminated UTF-8 and only borrowed for the
+/// length of the call.
+#[repr(C)]
+pub struct ClientHost {
+    pub handle: *mut HostHandle,
+    /// Register an action bound to a single button, written like `"Key:Space"` (see
+    /// `InputButton`). `description` may be null. Returns false if the button doesn't parse or
+    /// there is no `ActionsService`. Actions the client still has registered are removed
+    /// when it is unloaded.
+    pub register_action: extern "C" fn(
+        handle: *mut HostHandle,
+        name: *const c_char,
+        button: *const c_char,
+        description: *const c_char,
+        callback: ActionCallbackFn,
+        user_data: *mut c_void) -> bool,
+    /// Remove an action the client registered. Actions registered by anyone else are left alone.
+    pub unregister_action: extern "C" fn(handle: *mut HostHandle, name: *const c_char) -> bool,
+    /// Log through the engine's logger. `level` is a `log::Level` as a number, 1 (error) to 5 (trace).
+    pub log: extern "C" fn(handle: *mut HostHandle, level: u32, message: *const c_char),
+}
+
+/// Returns the `CLIENT_ABI_VERSION` the library was built against.
+pub type AbiVersionFn = extern "C" fn() -> u32;
+/// Creates the client's game state, restoring it from the `saved_state_len` bytes at `saved_state`
+/// when reloading (null and 0 on the first load). Returns null on failure.
+/// The returned pointer is owned by the client and handed back to every other entry point.
+pub type InitFn = extern "C" fn(host: *const ClientHost, saved_state: *const u8, saved_state_len: usize) -> *mut c_void;
+pub type UpdateFn = extern "C" fn(state: *mut c_void, host: *const ClientHost, frame: *const ClientFrame);
+/// Serializes the game state through `write` right before the library is unloaded.
+pub type SaveStateFn = extern "C" fn(state: *mut c_void, write: StateWriterFn, writer: *mut c_void);
+/// Frees the game state.
+pub type ShutdownFn = extern "C" fn(state: *mut c_void, host: *const ClientHost);
+
+pub const ABI_VERSION_SYMBOL: &[u8] = b"trident_client_abi_version";
+pub const INIT_SYMBOL: &[u8] = b"trident_client_init";
+pub const UPDATE_SYMBOL: &[u8] = b"trident_client_update";
+pub const SAVE_STATE_SYMBOL: &[u8] = b"trident_client_save_state";
+pub const SHUTDOWN_SYMBOL: &[u8] = b"trident_client_shutdown";
+
+/// Safe access to a `ClientHost` for client code.
+#[derive(Clone, Copy)]
+pub struct Host<'a> {
+    host: &'a ClientHost,
+}
+
+impl<'a> Host<'a> {
+    /// # Safety
+    /// `host` must be the pointer the engine passed to the entry point currently running.
+    pub unsafe fn from_ptr(host: *const ClientHost) -> Option<Host<'a>> {
+        host.as_ref().map(|host| Self { host })
+    }
+
+    pub fn register_action(
+        &self,
+        name: &str,
+        button: &str,
+        description: Option<&str>,
+        callback: ActionCallbackFn,
+        user_data: *mut c_void) -> bool
+    {
+        let (Ok(name), Ok(button)) = (CString::new(name), CString::new(button)) else {
+            return false;
+        };
+        let description = description.and_then(|description| CString::new(description).ok());
+        let description_ptr = description.as_ref().map_or(std::ptr::null(), |description| description.as_ptr());
+
+        (self.host.register_action)(self.host.handle, name.as_ptr(), button.as_ptr(), description_ptr, callback, user_data)
+    }
+
+    pub fn unregister_action(&self, name: &str) -> bool {
+        match CString::new(name) {
+            Ok(name) => (self.host.unregister_action)(self.host.handle, name.as_ptr()),
+            Err(_) => false,
+        }
+    }
+
+    pub fn log(&self, level: Level, message: &str) {
+        let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+        (self.host.log)(self.host.handle, level as u32, message.as_ptr());
+    }
+}
+
+/// What `HostHandle` points at.
+struct HostContext {
+    services: Arc<RwLock<ServiceManager>>,
+    owner: OwnerId,
+    library_name: String,
+}
+
+/// Lets a client's or plugin's callbacks be called from any thread the actions service runs on.
+pub(crate) struct ClientCallback {
+    pub callback: ActionCallbackFn,
+    pub user_data: *mut c_void,
+}
+
+// The client promises its callbacks and `user_data` can be used from any thread.
+unsafe impl Send for ClientCallback {}
+unsafe impl Sync for ClientCallback {}
+
+impl ClientCallback {
+    fn call(&self, event: &ActionEvent) {
+        let event = ClientActionEvent::from(event);
+        (self.callback)(self.user_data, &event);
+    }
+}
+
+pub(crate) unsafe fn host_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(string).to_str().ok()
+}
+
+/// Register an action for a client or plugin. Shared by `ClientHost` and `PluginHost`.
+///
+/// # Safety
+/// The strings must be null or valid NUL-terminated strings.
+pub(crate) unsafe fn register_foreign_action(
+    services: &ServiceManager,
+    owner: OwnerId,
+    source_name: &str,
+    name: *const c_char,
+    button: *const c_char,
+    description: *const c_char,
+    callback: ClientCallback) -> bool
+{
+    let (Some(name), Some(button)) = (host_str(name), host_str(button)) else {
+        return false;
+    };
+    let description = host_str(description).map(str::to_string);
+
+    let button: InputButton = match button.parse() {
+        Ok(button) => button,
+        Err(e) => {
+            warn!("{} tried to bind action {} to {}: {}", source_name, name, button, e);
+            return false;
+        },
+    };
+
+    let Some(actions) = services.get::<ActionsService>() else {
+        return false;
+    };
+
+    let action = Action::new(name.to_string(), button, description, move |event| callback.call(event));
+    task::block_on(actions.write()).register_action(action.owned_by(owner));
+    true
+}
+
+/// The `log::Level` for a level number passed through `ClientHost::log` or `PluginHost::log`.
+pub(crate) fn log_level(level: u32) -> Level {
+    match level {
+        1 => Level::Error,
+        2 => Level::Warn,
+        3 => Level::Info,
+        4 => Level::Debug,
+        _ => Level::Trace,
+    }
+}
+
+unsafe fn host_context<'a>(handle: *mut HostHandle) -> &'a HostContext {
+    &*(handle as *const HostContext)
+}
+
+extern "C" fn host_register_action(
+    handle: *mut HostHandle,
+    name: *const c_char,
+    button: *const c_char,
+    description: *const c_char,
+    callback: ActionCallbackFn,
+    user_data: *mut c_void) -> bool
+{
+    let context = unsafe { host_context(handle) };
+    let services = task::block_on(context.services.read());
+    let callback = ClientCallback { callback, user_data };
+
+    unsafe { register_foreign_action(&services, context.owner, &context.library_name, name, button, description, callback) }
+}
+
+extern "C" fn host_unregister_action(handle: *mut HostHandle, name: *const c_char) -> bool {
+    let context = unsafe { host_context(handle) };
+    let Some(name) = (unsafe { host_str(name) }) else {
+        return false;
+    };
+
+    let Some(actions) = task::block_on(context.services.read()).get::<ActionsService>() else {
+        return false;
+    };
+
+    let removed = task::block_on(actions.write()).unregister_owned_action(context.owner, name);
+    removed.is_some()
+}
+
+extern "C" fn host_log(handle: *mut HostHandle, level: u32, message: *const c_char) {
+    let context = unsafe { host_context(handle) };
+    if let Some(message) = unsafe { host_str(message) } {
+        log::log!(log_level(level), "[{}] {}", context.library_name, message);
+    }
+}
+
+extern "C" fn write_saved_state(writer: *mut c_void, data: *const u8, len: usize) {
+    if writer.is_null() || data.is_null() {
+        return;
+    }
+
+    let saved_state = unsafe { &mut *(writer as *mut Vec<u8>) };
+    saved_state.extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+}
+
+struct ClientEntryPoints {
+    init: InitFn,
+    update: UpdateFn,
+    save_state: SaveStateFn,
+    shutdown: ShutdownFn,
+}
+
+/// A loaded copy of the client library together with the state it created.
+struct LoadedClient {
+    entry_points: ClientEntryPoints,
+    state: *mut c_void,
+    shadow_path: PathBuf,
+    // Dropped last so the entry points above never outlive the code they point to.
+    _library: Library,
+}
+
+/// Loads the game logic from the `client` dylib and swaps in a new build whenever the file changes.
+/// The library is copied before loading so the build can overwrite the original while it's in use.
+///
+/// Only `repr(C)` types cross the boundary: the client reaches the engine through the `ClientHost`
+/// function table, so it works no matter how the client's copy of the engine crate was compiled.
+pub struct ClientLibrary {
+    path: PathBuf,
+    // Boxed so the pointer in `host.handle` stays valid when the library is moved.
+    context: Box<HostContext>,
+    host: Box<ClientHost>,
+    client: Option<LoadedClient>,
+    _watcher: RecommendedWatcher,
+    file_events: Receiver<notify::Result<Event>>,
+    changed_at: Option<Instant>,
+    saved_state: Option<Vec<u8>>,
+    reload_count: u32,
+}
+
+impl ClientLibrary {
+    /// Where cargo puts the `client` library: next to the engine executable, in `target/debug` or
+    /// `target/release`.
+    pub fn default_path() -> Result<PathBuf, Box<dyn Error>> {
+        let executable = std::env::current_exe()?;
+        let directory = executable.parent().ok_or("The executable has no parent directory")?;
+        Ok(directory.join(library_filename("client")))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, services: Arc<RwLock<ServiceManager>>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+
+        // Watch the directory rather than the file: builds usually replace the file,
+        // which would silently end a watch on the file itself.
+        let (sender, file_events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        let watch_dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        watcher.watch(watch_dir, RecursiveMode::NonRecursive)?;
+
+        let mut context = Box::new(HostContext {
+            services,
+            owner: OwnerId::unique(),
+            library_name: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+        });
+
+        let host = Box::new(ClientHost {
+            handle: context.as_mut() as *mut HostContext as *mut HostHandle,
+            register_action: host_register_action,
+            unregister_action: host_unregister_action,
+            log: host_log,
+        });
+
+        let mut library = Self {
+            path,
+            context,
+            host,
+            client: None,
+            _watcher: watcher,
+            file_events,
+            changed_at: None,
+            saved_state: None,
+            reload_count: 0,
+        };
+
+        library.client = Some(library.load_client(None)?);
+        Ok(library)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn reload_count(&self) -> u32 {
+        self.reload_count
+    }
+
+    /// Copy the library to `shadow_path` and load the copy, which is removed again if that fails.
+    fn load_client(&self, saved_state: Option<&[u8]>) -> Result<LoadedClient, Box<dyn Error>> {
+        let shadow_path = self.shadow_path();
+        fs::copy(&self.path, &shadow_path)?;
+
+        // The library is unloaded again by the time a failure gets here.
+        self.load_shadow_copy(shadow_path.clone(), saved_state).inspect_err(|_| {
+            let _ = fs::remove_file(&shadow_path);
+        })
+    }
+
+    fn load_shadow_copy(&self, shadow_path: PathBuf, saved_state: Option<&[u8]>) -> Result<LoadedClient, Box<dyn Error>> {
+        let library = unsafe { Library::new(&shadow_path)? };
+
+        let entry_points = unsafe {
+            let abi_version = library.get::<AbiVersionFn>(ABI_VERSION_SYMBOL)?();
+            if abi_version != CLIENT_ABI_VERSION {
+                return Err(format!(
+                    "{} was built for client ABI version {}, but the engine expects {}",
+                    self.path.display(), abi_version, CLIENT_ABI_VERSION
+                ).into());
+            }
+
+            ClientEntryPoints {
+                init: *library.get::<InitFn>(INIT_SYMBOL)?,
+                update: *library.get::<UpdateFn>(UPDATE_SYMBOL)?,
+                save_state: *library.get::<SaveStateFn>(SAVE_STATE_SYMBOL)?,
+                shutdown: *library.get::<ShutdownFn>(SHUTDOWN_SYMBOL)?,
+            }
+        };
+
+        let (saved_state_ptr, saved_state_len) = match saved_state {
+            Some(saved_state) => (saved_state.as_ptr(), saved_state.len()),
+            None => (std::ptr::null(), 0),
+        };
+
+        let state = (entry_points.init)(self.host.as_ref(), saved_state_ptr, saved_state_len);
+        if state.is_null() {
+            self.remove_client_registrations();
+            return Err(format!("{} failed to initialize", self.path.display()).into());
+        }
+
+        info!("Loaded client library {}", self.path.display());
+        Ok(LoadedClient {
+            entry_points,
+            state,
+            shadow_path,
+            _library: library,
+        })
+    }
+
+    fn unload_client(&mut self) -> Vec<u8> {
+        let mut saved_state = Vec::new();
+
+        if let Some(client) = self.client.take() {
+            let writer = &mut saved_state as *mut Vec<u8> as *mut c_void;
+            (client.entry_points.save_state)(client.state, write_saved_state, writer);
+            (client.entry_points.shutdown)(client.state, self.host.as_ref());
+
+            // Whatever the client forgot to unregister would call into the unloaded library.
+            self.remove_client_registrations();
+
+            let shadow_path = client.shadow_path.clone();
+            drop(client);
+            let _ = fs::remove_file(shadow_path);
+        }
+
+        saved_state
+    }
+
+    fn remove_client_registrations(&self) {
+        let actions = task::block_on(self.context.services.read()).get::<ActionsService>();
+        if let Some(actions) = actions {
+            let removed = task::block_on(actions.write()).unregister_owner(self.context.owner);
+            if !removed.is_empty() {
+                warn!("Removed {} action(s) the client library left registered", removed.len());
+            }
+        }
+    }
+
+    fn shadow_path(&self) -> PathBuf {
+        let file_name = self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        std::env::temp_dir().join(format!(
+            "trident-{}-{}-{}-{}", std::process::id(), self.context.owner, self.reload_count, file_name))
+    }
+
+    /// Unload the current library and load the file at `path` again, carrying the game state across.
+    /// If the new build fails to load, the game keeps running without client logic and the saved state
+    /// is held on to for the next successful reload.
+    pub fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.client.is_some() {
+            self.saved_state = Some(self.unload_client());
+        }
+
+        self.reload_count += 1;
+        let client = self.load_client(self.saved_state.as_deref())?;
+        self.saved_state = None;
+        self.client = Some(client);
+
+        info!("Reloaded client library ({} reloads)", self.reload_count);
+        Ok(())
+    }
+
+    /// Check for rebuilds of the library file and reload it once it has settled.
+    pub fn poll_for_changes(&mut self) {
+        while let Ok(event) = self.file_events.try_recv() {
+            match event {
+                Ok(event) if event.paths.iter().any(|path| path.file_name() == self.path.file_name()) => {
+                    self.changed_at = Some(Instant::now());
+                },
+                Ok(_) => {},
+                Err(e) => warn!("Error while watching {}: {}", self.path.display(), e),
+            }
+        }
+
+        if let Some(changed_at) = self.changed_at {
+            if changed_at.elapsed() >= RELOAD_SETTLE_TIME && self.path.exists() {
+                self.changed_at = None;
+                if let Err(e) = self.reload() {
+                    error!("Hot reload of {} failed: {}", self.path.display(), e);
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, frame: &FrameContext) {
+        self.poll_for_changes();
+
+        if let Some(client) = self.client.as_ref() {
+            let frame = ClientFrame::from(frame);
+            (client.entry_points.update)(client.state, self.host.as_ref(), &frame);
+        }
+    }
+}
+
+impl Drop for ClientLibrary {
+    fn drop(&mut self) {
+        self.unload_client();
+    }
+}
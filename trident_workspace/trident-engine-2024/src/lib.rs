@@ -1,8 +1,15 @@
 pub mod game;
-mod gl_loading;
-mod shader_management;
+pub mod gl_loading;
+pub mod shader_management;
 mod shader_errors;
-mod application;
+pub mod application;
+pub mod application_config;
+pub mod game_loop;
+pub mod gl_backend;
+pub mod headless;
+pub mod hot_reload;
+pub mod plugins;
 mod opengl_utils;
-mod texture_management;
-mod rendering;
\ No newline at end of file
+pub mod texture_management;
+pub mod material_management;
+pub mod rendering;
\ No newline at end of file
@@ -1,116 +1,122 @@
-mod gl_loading;
-mod shader_management;
-mod shader_errors;
-mod application;
-mod opengl_utils;
-mod game;
-mod texture_management;
-mod rendering;
-
-use crate::application::Application;
-use crate::gl_loading::{BufferObject, BufferType, VertexArrayObject, VertexAttributePointer};
-use crate::shader_management::{Shader, ShaderProgram, ShaderType};
-use crate::texture_management::TextureLoader;
+use trident_engine_2024::application::Application;
+use trident_engine_2024::application_config::ApplicationConfig;
+use trident_engine_2024::game::camera::{Camera, Projection};
+use trident_engine_2024::game::services::input_actions::ActionsService;
+use trident_engine_2024::game::scene::components::ComponentRegistry;
+use trident_engine_2024::game::scene::prefab::PrefabLibrary;
+use trident_engine_2024::game::scene::Scene;
+use trident_engine_2024::game::scene_graph::{NodeId, SceneGraph};
+use trident_engine_2024::game::world::World;
+use trident_engine_2024::game_loop::{FrameContext, GameLoop};
+use trident_engine_2024::hot_reload::ClientLibrary;
+use trident_engine_2024::plugins::PluginManager;
+use trident_engine_2024::game::mesh_management::Mesh;
+use trident_engine_2024::material_management::{Material, MaterialLibrary};
+use trident_engine_2024::rendering::Renderer;
+use trident_engine_2024::texture_management::TextureLoader;
 use std::error::Error;
-use std::ops::Add;
-use std::thread;
-use std::time::{Duration, Instant};
-use libloading::Library;
-use nalgebra_glm::Vec3;
-
-fn make_shader_stuff() -> ShaderProgram{
-    let vertex_source = Shader::load_shader_source("main_vertex.glsl")
-        .expect("Could not load vertex shade source");
-    let fragment_source = Shader::load_shader_source("main_fragment.glsl")
-        .expect("Could not load fragment shader source");
-
-    let vertex_shader = Shader::new(ShaderType::Vertex, vertex_source);
-    let fragment_shader = Shader::new(ShaderType::Fragment, fragment_source);
-
-    let shaders = [vertex_shader, fragment_shader];
-    let shader_program = ShaderProgram::new(&shaders);
-    shader_program.link();
-
-    shader_program
+use std::rc::Rc;
+use std::sync::Arc;
+use async_std::sync::RwLock;
+use async_std::task;
+use log::warn;
+use sdl2::video::Window;
+
+const PLUGINS_DIR: &str = "plugins";
+const DEMO_MATERIAL: &str = "trident-engine-2024/res/materials/demo.toml";
+const DEMO_SCENE: &str = "trident-engine-2024/res/scenes/demo.ron";
+
+struct DemoScene {
+    material: Rc<Material>,
+    mesh: Rc<Mesh>,
+    renderer: Renderer,
+    client: Option<ClientLibrary>,
+    world: World,
+    scene_graph: SceneGraph,
+    quad: NodeId,
+    camera: Arc<RwLock<Camera>>,
 }
 
-#[async_std::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
-
-    let mut application = Application::new().expect("Failed to init SDL");
-    let vertices = [
-        -0.5f32, 0.5, 0.0, 1.0f32, 0.0, 0.0, 0.0, 1.0, // Vertex 0
-        -0.5f32, -0.5, 0.0, 0.0f32, 1.0, 0.0, 0.0, 0.0, // Vertex 1
-        0.5f32, -0.5, 0.0, 0.0f32, 0.0, 1.0, 1.0, 0.0, // Vertex 2
-        0.5f32, 0.5, 0.0, 0.0f32, 0.0, 0.0, 1.0, 1.0, // Vertex 3
-    ];
-
-    let indices = [
-        0u32, 1, 2,
-        2, 3, 0,
-    ];
-
-    let stride_multiplier = 8;
-    let mut vao = VertexArrayObject::new(vec![
-        VertexAttributePointer::new((0, 3, gl::FLOAT, gl::FALSE,
-                                     stride_multiplier * size_of::<f32>(), 0)),
-        VertexAttributePointer::new((1, 3, gl::FLOAT, gl::FALSE,
-                                     stride_multiplier * size_of::<f32>(), 3 * size_of::<f32>())),
-        VertexAttributePointer::new((2, 2, gl::FLOAT, gl::FALSE,
-                                     stride_multiplier * size_of::<f32>(), 6 * size_of::<f32>()))
-    ]);
-
-    let vbo = BufferObject::new(&vertices, BufferType::ArrayBuffer);
-    let ebo = BufferObject::new(&indices, BufferType::ElementArrayBuffer);
-
-    vao.bind();
-    vbo.bind();
-    ebo.bind();
-
-    vao.set_attrib_pointer(0);
-    vao.set_attrib_pointer(1);
-    vao.set_attrib_pointer(2);
-    vao.enable_attrib_pointers();
-
-    let mut texture_loader = TextureLoader::new();
-    let mut shader_program = make_shader_stuff();
+impl GameLoop for DemoScene {
+    fn update(&mut self, frame: &FrameContext) {
+        if let Some(client) = self.client.as_mut() {
+            client.update(frame);
+        }
 
-    let my_vector = Vec3::new(0.5, 0.0, 0.7);
-    shader_program.get_uniform_locations(&["u_Color"]);
-    shader_program.use_program();
+        self.world.update(frame);
+    }
 
-    let target_fps = 60;
-    let frame_duration = Duration::from_secs_f32(1.0 / target_fps as f32);
+    fn render(&mut self, frame: &FrameContext, window: &mut Window) {
+        self.scene_graph.update_world_matrices();
+        let model = self.scene_graph.world_matrix(self.quad);
+        let view_projection = task::block_on(self.camera.read()).view_projection_matrix();
 
-    let mut last_frame_time = Instant::now();
+        self.renderer.draw(&self.mesh, &self.material, model);
+        self.renderer.render(&view_projection);
 
-    texture_loader.load_texture("trident-engine-2024/res/textures/grass.png");
+        let new_title = format!(
+            "Trident Engine - OpenGL | Delta time: {:.2?} | Draw calls: {}",
+            frame.delta,
+            self.renderer.stats().draw_calls);
+        window.set_title(&new_title).expect("TODO: panic message");
+    }
+}
 
-    application.run(|window| {
-        let frame_start = Instant::now();
-        shader_program.set_uniform_vec3("u_Color", &my_vector);
+#[async_std::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
 
-        unsafe {
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl::DrawElements(
-                gl::TRIANGLES,
-                indices.len() as i32,
-                gl::UNSIGNED_INT,
-                std::ptr::null())
-        }
+    let config = ApplicationConfig::from_file_or_default("trident.toml")?;
+    let mut application = Application::new(config).expect("Failed to init SDL");
 
-        let elapsed_time = frame_start.elapsed();
-        if elapsed_time < frame_duration {
-            thread::sleep(frame_duration - elapsed_time);
-        }
+    application
+        .services()
+        .write()
+        .await
+        .add_service("ActionsService", ActionsService::new());
 
-        let delta_time = last_frame_time.elapsed();
-        let new_title = format!("Trident Engine - OpenGL | Delta time: {:.2?}", delta_time);
-        window.set_title(&new_title).expect("TODO: panic message");
-        last_frame_time = Instant::now();
+    let mesh = Rc::new(Mesh::quad("Quad"));
 
-    }).expect("Failed to run SDL application");
+    let mut texture_loader = TextureLoader::new();
+    let mut materials = MaterialLibrary::new();
+    let material = materials.load(DEMO_MATERIAL, &mut texture_loader)?;
+
+    let mut plugins = PluginManager::new(application.services());
+    if std::path::Path::new(PLUGINS_DIR).is_dir() {
+        plugins.load_all(PLUGINS_DIR);
+    }
+
+    let client_path = match application.config().client_library.clone() {
+        Some(path) => path,
+        None => ClientLibrary::default_path()?,
+    };
+    let client = ClientLibrary::load(&client_path, application.services())
+        .inspect_err(|e| warn!("Running without client library {}: {}", client_path.display(), e))
+        .ok();
+
+    let camera = application.camera();
+    {
+        let mut camera = camera.write().await;
+        camera.projection = Projection::Orthographic { height: 2.0 };
+        camera.transform.translation.z = 1.0;
+    }
+
+    let demo_scene = Scene::load(DEMO_SCENE, &ComponentRegistry::new(), &mut PrefabLibrary::new())?;
+    let quad = demo_scene.graph.find("Quad").ok_or("The demo scene has no Quad object")?;
+
+    let mut scene = DemoScene {
+        material,
+        mesh,
+        renderer: Renderer::new(),
+        client,
+        world: World::new(application.services()),
+        scene_graph: demo_scene.graph,
+        quad,
+        camera,
+    };
+
+    application.run(&mut scene).expect("Failed to run SDL application");
+    plugins.unload_all(&mut scene.world);
 
     Ok(())
 }
@@ -0,0 +1,497 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::rc::Rc;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use crate::gl_backend;
+use crate::shader_management::{Shader, ShaderProgram, ShaderType, UniformValue};
+use crate::texture_management::{Texture, TextureLoader};
+
+/// A shader program with the uniform values and textures to draw with it.
+/// An instance made with `Material::instance` shares its parent's shader and falls back to the
+/// parent for every uniform and texture it doesn't override.
+pub struct Material {
+    pub name: String,
+    shader: Rc<ShaderProgram>,
+    parent: Option<Rc<Material>>,
+    uniforms: BTreeMap<String, UniformValue>,
+    /// Textures by the name of the sampler uniform they're bound to.
+    textures: BTreeMap<String, Rc<Texture>>,
+}
+
+impl Material {
+    pub fn new(name: &str, shader: Rc<ShaderProgram>) -> Material {
+        Self {
+            name: name.to_string(),
+            shader,
+            parent: None,
+            uniforms: BTreeMap::new(),
+            textures: BTreeMap::new(),
+        }
+    }
+
+    /// A material that overrides some of `parent`'s parameters and inherits the rest.
+    /// Later changes to the parent show through wherever the instance doesn't override them.
+    pub fn instance(parent: &Rc<Material>, name: &str) -> Material {
+        Self {
+            name: name.to_string(),
+            shader: parent.shader.clone(),
+            parent: Some(parent.clone()),
+            uniforms: BTreeMap::new(),
+            textures: BTreeMap::new(),
+        }
+    }
+
+    pub fn shader(&self) -> &ShaderProgram {
+        &self.shader
+    }
+
+    pub fn parent(&self) -> Option<&Rc<Material>> {
+        self.parent.as_ref()
+    }
+
+    pub fn set_uniform(&mut self, name: &str, value: UniformValue) {
+        self.uniforms.insert(name.to_string(), value);
+    }
+
+    pub fn with_uniform(mut self, name: &str, value: UniformValue) -> Self {
+        self.set_uniform(name, value);
+        self
+    }
+
+    /// Drop this material's own value for `name`, going back to the parent's.
+    pub fn clear_uniform(&mut self, name: &str) -> Option<UniformValue> {
+        self.uniforms.remove(name)
+    }
+
+    /// Bind `texture` to the sampler uniform `sampler`.
+    pub fn set_texture(&mut self, sampler: &str, texture: Rc<Texture>) {
+        self.textures.insert(sampler.to_string(), texture);
+    }
+
+    pub fn with_texture(mut self, sampler: &str, texture: Rc<Texture>) -> Self {
+        self.set_texture(sampler, texture);
+        self
+    }
+
+    pub fn clear_texture(&mut self, sampler: &str) -> Option<Rc<Texture>> {
+        self.textures.remove(sampler)
+    }
+
+    /// The value `name` gets when drawing, from this material or the closest parent that sets it.
+    pub fn uniform(&self, name: &str) -> Option<&UniformValue> {
+        self.uniforms
+            .get(name)
+            .or_else(|| self.parent.as_ref().and_then(|parent| parent.uniform(name)))
+    }
+
+    pub fn texture(&self, sampler: &str) -> Option<&Rc<Texture>> {
+        self.textures
+            .get(sampler)
+            .or_else(|| self.parent.as_ref().and_then(|parent| parent.texture(sampler)))
+    }
+
+    /// Whether this material sets `name` itself rather than inheriting it.
+    pub fn overrides_uniform(&self, name: &str) -> bool {
+        self.uniforms.contains_key(name)
+    }
+
+    /// Every uniform value after inheritance, by name.
+    pub fn resolved_uniforms(&self) -> BTreeMap<&str, &UniformValue> {
+        let mut uniforms = self.parent
+            .as_ref()
+            .map(|parent| parent.resolved_uniforms())
+            .unwrap_or_default();
+
+        uniforms.extend(self.uniforms.iter().map(|(name, value)| (name.as_str(), value)));
+        uniforms
+    }
+
+    /// Every texture after inheritance, by sampler name. Textures are bound to texture units in
+    /// this order, starting at unit 0.
+    pub fn resolved_textures(&self) -> BTreeMap<&str, &Rc<Texture>> {
+        let mut textures = self.parent
+            .as_ref()
+            .map(|parent| parent.resolved_textures())
+            .unwrap_or_default();
+
+        textures.extend(self.textures.iter().map(|(sampler, texture)| (sampler.as_str(), texture)));
+        textures
+    }
+
+    /// The OpenGL ids of the resolved textures, one per texture unit.
+    pub fn texture_ids(&self) -> Vec<u32> {
+        self.resolved_textures().values().map(|texture| texture.id).collect()
+    }
+
+    /// A hash of `texture_ids`. Materials with the same key bind the same textures to the same units.
+    pub fn texture_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for texture in self.resolved_textures().values() {
+            texture.id.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Set every uniform and point every sampler at its texture unit. The shader must be in use.
+    /// Uniforms and samplers this material doesn't set are left alone, so they keep whatever the
+    /// last material drawn with the same shader gave them: give every material that shares a
+    /// shader a value for each uniform and texture the shader reads.
+    pub fn apply_uniforms(&self) {
+        for (name, value) in self.resolved_uniforms() {
+            self.shader.set_uniform(name, value);
+        }
+
+        for (unit, sampler) in self.resolved_textures().keys().enumerate() {
+            self.shader.set_uniform(sampler, &UniformValue::Int(unit as i32));
+        }
+    }
+
+    /// Bind the resolved textures to texture units 0, 1, ... Units past the last one keep
+    /// whatever was bound to them before.
+    pub fn bind_textures(&self) {
+        let backend = gl_backend::current();
+        for (unit, texture) in self.resolved_textures().values().enumerate() {
+            backend.active_texture(gl::TEXTURE0 + unit as u32);
+            backend.bind_texture(gl::TEXTURE_2D, texture.id);
+        }
+    }
+
+    /// Use the shader and set everything up for drawing with this material.
+    pub fn bind(&self) {
+        self.shader.use_program();
+        self.bind_textures();
+        self.apply_uniforms();
+    }
+}
+
+/// A uniform value as written in a material file: a number or an array of 2, 3, 4 or 16 floats.
+/// Whole numbers are read as `int` uniforms, so write `1.0` for a float.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UniformData {
+    Int(i32),
+    Float(f32),
+    Array(Vec<f32>),
+}
+
+impl TryFrom<&UniformData> for UniformValue {
+    type Error = String;
+
+    fn try_from(data: &UniformData) -> Result<Self, Self::Error> {
+        match data {
+            UniformData::Int(value) => Ok(UniformValue::Int(*value)),
+            UniformData::Float(value) => Ok(UniformValue::Float(*value)),
+            UniformData::Array(values) => match values.len() {
+                2 => Ok(UniformValue::Vec2(glm::make_vec2(values))),
+                3 => Ok(UniformValue::Vec3(glm::make_vec3(values))),
+                4 => Ok(UniformValue::Vec4(glm::make_vec4(values))),
+                16 => Ok(UniformValue::Mat4(glm::make_mat4(values))),
+                len => Err(format!("Uniform arrays need 2, 3, 4 or 16 values, found {}", len)),
+            },
+        }
+    }
+}
+
+/// Shader sources for a material file, relative to the shaders directory.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MaterialShaders {
+    pub vertex: String,
+    pub fragment: String,
+}
+
+/// The on-disk form of a material, in TOML:
+///
+/// ```toml
+/// name = "Grass"
+/// shader = { vertex = "main_vertex.glsl", fragment = "main_fragment.glsl" }
+///
+/// [uniforms]
+/// u_Color = [0.5, 0.0, 0.7]
+///
+/// [textures]
+/// some_texture = "trident-engine-2024/res/textures/grass.png"
+/// ```
+///
+/// A material with `parent = "other.toml"` instead of `shader` becomes an instance of that material.
+/// `parent` is relative to the file that names it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDescription {
+    pub name: String,
+    pub shader: Option<MaterialShaders>,
+    pub parent: Option<String>,
+    pub uniforms: BTreeMap<String, UniformData>,
+    /// Texture files by sampler uniform name.
+    pub textures: BTreeMap<String, String>,
+}
+
+impl MaterialDescription {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+}
+
+/// Loads material files and shares the materials, shader programs and textures they use.
+#[derive(Default)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, Rc<Material>>,
+    shaders: HashMap<MaterialShaders, Rc<ShaderProgram>>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, path: &str) -> Option<Rc<Material>> {
+        self.materials.get(path).cloned()
+    }
+
+    /// Load the material file at `path`, or return it if it's already loaded.
+    /// Textures it names are loaded through `textures`.
+    pub fn load(&mut self, path: &str, textures: &mut TextureLoader) -> Result<Rc<Material>, Box<dyn Error>> {
+        self.load_inner(path, textures, &mut Vec::new())
+    }
+
+    fn load_inner(&mut self, path: &str, textures: &mut TextureLoader, stack: &mut Vec<String>) -> Result<Rc<Material>, Box<dyn Error>> {
+        if let Some(material) = self.get(path) {
+            return Ok(material);
+        }
+        if stack.iter().any(|parent| parent == path) {
+            return Err(format!("Material {} is its own parent: {} -> {}", path, stack.join(" -> "), path).into());
+        }
+
+        let description = MaterialDescription::load(path)
+            .map_err(|e| format!("Failed to load material {}: {}", path, e))?;
+
+        let mut material = match (&description.parent, &description.shader) {
+            (Some(parent), _) => {
+                let parent_path = Path::new(path).with_file_name(parent);
+
+                stack.push(path.to_string());
+                let parent = self.load_inner(&parent_path.to_string_lossy(), textures, stack)?;
+                stack.pop();
+
+                Material::instance(&parent, &description.name)
+            },
+            (None, Some(shaders)) => Material::new(&description.name, self.shader_program(shaders)?),
+            (None, None) => return Err(format!("Material {} needs a shader or a parent", path).into()),
+        };
+
+        for (name, data) in description.uniforms.iter() {
+            let value = UniformValue::try_from(data)
+                .map_err(|e| format!("Uniform {} in material {}: {}", name, path, e))?;
+            material.set_uniform(name, value);
+        }
+
+        for (sampler, texture_path) in description.textures.iter() {
+            material.set_texture(sampler, Self::texture(texture_path, textures)?);
+        }
+
+        let material = Rc::new(material);
+        self.materials.insert(path.to_string(), material.clone());
+        Ok(material)
+    }
+
+    fn shader_program(&mut self, shaders: &MaterialShaders) -> Result<Rc<ShaderProgram>, Box<dyn Error>> {
+        if let Some(program) = self.shaders.get(shaders) {
+            return Ok(program.clone());
+        }
+
+        let vertex = Shader::from_file(ShaderType::Vertex, &shaders.vertex)?;
+        let fragment = match Shader::from_file(ShaderType::Fragment, &shaders.fragment) {
+            Ok(fragment) => fragment,
+            Err(error) => {
+                vertex.delete();
+                return Err(error);
+            },
+        };
+
+        let program = ShaderProgram::new(&[vertex, fragment])?;
+        program.link()?;
+
+        let program = Rc::new(program);
+        self.shaders.insert(shaders.clone(), program.clone());
+        Ok(program)
+    }
+
+    fn texture(texture_path: &str, textures: &mut TextureLoader) -> Result<Rc<Texture>, Box<dyn Error>> {
+        match textures.get_texture(texture_path) {
+            Some(texture) => Ok(texture),
+            None => textures.load_texture(texture_path),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra_glm::{Vec2, Vec3, Vec4};
+    use sdl2::pixels::PixelFormatEnum;
+    use crate::gl_backend::recording::{GlCall, RecordingGl};
+
+    fn program() -> Rc<ShaderProgram> {
+        let vertex = Shader::new(ShaderType::Vertex, String::from("void main() {}")).unwrap();
+        let fragment = Shader::new(ShaderType::Fragment, String::from("void main() {}")).unwrap();
+        Rc::new(ShaderProgram::new(&[vertex, fragment]).unwrap())
+    }
+
+    fn texture() -> Rc<Texture> {
+        Rc::new(Texture {
+            id: gl_backend::current().gen_texture(),
+            width: 1,
+            height: 1,
+            format: PixelFormatEnum::RGBA32,
+        })
+    }
+
+    #[test]
+    fn instances_fall_back_to_their_parent() {
+        let _gl = RecordingGl::install();
+        let (diffuse, normal) = (texture(), texture());
+        let parent = Rc::new(Material::new("Base", program())
+            .with_uniform("u_Color", UniformValue::Vec3(Vec3::new(1.0, 0.0, 0.0)))
+            .with_uniform("u_Shininess", UniformValue::Float(8.0))
+            .with_texture("u_Diffuse", diffuse.clone()));
+
+        let mut instance = Material::instance(&parent, "Shiny")
+            .with_uniform("u_Shininess", UniformValue::Float(64.0))
+            .with_texture("u_Normal", normal.clone());
+
+        assert_eq!(instance.uniform("u_Color"), Some(&UniformValue::Vec3(Vec3::new(1.0, 0.0, 0.0))));
+        assert_eq!(instance.uniform("u_Shininess"), Some(&UniformValue::Float(64.0)));
+        assert_eq!(instance.uniform("u_Missing"), None);
+        assert!(instance.overrides_uniform("u_Shininess"));
+        assert!(!instance.overrides_uniform("u_Color"));
+        assert!(Rc::ptr_eq(instance.texture("u_Diffuse").unwrap(), &diffuse));
+        assert!(Rc::ptr_eq(instance.texture("u_Normal").unwrap(), &normal));
+        assert!(parent.texture("u_Normal").is_none());
+
+        instance.clear_uniform("u_Shininess");
+        assert_eq!(instance.uniform("u_Shininess"), Some(&UniformValue::Float(8.0)));
+    }
+
+    #[test]
+    fn resolved_parameters_prefer_the_closest_override() {
+        let _gl = RecordingGl::install();
+        let (base_albedo, base_normal, rock_normal, wet_albedo) = (texture(), texture(), texture(), texture());
+        let base = Rc::new(Material::new("Base", program())
+            .with_uniform("u_A", UniformValue::Int(1))
+            .with_uniform("u_B", UniformValue::Int(1))
+            .with_uniform("u_C", UniformValue::Int(1))
+            .with_texture("u_Albedo", base_albedo.clone())
+            .with_texture("u_Normal", base_normal.clone()));
+        let rock = Rc::new(Material::instance(&base, "Rock")
+            .with_uniform("u_B", UniformValue::Int(2))
+            .with_uniform("u_C", UniformValue::Int(2))
+            .with_texture("u_Normal", rock_normal.clone()));
+        let wet_rock = Material::instance(&rock, "Wet rock")
+            .with_uniform("u_C", UniformValue::Int(3))
+            .with_texture("u_Albedo", wet_albedo.clone());
+
+        let uniforms = wet_rock.resolved_uniforms();
+        assert_eq!(uniforms.into_iter().collect::<Vec<_>>(), vec![
+            ("u_A", &UniformValue::Int(1)),
+            ("u_B", &UniformValue::Int(2)),
+            ("u_C", &UniformValue::Int(3)),
+        ]);
+
+        // Textures bind to units in sampler name order.
+        assert_eq!(wet_rock.texture_ids(), vec![wet_albedo.id, rock_normal.id]);
+        assert_eq!(rock.texture_ids(), vec![base_albedo.id, rock_normal.id]);
+    }
+
+    #[test]
+    fn texture_keys_follow_the_resolved_textures() {
+        let _gl = RecordingGl::install();
+        let (albedo, other_albedo) = (texture(), texture());
+        let base = Rc::new(Material::new("Base", program()).with_texture("u_Albedo", albedo.clone()));
+
+        let tinted = Material::instance(&base, "Tinted").with_uniform("u_Tint", UniformValue::Float(0.5));
+        let same_texture = Material::new("Other", program()).with_texture("u_Albedo", albedo.clone());
+        let retextured = Material::instance(&base, "Retextured").with_texture("u_Albedo", other_albedo);
+
+        assert_eq!(tinted.texture_key(), base.texture_key());
+        assert_eq!(same_texture.texture_key(), base.texture_key());
+        assert_ne!(retextured.texture_key(), base.texture_key());
+    }
+
+    #[test]
+    fn binding_points_samplers_at_their_units() {
+        let gl = RecordingGl::install();
+        let (albedo, normal) = (texture(), texture());
+        let material = Material::new("Base", program())
+            .with_texture("u_Albedo", albedo.clone())
+            .with_texture("u_Normal", normal.clone());
+
+        gl.take_calls();
+        material.bind_textures();
+        material.apply_uniforms();
+
+        let samplers: Vec<UniformValue> = gl.calls().into_iter().filter_map(|call| match call {
+            GlCall::Uniform { value, .. } => Some(value),
+            _ => None,
+        }).collect();
+        assert_eq!(samplers, vec![UniformValue::Int(0), UniformValue::Int(1)]);
+        assert_eq!(gl.calls()[..4], [
+            GlCall::ActiveTexture(gl::TEXTURE0),
+            GlCall::BindTexture { target: gl::TEXTURE_2D, texture: albedo.id },
+            GlCall::ActiveTexture(gl::TEXTURE1),
+            GlCall::BindTexture { target: gl::TEXTURE_2D, texture: normal.id },
+        ]);
+    }
+
+    #[test]
+    fn uniform_data_converts_by_shape() {
+        let convert = |data: UniformData| UniformValue::try_from(&data);
+
+        assert_eq!(convert(UniformData::Int(3)), Ok(UniformValue::Int(3)));
+        assert_eq!(convert(UniformData::Float(0.5)), Ok(UniformValue::Float(0.5)));
+        assert_eq!(convert(UniformData::Array(vec![1.0, 2.0])), Ok(UniformValue::Vec2(Vec2::new(1.0, 2.0))));
+        assert_eq!(convert(UniformData::Array(vec![1.0, 2.0, 3.0])), Ok(UniformValue::Vec3(Vec3::new(1.0, 2.0, 3.0))));
+        assert_eq!(convert(UniformData::Array(vec![1.0, 2.0, 3.0, 4.0])), Ok(UniformValue::Vec4(Vec4::new(1.0, 2.0, 3.0, 4.0))));
+
+        let identity: Vec<f32> = glm::Mat4::identity().as_slice().to_vec();
+        assert_eq!(convert(UniformData::Array(identity)), Ok(UniformValue::Mat4(glm::Mat4::identity())));
+
+        assert_eq!(convert(UniformData::Array(vec![1.0; 5])), Err("Uniform arrays need 2, 3, 4 or 16 values, found 5".to_string()));
+    }
+
+    #[test]
+    fn material_files_read_whole_numbers_as_ints() {
+        let description: MaterialDescription = toml::from_str(r#"
+            name = "Grass"
+            parent = "base.toml"
+
+            [uniforms]
+            u_Count = 2
+            u_Scale = 2.0
+            u_Color = [0.5, 0.0, 0.7]
+        "#).unwrap();
+
+        assert_eq!(description.uniforms["u_Count"], UniformData::Int(2));
+        assert_eq!(description.uniforms["u_Scale"], UniformData::Float(2.0));
+        assert_eq!(description.uniforms["u_Color"], UniformData::Array(vec![0.5, 0.0, 0.7]));
+    }
+
+    #[test]
+    fn parent_cycles_are_an_error() {
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("a.toml"), "name = \"A\"\nparent = \"b.toml\"\n").unwrap();
+        fs::write(directory.path().join("b.toml"), "name = \"B\"\nparent = \"a.toml\"\n").unwrap();
+
+        let path = directory.path().join("a.toml").to_string_lossy().into_owned();
+        let mut library = MaterialLibrary::new();
+        let Err(error) = library.load(&path, &mut TextureLoader::new()) else {
+            panic!("Loaded a material that is its own parent");
+        };
+
+        assert!(error.to_string().starts_with(&format!("Material {} is its own parent", path)), "{}", error);
+        assert!(library.get(&path).is_none());
+    }
+}
@@ -0,0 +1,495 @@
+use crate::game::services::input_actions::ActionsService;
+use crate::game::services::{OwnerId, ServiceManager};
+use crate::game::world::World;
+use crate::game::GameObjectRegistry;
+use async_std::sync::RwLock;
+use async_std::task;
+use libloading::Library;
+use log::{error, info, warn};
+use plugin_abi::{PluginStatus, PluginVTable, RegisterFn, PLUGIN_ABI_VERSION, PLUGIN_VTABLE_SYMBOL};
+use plugin_errors::PluginError;
+use plugin_host::{PluginCall, PluginContext, PluginServices};
+use plugin_manifest::{PluginManifest, MANIFEST_FILE_NAME};
+use semver::Version;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+pub mod plugin_abi;
+pub mod plugin_errors;
+pub mod plugin_host;
+pub mod plugin_manifest;
+
+pub fn engine_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).unwrap()
+}
+
+struct LoadedPlugin {
+    manifest: PluginManifest,
+    /// Everything the plugin registers is owned by this id, so it can all be removed on unload.
+    owner: OwnerId,
+    /// Holds the plugin's `PluginHost`.
+    context: Arc<PluginContext>,
+    vtable: *const PluginVTable,
+    // Dropped last so `vtable` never outlives the library it points into. Shared with the
+    // plugin's services and game objects, so their code stays loaded as long as they exist.
+    library: Arc<Library>,
+}
+
+/// Discovers, loads and unloads the plugins in a plugins directory.
+/// Every plugin lives in its own subdirectory next to a `plugin.toml` manifest.
+///
+/// Plugins reach the engine only through the `repr(C)` `PluginHost` table. Their services live in
+/// `PluginServices`, which is added to the service manager here.
+pub struct PluginManager {
+    services: Arc<RwLock<ServiceManager>>,
+    game_objects: GameObjectRegistry,
+    plugins: Vec<LoadedPlugin>,
+}
+
+impl PluginManager {
+    pub fn new(services: Arc<RwLock<ServiceManager>>) -> PluginManager {
+        {
+            let mut services = task::block_on(services.write());
+            if !services.contains::<PluginServices>() {
+                services.add_service("PluginServices", PluginServices::default());
+            }
+        }
+
+        Self {
+            services,
+            game_objects: GameObjectRegistry::new(),
+            plugins: Vec::new(),
+        }
+    }
+
+    pub fn game_objects(&self) -> &GameObjectRegistry {
+        &self.game_objects
+    }
+
+    pub fn loaded_plugins(&self) -> impl Iterator<Item = &PluginManifest> {
+        self.plugins.iter().map(|plugin| &plugin.manifest)
+    }
+
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.plugins.iter().any(|plugin| plugin.manifest.name == name)
+    }
+
+    /// Read the manifest of every subdirectory of `directory` that has one.
+    pub fn discover<P: AsRef<Path>>(directory: P) -> (Vec<PluginManifest>, Vec<PluginError>) {
+        let mut manifests = Vec::new();
+        let mut errors = Vec::new();
+
+        let entries = match fs::read_dir(directory.as_ref()) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push(PluginError::InvalidManifest {
+                    path: directory.as_ref().display().to_string(),
+                    reason: e.to_string(),
+                });
+                return (manifests, errors);
+            },
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !path.join(MANIFEST_FILE_NAME).is_file() {
+                continue;
+            }
+
+            match PluginManifest::load(&path) {
+                Ok(manifest) => manifests.push(manifest),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        manifests.sort_by(|a, b| a.name.cmp(&b.name));
+        (manifests, errors)
+    }
+
+    /// Load every plugin in `directory`, dependencies first.
+    /// Plugins that can't be loaded are skipped along with everything that depends on them,
+    /// and the reasons are returned and logged.
+    pub fn load_all<P: AsRef<Path>>(&mut self, directory: P) -> Vec<PluginError> {
+        let (manifests, mut errors) = Self::discover(directory);
+
+        let (ordered, order_errors) = Self::resolve_order(manifests);
+        errors.extend(order_errors);
+
+        for manifest in ordered {
+            if let Err(e) = self.load(manifest) {
+                errors.push(e);
+            }
+        }
+
+        for e in errors.iter() {
+            error!("{}", e);
+        }
+
+        errors
+    }
+
+    /// Sort manifests so every plugin comes after its dependencies, dropping the ones whose
+    /// dependencies are missing, mismatched or cyclic.
+    fn resolve_order(manifests: Vec<PluginManifest>) -> (Vec<PluginManifest>, Vec<PluginError>) {
+        let mut errors = Vec::new();
+        let versions: HashMap<String, Version> = manifests
+            .iter()
+            .map(|manifest| (manifest.name.clone(), manifest.version.clone()))
+            .collect();
+
+        let mut pending: HashMap<String, PluginManifest> = HashMap::new();
+        for manifest in manifests {
+            let mismatch = manifest.dependencies.iter().find_map(|(dependency, required)| {
+                match versions.get(dependency) {
+                    None => Some(PluginError::MissingDependency {
+                        plugin: manifest.name.clone(),
+                        dependency: dependency.clone(),
+                        required: required.to_string(),
+                    }),
+                    Some(found) if !required.matches(found) => Some(PluginError::DependencyVersionMismatch {
+                        plugin: manifest.name.clone(),
+                        dependency: dependency.clone(),
+                        required: required.to_string(),
+                        found: found.to_string(),
+                    }),
+                    Some(_) => None,
+                }
+            });
+
+            match mismatch {
+                Some(e) => errors.push(e),
+                None => { pending.insert(manifest.name.clone(), manifest); },
+            }
+        }
+
+        let mut remaining: Vec<PluginManifest> = pending.into_values().collect();
+        remaining.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut ordered: Vec<PluginManifest> = Vec::with_capacity(remaining.len());
+
+        // A dependency that was dropped above counts as satisfied here;
+        // `load` reports it as missing when it gets to the dependent.
+        while !remaining.is_empty() {
+            let next = remaining.iter().position(|manifest| {
+                manifest.dependencies.keys().all(|dependency| {
+                    !remaining.iter().any(|other| other.name == *dependency)
+                })
+            });
+
+            match next {
+                Some(index) => ordered.push(remaining.remove(index)),
+                None => {
+                    errors.push(PluginError::DependencyCycle {
+                        plugins: remaining.iter().map(|manifest| manifest.name.clone()).collect(),
+                    });
+                    break;
+                },
+            }
+        }
+
+        (ordered, errors)
+    }
+
+    /// Load a single plugin. Fails if its dependencies aren't loaded in a matching version.
+    pub fn load(&mut self, manifest: PluginManifest) -> Result<(), PluginError> {
+        let engine_version = engine_version();
+        if !manifest.engine_version.matches(&engine_version) {
+            return Err(PluginError::EngineVersionMismatch {
+                plugin: manifest.name.clone(),
+                required: manifest.engine_version.to_string(),
+                engine: engine_version.to_string(),
+            });
+        }
+
+        self.check_dependencies(&manifest)?;
+
+        let library_error = |e: libloading::Error| PluginError::LibraryLoad {
+            plugin: manifest.name.clone(),
+            reason: e.to_string(),
+        };
+
+        let library = Arc::new(unsafe { Library::new(manifest.library_path()).map_err(library_error)? });
+        let vtable = unsafe {
+            *library.get::<*const PluginVTable>(PLUGIN_VTABLE_SYMBOL).map_err(library_error)?
+        };
+
+        let abi_version = unsafe { (*vtable).abi_version };
+        if abi_version != PLUGIN_ABI_VERSION {
+            return Err(PluginError::AbiVersionMismatch {
+                plugin: manifest.name.clone(),
+                found: abi_version,
+                expected: PLUGIN_ABI_VERSION,
+            });
+        }
+
+        let plugin = LoadedPlugin {
+            owner: OwnerId::unique(),
+            context: PluginContext::new(&manifest.name),
+            manifest,
+            vtable,
+            library,
+        };
+
+        let register = unsafe { (*plugin.vtable).register };
+        if let Err(e) = self.call_plugin(&plugin, register) {
+            // Take back whatever was registered before the failure, then let the library unload.
+            self.remove_registrations(&plugin);
+            return Err(e);
+        }
+
+        info!("Loaded plugin {} {}", plugin.manifest.name, plugin.manifest.version);
+        self.plugins.push(plugin);
+        Ok(())
+    }
+
+    fn check_dependencies(&self, manifest: &PluginManifest) -> Result<(), PluginError> {
+        for (dependency, required) in manifest.dependencies.iter() {
+            let loaded = self.loaded_plugins().find(|loaded| loaded.name == *dependency);
+            match loaded {
+                None => return Err(PluginError::MissingDependency {
+                    plugin: manifest.name.clone(),
+                    dependency: dependency.clone(),
+                    required: required.to_string(),
+                }),
+                Some(loaded) if !required.matches(&loaded.version) => return Err(PluginError::DependencyVersionMismatch {
+                    plugin: manifest.name.clone(),
+                    dependency: dependency.clone(),
+                    required: required.to_string(),
+                    found: loaded.version.to_string(),
+                }),
+                Some(_) => {},
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Call into the plugin. Panics are caught on the plugin's side by `export_plugin!`, and in
+    /// the plugin's services and game objects by `PluginRegistrar`.
+    fn call_plugin(&mut self, plugin: &LoadedPlugin, function: RegisterFn) -> Result<(), PluginError> {
+        let name = plugin.manifest.name.clone();
+        let mut services = task::block_on(self.services.write());
+        let mut call = PluginCall {
+            name: &name,
+            owner: plugin.owner,
+            context: &plugin.context,
+            library: &plugin.library,
+            services: &mut services,
+            game_objects: &mut self.game_objects,
+            error: None,
+        };
+
+        let status = plugin.context.enter(&mut call, function);
+        let message = call.error.take().unwrap_or_default();
+
+        match status {
+            PluginStatus::Ok => Ok(()),
+            PluginStatus::Failed => Err(PluginError::RegistrationFailed { plugin: name, reason: message }),
+            PluginStatus::Panicked => Err(PluginError::Panicked { plugin: name, message }),
+        }
+    }
+
+    /// Remove everything the plugin registered. Its services are shut down on the way out.
+    fn remove_registrations(&mut self, plugin: &LoadedPlugin) {
+        let (actions, plugin_services) = {
+            let services = task::block_on(self.services.read());
+            (services.get::<ActionsService>(), services.get::<PluginServices>())
+        };
+
+        let mut removed = 0;
+        if let Some(actions) = actions {
+            removed += task::block_on(actions.write()).unregister_owner(plugin.owner).len();
+        }
+
+        if let Some(plugin_services) = plugin_services {
+            removed += task::block_on(plugin_services.write()).remove_owner(plugin.owner);
+        }
+
+        removed += self.game_objects.unregister_owner(plugin.owner);
+
+        if removed > 0 {
+            info!("Removed {} registration(s) of plugin {}", removed, plugin.manifest.name);
+        }
+    }
+
+    /// Unload the plugin called `name`, first despawning every object in `world` created from
+    /// a type it registered. Fails if another loaded plugin still depends on it.
+    pub fn unload(&mut self, name: &str, world: &mut World) -> Result<(), PluginError> {
+        self.unload_plugin(name, Some(world))
+    }
+
+    fn unload_plugin(&mut self, name: &str, world: Option<&mut World>) -> Result<(), PluginError> {
+        if let Some(dependent) = self.plugins.iter().find(|plugin| plugin.manifest.dependencies.contains_key(name)) {
+            return Err(PluginError::StillRequired {
+                plugin: name.to_string(),
+                dependent: dependent.manifest.name.clone(),
+            });
+        }
+
+        let Some(index) = self.plugins.iter().position(|plugin| plugin.manifest.name == name) else {
+            return Ok(());
+        };
+
+        let plugin = self.plugins.remove(index);
+        self.shut_down(plugin, world)
+    }
+
+    /// Unregister a plugin that's already been taken out of `plugins`, and let its library unload.
+    fn shut_down(&mut self, plugin: LoadedPlugin, world: Option<&mut World>) -> Result<(), PluginError> {
+        if let Some(world) = world {
+            world.despawn_owned(plugin.owner);
+        }
+
+        let unregister = unsafe { (*plugin.vtable).unregister };
+        let result = self.call_plugin(&plugin, unregister);
+        self.remove_registrations(&plugin);
+
+        if Arc::strong_count(&plugin.library) > 1 {
+            warn!("Objects created by plugin {} are still alive; its library stays loaded until they are gone", plugin.manifest.name);
+        }
+
+        info!("Unloaded plugin {}", plugin.manifest.name);
+        result
+    }
+
+    /// Unload every plugin, dependents before their dependencies, despawning their objects in `world`.
+    pub fn unload_all(&mut self, world: &mut World) {
+        self.unload_all_plugins(Some(world));
+    }
+
+    fn unload_all_plugins(&mut self, mut world: Option<&mut World>) {
+        // `load` only accepts plugins whose dependencies are loaded, so no plugin depends on a later one.
+        while let Some(plugin) = self.plugins.pop() {
+            if let Err(e) = self.shut_down(plugin, world.as_deref_mut()) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+impl Drop for PluginManager {
+    fn drop(&mut self) {
+        self.unload_all_plugins(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use semver::VersionReq;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    fn manifest(name: &str, version: &str, dependencies: &[(&str, &str)]) -> PluginManifest {
+        PluginManifest {
+            name: name.to_string(),
+            version: Version::parse(version).unwrap(),
+            engine_version: VersionReq::parse(&format!("={}", engine_version())).unwrap(),
+            library: None,
+            dependencies: dependencies
+                .iter()
+                .map(|(name, required)| (name.to_string(), VersionReq::parse(required).unwrap()))
+                .collect::<BTreeMap<_, _>>(),
+            directory: PathBuf::from("does/not/exist"),
+        }
+    }
+
+    #[test]
+    fn load_rejects_plugins_whose_dependencies_are_not_loaded() {
+        let mut plugins = PluginManager::new(ServiceManager::new_shared());
+
+        let result = plugins.load(manifest("physics", "0.2.0", &[("math", "^1.0")]));
+
+        assert!(matches!(result, Err(PluginError::MissingDependency { ref dependency, .. }) if dependency == "math"), "{:?}", result);
+        assert!(!plugins.is_loaded("physics"));
+    }
+
+    fn names(manifests: &[PluginManifest]) -> Vec<&str> {
+        manifests.iter().map(|manifest| manifest.name.as_str()).collect()
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let (ordered, errors) = PluginManager::resolve_order(vec![
+            manifest("game", "1.0.0", &[("physics", "^0.2"), ("audio", "^1")]),
+            manifest("physics", "0.2.1", &[("math", "^1.0")]),
+            manifest("math", "1.3.0", &[]),
+            manifest("audio", "1.0.0", &[]),
+        ]);
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(names(&ordered), vec!["audio", "math", "physics", "game"]);
+    }
+
+    #[test]
+    fn cycles_are_reported_and_left_out() {
+        let (ordered, errors) = PluginManager::resolve_order(vec![
+            manifest("a", "1.0.0", &[("b", "*")]),
+            manifest("b", "1.0.0", &[("a", "*")]),
+            manifest("c", "1.0.0", &[]),
+        ]);
+
+        assert_eq!(names(&ordered), vec!["c"]);
+        assert!(matches!(&errors[..], [PluginError::DependencyCycle { plugins }] if plugins == &["a", "b"]), "{:?}", errors);
+    }
+
+    #[test]
+    fn missing_and_mismatched_dependencies_are_reported() {
+        let (ordered, errors) = PluginManager::resolve_order(vec![
+            manifest("physics", "0.2.0", &[("math", "^2.0")]),
+            manifest("math", "1.3.0", &[]),
+            manifest("audio", "1.0.0", &[("mixer", "^1")]),
+        ]);
+
+        assert_eq!(names(&ordered), vec!["math"]);
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(errors.iter().any(|e| matches!(e,
+            PluginError::DependencyVersionMismatch { plugin, found, .. } if plugin == "physics" && found == "1.3.0")), "{:?}", errors);
+        assert!(errors.iter().any(|e| matches!(e,
+            PluginError::MissingDependency { plugin, dependency, .. } if plugin == "audio" && dependency == "mixer")), "{:?}", errors);
+    }
+
+    #[test]
+    fn load_rejects_other_engine_versions() {
+        let mut plugins = PluginManager::new(ServiceManager::new_shared());
+        let mut physics = manifest("physics", "0.2.0", &[]);
+        physics.engine_version = VersionReq::parse(">1000.0").unwrap();
+
+        let result = plugins.load(physics);
+
+        assert!(matches!(result, Err(PluginError::EngineVersionMismatch { ref engine, .. }) if *engine == engine_version().to_string()), "{:?}", result);
+    }
+
+    #[test]
+    fn manifests_are_read_from_plugin_directories() {
+        let directory = tempfile::tempdir().unwrap();
+        let physics = directory.path().join("physics");
+        fs::create_dir(&physics).unwrap();
+        fs::write(physics.join(MANIFEST_FILE_NAME), r#"
+            name = "physics"
+            version = "0.2.0"
+            engine_version = "^0.1"
+            library = "physics_rs"
+
+            [dependencies]
+            math = "^1.0"
+        "#).unwrap();
+
+        let broken = directory.path().join("broken");
+        fs::create_dir(&broken).unwrap();
+        fs::write(broken.join(MANIFEST_FILE_NAME), "name = \"broken\"").unwrap();
+        fs::create_dir(directory.path().join("not_a_plugin")).unwrap();
+
+        let (manifests, errors) = PluginManager::discover(directory.path());
+
+        assert_eq!(names(&manifests), vec!["physics"]);
+        let physics_manifest = &manifests[0];
+        assert_eq!(physics_manifest.version, Version::new(0, 2, 0));
+        assert_eq!(physics_manifest.dependencies["math"], VersionReq::parse("^1.0").unwrap());
+        assert_eq!(physics_manifest.directory, physics);
+        assert_eq!(physics_manifest.library_path(), physics.join(libloading::library_filename("physics_rs")));
+
+        assert!(matches!(&errors[..], [PluginError::InvalidManifest { path, .. }] if path.contains("broken")), "{:?}", errors);
+    }
+}
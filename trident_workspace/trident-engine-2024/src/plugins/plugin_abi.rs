@@ -0,0 +1,460 @@
+use crate::game::services::Service;
+use crate::game_loop::FrameContext;
+use crate::hot_reload::{ActionCallbackFn, ClientActionEvent, ClientFrame};
+use log::Level;
+use std::any::Any;
+use std::error::Error;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Bumped whenever `PluginVTable`, `PluginHost` or any `repr(C)` type they use changes in a way
+/// that breaks built plugins.
+pub const PLUGIN_ABI_VERSION: u32 = 3;
+
+/// Name of the `PluginVTable` static every plugin library exports. See `export_plugin!`.
+pub const PLUGIN_VTABLE_SYMBOL: &[u8] = b"trident_plugin_vtable";
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginStatus {
+    Ok = 0,
+    Failed = 1,
+    Panicked = 2,
+}
+
+pub type RegisterFn = extern "C" fn(host: *const PluginHost) -> PluginStatus;
+pub type UnregisterFn = extern "C" fn(host: *const PluginHost) -> PluginStatus;
+
+/// The table a plugin library exports under `PLUGIN_VTABLE_SYMBOL`.
+/// `abi_version` comes first so the engine can always read it, whatever the rest looks like.
+#[repr(C)]
+pub struct PluginVTable {
+    pub abi_version: u32,
+    pub register: RegisterFn,
+    pub unregister: UnregisterFn,
+}
+
+/// The engine side of a plugin call. Opaque to the plugin, which only ever passes it back to the
+/// functions in `PluginHost`.
+#[repr(C)]
+pub struct PluginHandle {
+    _private: [u8; 0],
+}
+
+/// A service implemented by a plugin. `state` belongs to the plugin and is passed back to every
+/// function. The engine calls `shutdown` exactly once, when the plugin is unloaded or the services
+/// are shut down, and never touches `state` afterwards.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginServiceVTable {
+    pub state: *mut c_void,
+    pub update: extern "C" fn(state: *mut c_void, frame: *const ClientFrame),
+    pub fixed_update: extern "C" fn(state: *mut c_void, frame: *const ClientFrame),
+    pub shutdown: extern "C" fn(state: *mut c_void),
+}
+
+/// A game object type implemented by a plugin. `create` returns a new object, or null on failure;
+/// the engine hands it to `update` every frame and to `destroy` once when it's despawned. `create`
+/// gets the plugin's `PluginHost`, e.g. to log from the object later.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginGameObjectVTable {
+    pub create: extern "C" fn(host: *const PluginHost) -> *mut c_void,
+    pub update: extern "C" fn(object: *mut c_void, dt: f32),
+    pub destroy: extern "C" fn(object: *mut c_void),
+}
+
+/// What the engine offers a plugin. Strings are NUL-terminated UTF-8 and only borrowed for the
+/// length of the call. Everything registered through it is removed again when the plugin is unloaded.
+///
+/// The host stays valid for as long as anything the plugin registered is alive, so services and game
+/// objects can keep it to `log`. The other functions only work while the plugin's register or
+/// unregister function runs, and fail otherwise.
+#[repr(C)]
+pub struct PluginHost {
+    pub handle: *mut PluginHandle,
+    pub plugin_name: *const c_char,
+    /// Add a service under `name`. Fails if the plugin already added one with that name.
+    pub add_service: extern "C" fn(
+        handle: *mut PluginHandle,
+        name: *const c_char,
+        service: *const PluginServiceVTable) -> bool,
+    /// Register an action bound to a single button; see `ClientHost::register_action`.
+    pub register_action: extern "C" fn(
+        handle: *mut PluginHandle,
+        name: *const c_char,
+        button: *const c_char,
+        description: *const c_char,
+        callback: ActionCallbackFn,
+        user_data: *mut c_void) -> bool,
+    pub register_game_object: extern "C" fn(
+        handle: *mut PluginHandle,
+        type_name: *const c_char,
+        game_object: *const PluginGameObjectVTable) -> bool,
+    /// Log through the engine's logger. `level` is a `log::Level` as a number, 1 (error) to 5 (trace).
+    pub log: extern "C" fn(handle: *mut PluginHandle, level: u32, message: *const c_char),
+    /// Set the message reported along with a `Failed` or `Panicked` status.
+    pub set_error: extern "C" fn(handle: *mut PluginHandle, message: *const c_char),
+}
+
+/// A game object type a plugin can register. Plugin objects only see their own frame delta;
+/// they have no `WorldContext`, which can't cross the library boundary.
+pub trait PluginObject: Default + 'static {
+    fn update(&mut self, dt: f32);
+}
+
+/// Safe access to a `PluginHost` for plugin code. Handed to the functions given to `export_plugin!`.
+pub struct PluginRegistrar<'a> {
+    host: &'a PluginHost,
+}
+
+impl<'a> PluginRegistrar<'a> {
+    /// # Safety
+    /// `host` must be the pointer the engine passed to the register or unregister function
+    /// currently running.
+    pub unsafe fn from_ptr(host: *const PluginHost) -> Option<PluginRegistrar<'a>> {
+        host.as_ref().map(|host| Self { host })
+    }
+
+    pub fn plugin_name(&self) -> &str {
+        unsafe { CStr::from_ptr(self.host.plugin_name) }.to_str().unwrap_or_default()
+    }
+
+    /// Add `service` under `name`. It gets `update`, `fixed_update` and `shutdown` calls;
+    /// input events aren't forwarded to plugin services. `init` runs right away.
+    /// Panics in the service are caught and logged through the host.
+    pub fn add_service<T: Service + Send + 'static>(&mut self, name: &str, mut service: T) -> Result<(), Box<dyn Error>> {
+        struct State<T> {
+            service: T,
+            name: String,
+            host: *const PluginHost,
+        }
+
+        extern "C" fn update<T: Service>(state: *mut c_void, frame: *const ClientFrame) {
+            let state = unsafe { &mut *(state as *mut State<T>) };
+            let frame = FrameContext::from(unsafe { &*frame });
+            guard(state.host, &state.name, "update", || state.service.update(&frame));
+        }
+
+        extern "C" fn fixed_update<T: Service>(state: *mut c_void, frame: *const ClientFrame) {
+            let state = unsafe { &mut *(state as *mut State<T>) };
+            let frame = FrameContext::from(unsafe { &*frame });
+            guard(state.host, &state.name, "fixed_update", || state.service.fixed_update(&frame));
+        }
+
+        extern "C" fn shutdown<T: Service>(state: *mut c_void) {
+            let mut state = unsafe { Box::from_raw(state as *mut State<T>) };
+            let (host, name) = (state.host, std::mem::take(&mut state.name));
+            guard(host, &name, "shutdown", move || {
+                state.service.shutdown();
+                drop(state);
+            });
+        }
+
+        service.init()?;
+
+        let state = State {
+            service,
+            name: name.to_string(),
+            host: self.host,
+        };
+
+        let vtable = PluginServiceVTable {
+            state: Box::into_raw(Box::new(state)) as *mut c_void,
+            update: update::<T>,
+            fixed_update: fixed_update::<T>,
+            shutdown: shutdown::<T>,
+        };
+
+        let name = CString::new(name)?;
+        if !(self.host.add_service)(self.host.handle, name.as_ptr(), &vtable) {
+            shutdown::<T>(vtable.state);
+            return Err(format!("A service called {} has already been added", name.to_string_lossy()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Register an action bound to a single button, written like `"Key:Space"`.
+    pub fn register_action(
+        &mut self,
+        name: &str,
+        button: &str,
+        description: Option<&str>,
+        callback: fn(&ClientActionEvent)) -> Result<(), Box<dyn Error>>
+    {
+        extern "C" fn call(user_data: *mut c_void, event: *const ClientActionEvent) {
+            let callback: fn(&ClientActionEvent) = unsafe { std::mem::transmute(user_data) };
+            let _ = catch_unwind(|| callback(unsafe { &*event }));
+        }
+
+        let (name, button) = (CString::new(name)?, CString::new(button)?);
+        let description = description.map(CString::new).transpose()?;
+        let description_ptr = description.as_ref().map_or(std::ptr::null(), |description| description.as_ptr());
+
+        let registered = (self.host.register_action)(
+            self.host.handle,
+            name.as_ptr(),
+            button.as_ptr(),
+            description_ptr,
+            call,
+            callback as *mut c_void);
+
+        if !registered {
+            return Err(format!("Failed to register action {}", name.to_string_lossy()).into());
+        }
+
+        Ok(())
+    }
+
+    /// Register `T` as a game object type. Panics in its methods are caught and logged through
+    /// the host; an object whose `default` panics is never created.
+    pub fn register_game_object<T: PluginObject>(&mut self, type_name: &str) -> Result<(), Box<dyn Error>> {
+        struct State<T> {
+            object: T,
+            host: *const PluginHost,
+        }
+
+        extern "C" fn create<T: PluginObject>(host: *const PluginHost) -> *mut c_void {
+            let mut object = None;
+            guard(host, std::any::type_name::<T>(), "default", || object = Some(T::default()));
+
+            match object {
+                Some(object) => Box::into_raw(Box::new(State { object, host })) as *mut c_void,
+                None => std::ptr::null_mut(),
+            }
+        }
+
+        extern "C" fn update<T: PluginObject>(object: *mut c_void, dt: f32) {
+            let state = unsafe { &mut *(object as *mut State<T>) };
+            guard(state.host, std::any::type_name::<T>(), "update", || state.object.update(dt));
+        }
+
+        extern "C" fn destroy<T: PluginObject>(object: *mut c_void) {
+            let state = unsafe { Box::from_raw(object as *mut State<T>) };
+            guard(state.host, std::any::type_name::<T>(), "drop", move || drop(state));
+        }
+
+        let vtable = PluginGameObjectVTable {
+            create: create::<T>,
+            update: update::<T>,
+            destroy: destroy::<T>,
+        };
+
+        let type_name = CString::new(type_name)?;
+        if !(self.host.register_game_object)(self.host.handle, type_name.as_ptr(), &vtable) {
+            return Err(format!("Failed to register game object type {}", type_name.to_string_lossy()).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn log(&self, level: Level, message: &str) {
+        let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+        (self.host.log)(self.host.handle, level as u32, message.as_ptr());
+    }
+
+    fn set_error(&self, message: &str) {
+        let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+        (self.host.set_error)(self.host.handle, message.as_ptr());
+    }
+}
+
+/// Turn a panic payload into something printable.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+/// Run `function` for `what` of the service or object called `name`, logging a panic through `host`
+/// instead of letting it unwind into the engine, which would abort the process.
+fn guard(host: *const PluginHost, name: &str, what: &str, function: impl FnOnce()) {
+    if let Err(payload) = catch_unwind(AssertUnwindSafe(function)) {
+        if let Some(registrar) = unsafe { PluginRegistrar::from_ptr(host) } {
+            registrar.log(Level::Error, &format!("{} panicked in {}: {}", name, what, panic_message(payload.as_ref())));
+        }
+    }
+}
+
+/// Run a plugin function on the plugin's side of the FFI boundary, turning errors and panics
+/// into a `PluginStatus`. Used by `export_plugin!`.
+///
+/// # Safety
+/// `host` must be the pointer the engine passed to the register or unregister function
+/// currently running.
+pub unsafe fn call_guarded<F>(host: *const PluginHost, function: F) -> PluginStatus
+where F: FnOnce(&mut PluginRegistrar) -> Result<(), Box<dyn Error>>
+{
+    let Some(mut registrar) = PluginRegistrar::from_ptr(host) else {
+        return PluginStatus::Failed;
+    };
+
+    match catch_unwind(AssertUnwindSafe(|| function(&mut registrar))) {
+        Ok(Ok(())) => PluginStatus::Ok,
+        Ok(Err(e)) => {
+            registrar.set_error(&e.to_string());
+            PluginStatus::Failed
+        },
+        Err(payload) => {
+            registrar.set_error(&panic_message(payload.as_ref()));
+            PluginStatus::Panicked
+        },
+    }
+}
+
+/// Export a plugin's vtable. Both functions take `&mut PluginRegistrar` and return
+/// `Result<(), Box<dyn Error>>`:
+///
+/// ```ignore
+/// trident_engine_2024::export_plugin!(register, unregister);
+/// ```
+#[macro_export]
+macro_rules! export_plugin {
+    ($register:path, $unregister:path) => {
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static trident_plugin_vtable: $crate::plugins::plugin_abi::PluginVTable = {
+            extern "C" fn register(
+                host: *const $crate::plugins::plugin_abi::PluginHost
+            ) -> $crate::plugins::plugin_abi::PluginStatus {
+                unsafe { $crate::plugins::plugin_abi::call_guarded(host, $register) }
+            }
+
+            extern "C" fn unregister(
+                host: *const $crate::plugins::plugin_abi::PluginHost
+            ) -> $crate::plugins::plugin_abi::PluginStatus {
+                unsafe { $crate::plugins::plugin_abi::call_guarded(host, $unregister) }
+            }
+
+            $crate::plugins::plugin_abi::PluginVTable {
+                abi_version: $crate::plugins::plugin_abi::PLUGIN_ABI_VERSION,
+                register,
+                unregister,
+            }
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static LOGGED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+        static SERVICE: RefCell<Option<PluginServiceVTable>> = const { RefCell::new(None) };
+        static GAME_OBJECT: RefCell<Option<PluginGameObjectVTable>> = const { RefCell::new(None) };
+    }
+
+    extern "C" fn add_service(_: *mut PluginHandle, _: *const c_char, service: *const PluginServiceVTable) -> bool {
+        SERVICE.with(|added| *added.borrow_mut() = Some(unsafe { *service }));
+        true
+    }
+
+    extern "C" fn register_action(
+        _: *mut PluginHandle,
+        _: *const c_char,
+        _: *const c_char,
+        _: *const c_char,
+        _: ActionCallbackFn,
+        _: *mut c_void) -> bool
+    {
+        false
+    }
+
+    extern "C" fn register_game_object(_: *mut PluginHandle, _: *const c_char, game_object: *const PluginGameObjectVTable) -> bool {
+        GAME_OBJECT.with(|added| *added.borrow_mut() = Some(unsafe { *game_object }));
+        true
+    }
+
+    extern "C" fn log(_: *mut PluginHandle, _: u32, message: *const c_char) {
+        let message = unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+        LOGGED.with(|logged| logged.borrow_mut().push(message));
+    }
+
+    extern "C" fn set_error(_: *mut PluginHandle, _: *const c_char) {}
+
+    fn host() -> PluginHost {
+        PluginHost {
+            handle: std::ptr::null_mut(),
+            plugin_name: c"test".as_ptr(),
+            add_service,
+            register_action,
+            register_game_object,
+            log,
+            set_error,
+        }
+    }
+
+    fn logged() -> Vec<String> {
+        LOGGED.with(|logged| logged.borrow_mut().drain(..).collect())
+    }
+
+    struct PanickingService;
+
+    impl Service for PanickingService {
+        fn update(&mut self, _frame: &FrameContext) {
+            panic!("update failed");
+        }
+
+        fn shutdown(&mut self) {
+            panic!("shutdown failed");
+        }
+    }
+
+    #[derive(Default)]
+    struct PanickingObject;
+
+    impl PluginObject for PanickingObject {
+        fn update(&mut self, _dt: f32) {
+            panic!("object update failed");
+        }
+    }
+
+    impl Drop for PanickingObject {
+        fn drop(&mut self) {
+            panic!("object drop failed");
+        }
+    }
+
+    #[test]
+    fn service_panics_are_logged_instead_of_unwinding() {
+        let host = host();
+        let mut registrar = unsafe { PluginRegistrar::from_ptr(&host) }.unwrap();
+        registrar.add_service("Panicking", PanickingService).unwrap();
+        let service = SERVICE.with(|added| added.borrow_mut().take()).unwrap();
+
+        let frame = ClientFrame { delta_secs: 0.1, total_secs: 0.1, frame_index: 0, alpha: 0.0 };
+        (service.update)(service.state, &frame);
+        (service.fixed_update)(service.state, &frame);
+        (service.shutdown)(service.state);
+
+        assert_eq!(logged(), vec![
+            "Panicking panicked in update: update failed".to_string(),
+            "Panicking panicked in shutdown: shutdown failed".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn game_object_panics_are_logged_instead_of_unwinding() {
+        let host = host();
+        let mut registrar = unsafe { PluginRegistrar::from_ptr(&host) }.unwrap();
+        registrar.register_game_object::<PanickingObject>("Panicking").unwrap();
+        let game_object = GAME_OBJECT.with(|added| added.borrow_mut().take()).unwrap();
+
+        let object = (game_object.create)(&host);
+        assert!(!object.is_null());
+        (game_object.update)(object, 0.1);
+        (game_object.destroy)(object);
+
+        let logged = logged();
+        assert_eq!(logged.len(), 2, "{:?}", logged);
+        assert!(logged[0].ends_with("PanickingObject panicked in update: object update failed"), "{}", logged[0]);
+        assert!(logged[1].ends_with("PanickingObject panicked in drop: object drop failed"), "{}", logged[1]);
+    }
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::fmt::Display;
+
+/// Why a plugin could not be loaded, registered or unloaded.
+#[derive(Debug)]
+pub enum PluginError {
+    InvalidManifest { path: String, reason: String },
+    EngineVersionMismatch { plugin: String, required: String, engine: String },
+    AbiVersionMismatch { plugin: String, found: u32, expected: u32 },
+    MissingDependency { plugin: String, dependency: String, required: String },
+    DependencyVersionMismatch { plugin: String, dependency: String, required: String, found: String },
+    DependencyCycle { plugins: Vec<String> },
+    StillRequired { plugin: String, dependent: String },
+    LibraryLoad { plugin: String, reason: String },
+    RegistrationFailed { plugin: String, reason: String },
+    Panicked { plugin: String, message: String },
+}
+
+impl Display for PluginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginError::InvalidManifest { path, reason } => {
+                write!(f, "Invalid plugin manifest {}: {}", path, reason)
+            },
+            PluginError::EngineVersionMismatch { plugin, required, engine } => {
+                write!(f, "Plugin {} requires engine version {}, but this is {}", plugin, required, engine)
+            },
+            PluginError::AbiVersionMismatch { plugin, found, expected } => {
+                write!(f, "Plugin {} was built for plugin ABI version {}, but the engine expects {}", plugin, found, expected)
+            },
+            PluginError::MissingDependency { plugin, dependency, required } => {
+                write!(f, "Plugin {} depends on {} {}, which is not installed", plugin, dependency, required)
+            },
+            PluginError::DependencyVersionMismatch { plugin, dependency, required, found } => {
+                write!(f, "Plugin {} requires {} {}, but {} is installed", plugin, dependency, required, found)
+            },
+            PluginError::DependencyCycle { plugins } => {
+                write!(f, "Plugin dependencies form a cycle between: {}", plugins.join(", "))
+            },
+            PluginError::StillRequired { plugin, dependent } => {
+                write!(f, "Plugin {} can't be unloaded while {} depends on it", plugin, dependent)
+            },
+            PluginError::LibraryLoad { plugin, reason } => {
+                write!(f, "Failed to load the library of plugin {}: {}", plugin, reason)
+            },
+            PluginError::RegistrationFailed { plugin, reason } => {
+                write!(f, "Plugin {} reported an error: {}", plugin, reason)
+            },
+            PluginError::Panicked { plugin, message } => {
+                write!(f, "Plugin {} panicked: {}", plugin, message)
+            },
+        }
+    }
+}
+
+impl Error for PluginError {}
@@ -0,0 +1,276 @@
+use crate::game::services::{OwnerId, Service, ServiceManager};
+use crate::game::world::WorldContext;
+use crate::game::{GameObject, GameObjectRegistry};
+use crate::game_loop::FrameContext;
+use crate::hot_reload::{host_str, log_level, register_foreign_action, ActionCallbackFn, ClientCallback, ClientFrame};
+use async_std::task;
+use libloading::Library;
+use std::ffi::{c_char, c_void, CString};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Arc;
+use super::plugin_abi::{
+    PluginGameObjectVTable, PluginHandle, PluginHost, PluginServiceVTable, PluginStatus, RegisterFn
+};
+
+/// A service a plugin added, shut down when it's dropped.
+struct ForeignService {
+    owner: OwnerId,
+    name: String,
+    vtable: PluginServiceVTable,
+    // The service may log through the plugin's host until `shutdown` has run.
+    _context: Arc<PluginContext>,
+    // Keeps the code behind `vtable` loaded until `shutdown` has run.
+    _library: Arc<Library>,
+}
+
+// Plugins promise their services can be used from any thread, like any other `Service`.
+unsafe impl Send for ForeignService {}
+unsafe impl Sync for ForeignService {}
+
+impl Drop for ForeignService {
+    fn drop(&mut self) {
+        (self.vtable.shutdown)(self.vtable.state);
+    }
+}
+
+/// Holds the services plugins add and forwards updates to them. `ServiceManager` tells services
+/// apart by Rust type, which a plugin's services don't share with the engine.
+#[derive(Default)]
+pub struct PluginServices {
+    services: Vec<ForeignService>,
+}
+
+impl PluginServices {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.services.iter().map(|service| service.name.as_str())
+    }
+
+    fn add(&mut self, call: &PluginCall, name: &str, vtable: PluginServiceVTable) -> bool {
+        if self.services.iter().any(|service| service.owner == call.owner && service.name == name) {
+            return false;
+        }
+
+        self.services.push(ForeignService {
+            owner: call.owner,
+            name: name.to_string(),
+            vtable,
+            _context: call.context.clone(),
+            _library: call.library.clone(),
+        });
+        true
+    }
+
+    /// Shut down and remove every service `owner` added. Returns how many there were.
+    pub fn remove_owner(&mut self, owner: OwnerId) -> usize {
+        let count = self.services.len();
+        self.services.retain(|service| service.owner != owner);
+        count - self.services.len()
+    }
+}
+
+impl Service for PluginServices {
+    fn update(&mut self, frame: &FrameContext) {
+        let frame = ClientFrame::from(frame);
+        for service in self.services.iter() {
+            (service.vtable.update)(service.vtable.state, &frame);
+        }
+    }
+
+    fn fixed_update(&mut self, frame: &FrameContext) {
+        let frame = ClientFrame::from(frame);
+        for service in self.services.iter() {
+            (service.vtable.fixed_update)(service.vtable.state, &frame);
+        }
+    }
+
+    fn shutdown(&mut self) {
+        // Dependents were added after their dependencies, so shut down in reverse.
+        while self.services.pop().is_some() {}
+    }
+}
+
+/// A game object created by a plugin.
+struct PluginGameObject {
+    vtable: PluginGameObjectVTable,
+    object: *mut c_void,
+    _context: Arc<PluginContext>,
+    // Keeps the code behind `vtable` loaded for as long as the object exists.
+    _library: Arc<Library>,
+}
+
+impl GameObject for PluginGameObject {
+    fn update(&mut self, dt: f32, _world: &mut WorldContext) {
+        if !self.object.is_null() {
+            (self.vtable.update)(self.object, dt);
+        }
+    }
+}
+
+impl Drop for PluginGameObject {
+    fn drop(&mut self) {
+        if !self.object.is_null() {
+            (self.vtable.destroy)(self.object);
+        }
+    }
+}
+
+struct PluginGameObjectFactory {
+    vtable: PluginGameObjectVTable,
+    context: Arc<PluginContext>,
+    library: Arc<Library>,
+}
+
+// The factory only calls `create`, which plugins promise can run on any thread.
+unsafe impl Send for PluginGameObjectFactory {}
+unsafe impl Sync for PluginGameObjectFactory {}
+
+impl PluginGameObjectFactory {
+    fn create(&self) -> Box<dyn GameObject> {
+        Box::new(PluginGameObject {
+            vtable: self.vtable,
+            object: (self.vtable.create)(self.context.host()),
+            _context: self.context.clone(),
+            _library: self.library.clone(),
+        })
+    }
+}
+
+/// What `PluginHandle` points at, one per loaded plugin. It lives as long as anything the plugin
+/// registered, since services and game objects keep the host to log through it.
+pub(crate) struct PluginContext {
+    name: String,
+    // `plugin_name` points into this.
+    _c_name: CString,
+    host: PluginHost,
+    /// The `PluginCall` of the register or unregister function running right now, or null.
+    call: AtomicPtr<c_void>,
+}
+
+// `host` only points back into the context, and `call` is only set while the plugin runs.
+unsafe impl Send for PluginContext {}
+unsafe impl Sync for PluginContext {}
+
+impl PluginContext {
+    pub fn new(name: &str) -> Arc<PluginContext> {
+        let c_name = CString::new(name).unwrap_or_default();
+
+        Arc::new_cyclic(|context| PluginContext {
+            name: name.to_string(),
+            host: PluginHost {
+                handle: context.as_ptr() as *mut PluginHandle,
+                plugin_name: c_name.as_ptr(),
+                add_service: host_add_service,
+                register_action: host_register_action,
+                register_game_object: host_register_game_object,
+                log: host_log,
+                set_error: host_set_error,
+            },
+            _c_name: c_name,
+            call: AtomicPtr::new(std::ptr::null_mut()),
+        })
+    }
+
+    pub fn host(&self) -> *const PluginHost {
+        &self.host
+    }
+
+    /// Call `function` with the plugin's host, which registers into `call` until it returns.
+    pub fn enter(&self, call: &mut PluginCall, function: RegisterFn) -> PluginStatus {
+        self.call.store(call as *mut PluginCall as *mut c_void, Ordering::Release);
+        let status = function(&self.host);
+        self.call.store(std::ptr::null_mut(), Ordering::Release);
+        status
+    }
+}
+
+/// What a plugin's register or unregister function registers into.
+pub(crate) struct PluginCall<'a> {
+    pub name: &'a str,
+    pub owner: OwnerId,
+    pub context: &'a Arc<PluginContext>,
+    pub library: &'a Arc<Library>,
+    pub services: &'a mut ServiceManager,
+    pub game_objects: &'a mut GameObjectRegistry,
+    pub error: Option<String>,
+}
+
+unsafe fn plugin_context<'a>(handle: *mut PluginHandle) -> &'a PluginContext {
+    &*(handle as *const PluginContext)
+}
+
+/// The call running right now, or `None` if the plugin uses its host outside one.
+unsafe fn plugin_call<'a>(handle: *mut PluginHandle) -> Option<&'a mut PluginCall<'a>> {
+    let call = plugin_context(handle).call.load(Ordering::Acquire);
+    (call as *mut PluginCall).as_mut()
+}
+
+extern "C" fn host_add_service(
+    handle: *mut PluginHandle,
+    name: *const c_char,
+    service: *const PluginServiceVTable) -> bool
+{
+    let Some(call) = (unsafe { plugin_call(handle) }) else {
+        return false;
+    };
+    let (Some(name), Some(service)) = (unsafe { host_str(name) }, unsafe { service.as_ref() }) else {
+        return false;
+    };
+
+    let Some(plugin_services) = call.services.get::<PluginServices>() else {
+        return false;
+    };
+
+    let added = task::block_on(plugin_services.write()).add(call, name, *service);
+    added
+}
+
+extern "C" fn host_register_action(
+    handle: *mut PluginHandle,
+    name: *const c_char,
+    button: *const c_char,
+    description: *const c_char,
+    callback: ActionCallbackFn,
+    user_data: *mut c_void) -> bool
+{
+    let Some(call) = (unsafe { plugin_call(handle) }) else {
+        return false;
+    };
+    let callback = ClientCallback { callback, user_data };
+
+    unsafe { register_foreign_action(call.services, call.owner, call.name, name, button, description, callback) }
+}
+
+extern "C" fn host_register_game_object(
+    handle: *mut PluginHandle,
+    type_name: *const c_char,
+    game_object: *const PluginGameObjectVTable) -> bool
+{
+    let Some(call) = (unsafe { plugin_call(handle) }) else {
+        return false;
+    };
+    let (Some(type_name), Some(game_object)) = (unsafe { host_str(type_name) }, unsafe { game_object.as_ref() }) else {
+        return false;
+    };
+
+    let factory = PluginGameObjectFactory {
+        vtable: *game_object,
+        context: call.context.clone(),
+        library: call.library.clone(),
+    };
+
+    call.game_objects.register_owned(call.owner, type_name, move || factory.create());
+    true
+}
+
+extern "C" fn host_log(handle: *mut PluginHandle, level: u32, message: *const c_char) {
+    let context = unsafe { plugin_context(handle) };
+    if let Some(message) = unsafe { host_str(message) } {
+        log::log!(log_level(level), "[{}] {}", context.name, message);
+    }
+}
+
+extern "C" fn host_set_error(handle: *mut PluginHandle, message: *const c_char) {
+    if let Some(call) = unsafe { plugin_call(handle) } {
+        call.error = unsafe { host_str(message) }.map(str::to_string);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::plugins::plugin_errors::PluginError;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File every plugin directory has to contain.
+pub const MANIFEST_FILE_NAME: &str = "plugin.toml";
+
+/// Describes a plugin, read from `plugin.toml` in the plugin's directory:
+///
+/// ```toml
+/// name = "physics"
+/// version = "0.2.0"
+/// engine_version = "^0.1"
+/// library = "physics"
+///
+/// [dependencies]
+/// math = "^1.0"
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginManifest {
+    pub name: String,
+    pub version: Version,
+    /// Engine versions the plugin was built for.
+    pub engine_version: VersionReq,
+    /// Library name without the platform prefix and extension, e.g. `physics` for `libphysics.so`.
+    /// Defaults to the plugin name.
+    #[serde(default)]
+    pub library: Option<String>,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    #[serde(skip)]
+    pub directory: PathBuf,
+}
+
+impl PluginManifest {
+    /// Read the manifest in `directory`.
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<Self, PluginError> {
+        let directory = directory.as_ref();
+        let path = directory.join(MANIFEST_FILE_NAME);
+        let invalid = |reason: String| PluginError::InvalidManifest {
+            path: path.display().to_string(),
+            reason,
+        };
+
+        let contents = fs::read_to_string(&path).map_err(|e| invalid(e.to_string()))?;
+        let mut manifest: PluginManifest = toml::from_str(&contents).map_err(|e| invalid(e.to_string()))?;
+        manifest.directory = directory.to_path_buf();
+
+        Ok(manifest)
+    }
+
+    pub fn library_path(&self) -> PathBuf {
+        let library = self.library.as_deref().unwrap_or(&self.name);
+        self.directory.join(libloading::library_filename(library))
+    }
+}
@@ -1,4 +1,9 @@
 use crate::game::mesh_management::Mesh;
+use crate::gl_backend;
+use crate::material_management::Material;
+use crate::shader_management::UniformValue;
+use nalgebra_glm::Mat4;
+use std::rc::Rc;
 
 pub fn prepare_rendering() {
     unsafe {
@@ -6,14 +11,253 @@ pub fn prepare_rendering() {
     }
 }
 
-pub fn render(mesh: &Mesh) {
+/// One mesh to draw this frame. `transform` is uploaded as `u_Model`.
+pub struct DrawCommand {
+    pub mesh: Rc<Mesh>,
+    pub material: Rc<Material>,
+    pub transform: Mat4,
+}
 
-    unsafe {
-        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-        gl::DrawElements(
-            gl::TRIANGLES,
-            mesh.get_ebo().get_data_len() as i32,
-            gl::UNSIGNED_INT,
-            std::ptr::null());
-    }
-}
\ No newline at end of file
+/// Commands are drawn in this order, so each shader and texture is bound as few times as
+/// possible: shader, then a hash of the bound textures, then material and mesh. Commands with
+/// the same material end up next to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct SortKey {
+    shader: u32,
+    textures: u64,
+    material: *const Material,
+    mesh: u32,
+}
+
+impl DrawCommand {
+    fn sort_key(&self) -> SortKey {
+        SortKey {
+            shader: self.material.shader().id(),
+            textures: self.material.texture_key(),
+            material: Rc::as_ptr(&self.material),
+            mesh: self.mesh.get_vao().id(),
+        }
+    }
+}
+
+/// What the last `Renderer::render` did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub triangles: u64,
+    pub shader_switches: u32,
+    pub texture_switches: u32,
+    pub mesh_switches: u32,
+}
+
+impl RenderStats {
+    pub fn state_switches(&self) -> u32 {
+        self.shader_switches + self.texture_switches + self.mesh_switches
+    }
+}
+
+/// Collects `DrawCommand`s over a frame and draws them all at once in `render`.
+/// Shaders get the camera in `u_ViewProjection` and the command's transform in `u_Model`.
+#[derive(Default)]
+pub struct Renderer {
+    commands: Vec<DrawCommand>,
+    order: Vec<(SortKey, usize)>,
+    stats: RenderStats,
+}
+
+impl Renderer {
+    pub fn new() -> Renderer {
+        Self::default()
+    }
+
+    pub fn submit(&mut self, command: DrawCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn draw(&mut self, mesh: &Rc<Mesh>, material: &Rc<Material>, transform: Mat4) {
+        self.submit(DrawCommand { mesh: mesh.clone(), material: material.clone(), transform });
+    }
+
+    pub fn queued_commands(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Stats of the last rendered frame.
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Clear the screen, then draw and empty the queue.
+    pub fn render(&mut self, view_projection: &Mat4) {
+        let mut stats = RenderStats::default();
+
+        // Sort indices rather than the commands, keeping each key for the state checks below.
+        self.order.clear();
+        self.order.extend(self.commands.iter().enumerate().map(|(index, command)| (command.sort_key(), index)));
+        self.order.sort_unstable();
+
+        let backend = gl_backend::current();
+        backend.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+        let mut current: Option<SortKey> = None;
+
+        for (key, index) in self.order.iter() {
+            let command = &self.commands[*index];
+            let material = &command.material;
+            let shader = material.shader();
+
+            if current.map(|current| current.shader) != Some(key.shader) {
+                shader.use_program();
+                shader.set_uniform("u_ViewProjection", &UniformValue::Mat4(*view_projection));
+                stats.shader_switches += 1;
+            }
+
+            if current.map(|current| current.textures) != Some(key.textures) {
+                material.bind_textures();
+                stats.texture_switches += 1;
+            }
+
+            if current.map(|current| current.material) != Some(key.material) {
+                material.apply_uniforms();
+            }
+
+            if current.map(|current| current.mesh) != Some(key.mesh) {
+                command.mesh.get_vao().bind();
+                stats.mesh_switches += 1;
+            }
+
+            current = Some(*key);
+            shader.set_uniform("u_Model", &UniformValue::Mat4(command.transform));
+
+            let index_count = command.mesh.index_count();
+            backend.draw_elements(gl::TRIANGLES, index_count as i32, gl::UNSIGNED_INT, 0);
+
+            stats.draw_calls += 1;
+            stats.triangles += index_count as u64 / 3;
+        }
+
+        self.commands.clear();
+        self.stats = stats;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_backend::recording::{GlCall, RecordingGl};
+    use crate::shader_management::{Shader, ShaderProgram, ShaderType};
+    use crate::texture_management::Texture;
+    use nalgebra_glm as glm;
+    use sdl2::pixels::PixelFormatEnum;
+
+    fn program() -> Rc<ShaderProgram> {
+        let vertex = Shader::new(ShaderType::Vertex, String::from("void main() {}")).unwrap();
+        let fragment = Shader::new(ShaderType::Fragment, String::from("void main() {}")).unwrap();
+        Rc::new(ShaderProgram::new(&[vertex, fragment]).unwrap())
+    }
+
+    fn material(name: &str, shader: &Rc<ShaderProgram>) -> Rc<Material> {
+        let texture = Rc::new(Texture {
+            id: gl_backend::current().gen_texture(),
+            width: 1,
+            height: 1,
+            format: PixelFormatEnum::RGBA32,
+        });
+
+        Rc::new(Material::new(name, shader.clone()).with_texture("u_Texture", texture))
+    }
+
+    /// What was bound for one `DrawElements` call.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Draw {
+        command: usize,
+        program: u32,
+        vertex_array: u32,
+    }
+
+    /// The draws in `calls`, identified by the x translation `u_Model` was set to before each one.
+    fn draws(calls: &[GlCall]) -> Vec<Draw> {
+        let (mut program, mut vertex_array, mut model) = (0, 0, glm::Mat4::identity());
+        let mut draws = Vec::new();
+
+        for call in calls {
+            match call {
+                GlCall::UseProgram(id) => program = *id,
+                GlCall::BindVertexArray(id) => vertex_array = *id,
+                GlCall::Uniform { value: UniformValue::Mat4(value), .. } => model = *value,
+                GlCall::DrawElements { .. } => draws.push(Draw { command: model[(0, 3)] as usize, program, vertex_array }),
+                _ => {},
+            }
+        }
+
+        draws
+    }
+
+    #[test]
+    fn commands_are_drawn_grouped_by_state() {
+        let gl = RecordingGl::install();
+        let (first_shader, second_shader) = (program(), program());
+        let (grass, dirt, rock) = (material("Grass", &first_shader), material("Dirt", &first_shader), material("Rock", &second_shader));
+        let grass_tinted = Rc::new(Material::instance(&grass, "Tinted grass").with_uniform("u_Tint", UniformValue::Float(0.5)));
+        let (quad, other_quad) = (Rc::new(Mesh::quad("Quad")), Rc::new(Mesh::quad("Other quad")));
+
+        let commands = [
+            (&other_quad, &rock),
+            (&other_quad, &grass),
+            (&quad, &dirt),
+            (&quad, &grass_tinted),
+            (&quad, &grass),
+            (&other_quad, &grass_tinted),
+            (&quad, &rock),
+        ];
+
+        let mut renderer = Renderer::new();
+        for (index, (mesh, material)) in commands.iter().enumerate() {
+            renderer.draw(mesh, material, glm::translation(&glm::vec3(index as f32, 0.0, 0.0)));
+        }
+
+        gl.take_calls();
+        renderer.render(&glm::Mat4::identity());
+        let calls = gl.take_calls();
+        let draws = draws(&calls);
+
+        assert_eq!(calls[0], GlCall::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT));
+        let mut drawn: Vec<usize> = draws.iter().map(|draw| draw.command).collect();
+        drawn.sort();
+        assert_eq!(drawn, (0..commands.len()).collect::<Vec<_>>());
+
+        // Shaders, then textures, then materials are never revisited once left; within a material,
+        // meshes are in order.
+        let material_of = |draw: &Draw| Rc::as_ptr(commands[draw.command].1);
+        let texture_of = |draw: &Draw| commands[draw.command].1.texture_key();
+        for (index, pair) in draws.windows(2).enumerate() {
+            let later = &draws[index + 1..];
+            assert!(pair[0].program <= pair[1].program, "{:?}", draws);
+            if texture_of(&pair[0]) != texture_of(&pair[1]) {
+                assert!(later.iter().all(|draw| texture_of(draw) != texture_of(&pair[0])), "{:?}", draws);
+            }
+            if material_of(&pair[0]) != material_of(&pair[1]) {
+                assert!(later.iter().all(|draw| material_of(draw) != material_of(&pair[0])), "{:?}", draws);
+            } else {
+                assert!(pair[0].vertex_array <= pair[1].vertex_array, "{:?}", draws);
+            }
+        }
+
+        let mesh_changes = 1 + draws.windows(2).filter(|pair| pair[0].vertex_array != pair[1].vertex_array).count() as u32;
+        let stats = renderer.stats();
+        assert_eq!(stats, RenderStats {
+            draw_calls: 7,
+            triangles: 14,
+            shader_switches: 2,
+            // Grass and its instance share a texture, the other two have their own.
+            texture_switches: 3,
+            mesh_switches: mesh_changes,
+        });
+
+        let count = |predicate: fn(&GlCall) -> bool| calls.iter().filter(|call| predicate(call)).count() as u32;
+        assert_eq!(count(|call| matches!(call, GlCall::UseProgram(_))), stats.shader_switches);
+        assert_eq!(count(|call| matches!(call, GlCall::ActiveTexture(_))), stats.texture_switches);
+        assert_eq!(count(|call| matches!(call, GlCall::BindVertexArray(_))), stats.mesh_switches);
+        assert_eq!(renderer.queued_commands(), 0);
+    }
+}
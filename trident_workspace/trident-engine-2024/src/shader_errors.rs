@@ -1,16 +1,191 @@
 use std::error::Error;
 use std::fmt::Display;
+use crate::shader_management::ShaderType;
 
-#[allow(dead_code)]
-#[derive(Debug)]
-pub struct ShaderCreationFailure {
-    source: String,
+/// A line of shader source that a driver's info log points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// 1-based, like the driver's.
+    pub number: u32,
+    pub text: String,
 }
 
-impl Display for ShaderCreationFailure {
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShaderError {
+    /// `glCreateShader` returned 0.
+    Create {
+        stage: ShaderType,
+        source_path: Option<String>,
+    },
+    /// `glCreateProgram` returned 0.
+    CreateProgram {
+        /// The stage and source path of every shader it would have linked.
+        shaders: Vec<(ShaderType, Option<String>)>,
+    },
+    Compile {
+        stage: ShaderType,
+        source_path: Option<String>,
+        info_log: String,
+        /// The source lines the info log mentions, in the order it mentions them.
+        lines: Vec<SourceLine>,
+    },
+    Link {
+        /// The stage and source path of every attached shader.
+        shaders: Vec<(ShaderType, Option<String>)>,
+        info_log: String,
+    },
+}
+
+impl ShaderError {
+    pub fn compile(stage: ShaderType, source_path: Option<String>, info_log: String, source: &str) -> ShaderError {
+        let source_lines: Vec<&str> = source.lines().collect();
+        let lines = error_line_numbers(&info_log)
+            .into_iter()
+            .filter_map(|number| {
+                let text = source_lines.get((number as usize).checked_sub(1)?)?;
+                Some(SourceLine { number, text: text.to_string() })
+            })
+            .collect();
+
+        ShaderError::Compile { stage, source_path, info_log, lines }
+    }
+
+    pub fn info_log(&self) -> Option<&str> {
+        match self {
+            ShaderError::Create { .. } | ShaderError::CreateProgram { .. } => None,
+            ShaderError::Compile { info_log, .. } | ShaderError::Link { info_log, .. } => Some(info_log),
+        }
+    }
+}
+
+fn describe_shader(stage: &ShaderType, source_path: &Option<String>) -> String {
+    match source_path {
+        Some(path) => format!("{} shader {}", stage, path),
+        None => format!("{} shader", stage),
+    }
+}
+
+fn describe_shaders(shaders: &[(ShaderType, Option<String>)]) -> String {
+    let shaders: Vec<String> = shaders
+        .iter()
+        .map(|(stage, source_path)| describe_shader(stage, source_path))
+        .collect();
+
+    shaders.join(", ")
+}
+
+impl Display for ShaderError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Shader creation error: {}", self.source)
+        match self {
+            ShaderError::Create { stage, source_path } => {
+                write!(f, "Failed to create {}", describe_shader(stage, source_path))
+            },
+            ShaderError::CreateProgram { shaders } => {
+                write!(f, "Failed to create shader program ({})", describe_shaders(shaders))
+            },
+            ShaderError::Compile { stage, source_path, info_log, lines } => {
+                write!(f, "Failed to compile {}:\n{}", describe_shader(stage, source_path), info_log.trim_end())?;
+
+                for line in lines {
+                    write!(f, "\n{:>5} | {}", line.number, line.text)?;
+                }
+
+                Ok(())
+            },
+            ShaderError::Link { shaders, info_log } => {
+                write!(f, "Failed to link shader program ({}):\n{}", describe_shaders(shaders), info_log.trim_end())
+            },
+        }
     }
 }
 
-impl Error for ShaderCreationFailure {}
+impl Error for ShaderError {}
+
+/// The source line numbers in a driver's info log, without duplicates. Understands the
+/// `0:12(5): error: ...` (Mesa), `0(12) : error C0000: ...` (NVIDIA) and
+/// `ERROR: 0:12: ...` (AMD and others) forms, where the first number is the source string.
+pub fn error_line_numbers(info_log: &str) -> Vec<u32> {
+    let mut numbers = Vec::new();
+
+    for log_line in info_log.lines() {
+        if let Some(number) = parse_line_number(log_line) {
+            if !numbers.contains(&number) {
+                numbers.push(number);
+            }
+        }
+    }
+
+    numbers
+}
+
+fn parse_line_number(log_line: &str) -> Option<u32> {
+    let mut rest = log_line.trim_start();
+    for prefix in ["ERROR:", "WARNING:", "error:", "warning:"] {
+        if let Some(stripped) = rest.strip_prefix(prefix) {
+            rest = stripped.trim_start();
+            break;
+        }
+    }
+
+    let string_index_len = rest.find(|c: char| !c.is_ascii_digit())?;
+    if string_index_len == 0 {
+        return None;
+    }
+
+    let rest = &rest[string_index_len..];
+    let (line, terminator) = if let Some(rest) = rest.strip_prefix(':') {
+        (rest, None)
+    } else {
+        (rest.strip_prefix('(')?, Some(')'))
+    };
+
+    let line_len = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+    if let Some(terminator) = terminator {
+        if !line[line_len..].starts_with(terminator) {
+            return None;
+        }
+    }
+
+    line[..line_len].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_driver_line_formats() {
+        assert_eq!(parse_line_number("0:12(5): error: `foo' undeclared"), Some(12));
+        assert_eq!(parse_line_number("0(12) : error C0000: syntax error"), Some(12));
+        assert_eq!(parse_line_number("ERROR: 0:12: 'foo' : undeclared identifier"), Some(12));
+        assert_eq!(parse_line_number("  WARNING: 1:3: unused variable"), Some(3));
+    }
+
+    #[test]
+    fn ignores_lines_without_a_location() {
+        assert_eq!(parse_line_number(""), None);
+        assert_eq!(parse_line_number("ERROR: 2 compilation errors.  No code generated."), None);
+        assert_eq!(parse_line_number("error: linking failed"), None);
+        assert_eq!(parse_line_number("0(12 : error"), None);
+        assert_eq!(parse_line_number(":12: error"), None);
+    }
+
+    #[test]
+    fn error_line_numbers_keeps_log_order_without_duplicates() {
+        let info_log = "0:7(3): error: a\n0:2(1): error: b\n0:7(9): error: c\nno location here\n";
+        assert_eq!(error_line_numbers(info_log), vec![7, 2]);
+    }
+
+    #[test]
+    fn compile_errors_quote_the_lines_they_mention() {
+        let source = "#version 330 core\nvoid main() {\n    oops\n}";
+        let error = ShaderError::compile(ShaderType::Vertex, Some(String::from("basic/vert.glsl")), String::from("0:3(5): error: syntax error\n0:99(1): error: past the end"), source);
+
+        let ShaderError::Compile { lines, .. } = &error else {
+            panic!("Expected a compile error, got {:?}", error);
+        };
+        assert_eq!(lines, &vec![SourceLine { number: 3, text: String::from("    oops") }]);
+        assert!(error.to_string().starts_with("Failed to compile vertex shader basic/vert.glsl:"), "{}", error);
+        assert!(error.to_string().ends_with("    3 |     oops"), "{}", error);
+    }
+}
@@ -1,135 +1,192 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use gl::{FRAGMENT_SHADER, VERTEX_SHADER};
-use gl::types::GLchar;
+use gl::types::GLenum;
 use std::error::Error;
-use std::ffi::{CStr, CString};
+use std::fmt::Display;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
-use std::ptr;
-use log::{error, info};
-use nalgebra_glm::Vec3;
+use log::info;
+use nalgebra_glm::{Mat4, Vec2, Vec3};
 use nalgebra_glm::Vec4;
+use crate::gl_backend;
 use crate::opengl_utils::check_opengl_error;
+use crate::shader_errors::ShaderError;
 
-#[derive(Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex,
     Fragment,
 }
 
+impl ShaderType {
+    fn gl_type(&self) -> GLenum {
+        match self {
+            ShaderType::Vertex => VERTEX_SHADER,
+            ShaderType::Fragment => FRAGMENT_SHADER,
+        }
+    }
+}
+
+impl Display for ShaderType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShaderType::Vertex => write!(f, "vertex"),
+            ShaderType::Fragment => write!(f, "fragment"),
+        }
+    }
+}
+
+/// A value for any uniform type a `Material` can set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UniformValue {
+    Int(i32),
+    Float(f32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    Mat4(Mat4),
+}
+
 pub struct ShaderProgram {
     program_id: u32,
     shaders: Vec<Shader>,
-    uniforms: HashMap<String, i32>,
+    uniforms: RefCell<HashMap<String, i32>>,
 }
 
 impl Drop for ShaderProgram {
     fn drop(&mut self) {
+        let backend = gl_backend::current();
         for shader in self.shaders.iter() {
-            unsafe {
-                gl::DetachShader(self.program_id, shader.shader_id);
-                gl::DeleteShader(shader.shader_id);
-            }
+            backend.detach_shader(self.program_id, shader.shader_id);
+            backend.delete_shader(shader.shader_id);
         }
 
-        unsafe {
-            gl::DeleteProgram(self.program_id);
+        backend.delete_program(self.program_id);
 
-            #[cfg(debug_assertions)]
-            check_opengl_error("shader_management", 39);
-        }
+        #[cfg(debug_assertions)]
+        check_opengl_error("shader_management", line!());
     }
 }
 
 impl ShaderProgram {
-    pub fn new(shaders: &[Shader]) -> Self {
-        let program_id = unsafe { gl::CreateProgram() };
+    /// Create a program with `shaders` attached. The program deletes the shaders when it's
+    /// dropped, and if it can't be created, `new` deletes them right away.
+    pub fn new(shaders: &[Shader]) -> Result<Self, ShaderError> {
+        let backend = gl_backend::current();
+        let program_id = backend.create_program();
         let shaders = shaders.to_vec();
 
-        for shader in shaders.iter() {
-            unsafe {
-                gl::AttachShader(program_id, shader.shader_id);
-                check_opengl_error("shader_management", 52);
+        if program_id == 0 {
+            let stages = shaders
+                .iter()
+                .map(|shader| (shader.shader_type, shader.source_path.clone()))
+                .collect();
+
+            for shader in shaders {
+                shader.delete();
             }
+
+            return Err(ShaderError::CreateProgram { shaders: stages });
+        }
+
+        for shader in shaders.iter() {
+            backend.attach_shader(program_id, shader.shader_id);
+            check_opengl_error("shader_management", line!());
         }
 
-        Self {
+        Ok(Self {
             program_id,
             shaders,
-            uniforms: HashMap::new(),
-        }
+            uniforms: RefCell::new(HashMap::new()),
+        })
     }
 
-    pub fn link(&self) {
+    pub fn link(&self) -> Result<(), ShaderError> {
+        let backend = gl_backend::current();
         let program_id = self.program_id;
-        let mut link_status = 0;
-        let mut log_length = 0;
-
-        unsafe {
-            gl::LinkProgram(program_id);
-            gl::GetProgramiv(program_id, gl::LINK_STATUS, &mut link_status);
-            gl::GetProgramiv(program_id, gl::INFO_LOG_LENGTH, &mut log_length);
-
-            if link_status != gl::TRUE as i32 {
-                let mut written_len = 0;
-                let mut buffer: Vec<u8> = Vec::with_capacity(log_length as usize);
-
-                gl::GetProgramInfoLog(
-                    program_id,
-                    log_length,
-                    &mut written_len,
-                    buffer.as_mut_ptr() as *mut GLchar,
-                );
-
-                if let Ok(log) = CStr::from_ptr(buffer.as_ptr() as *const GLchar).to_str() {
-                    error!("An error occurred in linking the shader program! ERROR: {}", log);
-                }
-            } else {
-                info!("Shader program linked successfully");
-            }
+
+        backend.link_program(program_id);
+        let link_status = backend.get_program_iv(program_id, gl::LINK_STATUS);
+
+        if link_status != gl::TRUE as i32 {
+            return Err(ShaderError::Link {
+                shaders: self
+                    .shaders
+                    .iter()
+                    .map(|shader| (shader.shader_type, shader.source_path.clone()))
+                    .collect(),
+                info_log: backend.get_program_info_log(program_id),
+            });
         }
+
+        info!("Shader program linked successfully");
+        Ok(())
+    }
+
+    pub fn id(&self) -> u32 {
+        self.program_id
     }
 
     pub fn use_program(&self) {
-        unsafe {
-            gl::UseProgram(self.program_id);
-        }
+        gl_backend::current().use_program(self.program_id);
     }
 
     pub fn get_uniform_locations(&mut self, names: &[&str]) {
         for uname in names {
             let (uniform_name, location) = self.get_uniform_location(uname);
-            self.uniforms.insert(uniform_name, location);
+            self.uniforms.get_mut().insert(uniform_name, location);
         }
     }
 
-    pub fn set_uniform_vec3(&self, location: &str, vector: &Vec3) {
-        let location_int = self.uniforms.get(location).unwrap();
-        unsafe {
-            gl::Uniform3f(*location_int, vector.x, vector.y, vector.z);
+    /// The location of `name`, looked up on first use. -1 if the program has no such uniform,
+    /// which OpenGL silently ignores when setting it.
+    pub fn uniform_location(&self, name: &str) -> i32 {
+        if let Some(location) = self.uniforms.borrow().get(name) {
+            return *location;
         }
+
+        let (uniform_name, location) = self.get_uniform_location(name);
+        self.uniforms.borrow_mut().insert(uniform_name, location);
+        location
+    }
+
+    /// Set a uniform of any type on this program, which must be in use.
+    pub fn set_uniform(&self, name: &str, value: &UniformValue) {
+        let location = self.uniform_location(name);
+        gl_backend::current().uniform(location, value);
+
+        #[cfg(debug_assertions)]
+        check_opengl_error("shader_management", line!());
+    }
+
+    pub fn set_uniform_vec3(&self, location: &str, vector: &Vec3) {
+        let location_int = self.uniform_location(location);
+        gl_backend::current().uniform(location_int, &UniformValue::Vec3(*vector));
     }
 
     pub fn set_uniform_vec4(&self, location: &str, vector: &Vec4) {
-        let location_int = self.uniforms.get(location).unwrap();
-        unsafe {
-            gl::Uniform4f(*location_int, vector.x, vector.y, vector.z, vector.w);
-            #[cfg(debug_assertions)]
-            check_opengl_error("shader_management", 118);
-        }
+        let location_int = self.uniform_location(location);
+        gl_backend::current().uniform(location_int, &UniformValue::Vec4(*vector));
+        #[cfg(debug_assertions)]
+        check_opengl_error("shader_management", line!());
+    }
+
+    pub fn set_uniform_mat4(&self, location: &str, matrix: &Mat4) {
+        let location_int = self.uniform_location(location);
+        gl_backend::current().uniform(location_int, &UniformValue::Mat4(*matrix));
+        #[cfg(debug_assertions)]
+        check_opengl_error("shader_management", line!());
     }
 
     fn get_uniform_location(&self, uniform_name: &str) -> (String, i32) {
         let uname_string = String::from(uniform_name);
-        let uname_cstr = CString::new(uname_string.as_bytes()).unwrap();
-        unsafe {
-            let location = gl::GetUniformLocation(self.program_id, uname_cstr.as_ptr());
-            #[cfg(debug_assertions)]
-            check_opengl_error("shader_management", 128);
+        let location = gl_backend::current().get_uniform_location(self.program_id, &uname_string);
+        #[cfg(debug_assertions)]
+        check_opengl_error("shader_management", line!());
 
-            (uname_string, location)
-        }
+        (uname_string, location)
     }
 }
 
@@ -137,40 +194,24 @@ impl ShaderProgram {
 pub struct Shader {
     pub shader_type: ShaderType,
     pub shader_id: u32,
+    /// Where the source came from, if it was loaded with `from_file`.
+    pub source_path: Option<String>,
 }
 
 impl Shader {
-    fn compile_shader(shader_id: u32, shader_source: &String) {
-        let src_c = CString::new(shader_source.as_bytes()).unwrap();
-
-        unsafe {
-            gl::ShaderSource(shader_id, 1, &src_c.as_ptr(), ptr::null());
-            gl::CompileShader(shader_id);
+    /// Compile `shader_source` into `shader_id`, returning the info log if it fails.
+    fn compile_shader(shader_id: u32, shader_source: &str) -> Result<(), String> {
+        let backend = gl_backend::current();
+        backend.shader_source(shader_id, shader_source);
+        backend.compile_shader(shader_id);
+
+        let status = backend.get_shader_iv(shader_id, gl::COMPILE_STATUS);
+        if status != gl::TRUE as i32 {
+            return Err(backend.get_shader_info_log(shader_id));
         }
 
-        let mut status = 0;
-        let mut log_length = 0;
-        unsafe {
-            gl::GetShaderiv(shader_id, gl::COMPILE_STATUS, &mut status);
-            gl::GetShaderiv(shader_id, gl::INFO_LOG_LENGTH, &mut log_length);
-
-            if status != 1 {
-                let mut written_len = 0;
-                let mut buffer: Vec<u8> = Vec::with_capacity(log_length as usize);
-
-                gl::GetShaderInfoLog(
-                    shader_id,
-                    log_length,
-                    &mut written_len,
-                    buffer.as_mut_ptr() as *mut GLchar, );
-
-                if let Ok(log) = CStr::from_ptr(buffer.as_ptr() as *const GLchar).to_str() {
-                    info!("An error occurred in shader compilation: {}", log);
-                }
-            } else {
-                info!("Shader compilation successful");
-            }
-        }
+        info!("Shader compilation successful");
+        Ok(())
     }
 
     /// Load the contents of a GLSL file into memory
@@ -183,7 +224,7 @@ impl Shader {
     /// `some_dir/my_shader.glsl`
     #[cfg(debug_assertions)]
     pub fn load_shader_source(source_path: &str) -> Result<String, Box<dyn Error>> {
-        let formatted_path = format!("trident-engine-2024/shaders/{}", source_path.to_string());
+        let formatted_path = format!("trident-engine-2024/shaders/{}", source_path);
 
         let shader_path = Path::new(&formatted_path);
         let mut shader_file = File::open(shader_path)?;
@@ -195,7 +236,7 @@ impl Shader {
     }
     #[cfg(not(debug_assertions))]
     pub fn load_shader_source(source_path: &str) -> Result<String, Box<dyn Error>> {
-        let formatted_path = format!("shaders/{}", source_path.to_string());
+        let formatted_path = format!("shaders/{}", source_path);
 
         let shader_path = Path::new(&formatted_path);
         let mut shader_file = File::open(shader_path)?;
@@ -207,38 +248,165 @@ impl Shader {
     }
 
     /// This constructs a new Shader object using the provided `shader_type` and `shader_source`
-    /// arguments.
-    pub fn new(shader_type: ShaderType, shader_source: String) -> Self {
-        let mut shader_id = 0u32;
-
-        match shader_type {
-            ShaderType::Vertex => {
-                unsafe {
-                    shader_id = gl::CreateShader(VERTEX_SHADER);
-                }
-
-                if shader_id != 0 {
-                    Self::compile_shader(shader_id, &shader_source);
-                } else {
-                    error!("gl::CreateShader failed for type VERTEX_SHADER!");
-                }
-            },
-            ShaderType::Fragment => {
-                unsafe {
-                    shader_id = gl::CreateShader(FRAGMENT_SHADER);
-                }
-
-                if shader_id != 0 {
-                    Self::compile_shader(shader_id, &shader_source);
-                } else {
-                    error!("gl::CreateShader failed for type FRAGMENT_SHADER!");
-                }
-            }
+    /// arguments, failing with the driver's info log if the source doesn't compile.
+    pub fn new(shader_type: ShaderType, shader_source: String) -> Result<Self, ShaderError> {
+        Self::create(shader_type, &shader_source, None)
+    }
+
+    /// Load a shader with `load_shader_source` and compile it. Errors name `source_path`.
+    pub fn from_file(shader_type: ShaderType, source_path: &str) -> Result<Self, Box<dyn Error>> {
+        let shader_source = Self::load_shader_source(source_path)?;
+        Ok(Self::create(shader_type, &shader_source, Some(source_path.to_string()))?)
+    }
+
+    /// Delete the shader object. Only needed for shaders that never made it into a
+    /// `ShaderProgram`, which deletes its own.
+    pub fn delete(self) {
+        gl_backend::current().delete_shader(self.shader_id);
+    }
+
+    fn create(shader_type: ShaderType, shader_source: &str, source_path: Option<String>) -> Result<Self, ShaderError> {
+        let shader_id = gl_backend::current().create_shader(shader_type.gl_type());
+        if shader_id == 0 {
+            return Err(ShaderError::Create {
+                stage: shader_type,
+                source_path,
+            });
         }
 
-        Self {
+        if let Err(info_log) = Self::compile_shader(shader_id, shader_source) {
+            gl_backend::current().delete_shader(shader_id);
+            return Err(ShaderError::compile(shader_type, source_path, info_log, shader_source));
+        }
+
+        Ok(Self {
             shader_id,
             shader_type,
-        }
+            source_path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_backend::recording::{GlCall, GlObjectKind, RecordingGl};
+
+    #[test]
+    fn failing_to_create_a_shader_is_an_error() {
+        let gl = RecordingGl::install();
+        gl.fail_create_shader(true);
+
+        let Err(error) = Shader::new(ShaderType::Vertex, String::from("void main() {}")) else {
+            panic!("Created a shader while glCreateShader fails");
+        };
+
+        assert_eq!(error, ShaderError::Create { stage: ShaderType::Vertex, source_path: None });
+        assert_eq!(gl.count(|call| matches!(call, GlCall::CompileShader(_))), 0);
+    }
+
+    #[test]
+    fn failing_to_compile_deletes_the_shader() {
+        let gl = RecordingGl::install();
+        gl.fail_compilation(Some("0:2(1): error: syntax error"));
+
+        let Err(error) = Shader::new(ShaderType::Fragment, String::from("void main() {\n    oops\n}")) else {
+            panic!("Compiled a shader while compilation fails");
+        };
+
+        let ShaderError::Compile { stage, lines, .. } = &error else {
+            panic!("Expected a compile error, got {:?}", error);
+        };
+        assert_eq!(*stage, ShaderType::Fragment);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "    oops");
+
+        assert_eq!(gl.count(|call| matches!(call, GlCall::DeleteShader(_))), 1);
+        assert!(gl.live_objects(GlObjectKind::Shader).is_empty());
+    }
+
+    #[test]
+    fn dropping_a_program_detaches_and_deletes_its_shaders() {
+        let gl = RecordingGl::install();
+        let vertex = Shader::new(ShaderType::Vertex, String::from("void main() {}")).unwrap();
+        let fragment = Shader::new(ShaderType::Fragment, String::from("void main() {}")).unwrap();
+        let shader_ids = [vertex.shader_id, fragment.shader_id];
+
+        let program = ShaderProgram::new(&[vertex, fragment]).unwrap();
+        program.link().unwrap();
+        let program_id = program.id();
+
+        gl.take_calls();
+        drop(program);
+
+        assert_eq!(gl.calls(), vec![
+            GlCall::DetachShader { program: program_id, shader: shader_ids[0] },
+            GlCall::DeleteShader(shader_ids[0]),
+            GlCall::DetachShader { program: program_id, shader: shader_ids[1] },
+            GlCall::DeleteShader(shader_ids[1]),
+            GlCall::DeleteProgram(program_id),
+        ]);
+        assert!(gl.live_objects(GlObjectKind::Shader).is_empty());
+        assert!(gl.live_objects(GlObjectKind::Program).is_empty());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn failing_to_link_reports_the_info_log_and_every_shader() {
+        let gl = RecordingGl::install();
+        let mut vertex = Shader::new(ShaderType::Vertex, String::from("void main() {}")).unwrap();
+        vertex.source_path = Some(String::from("basic/vert.glsl"));
+        let fragment = Shader::new(ShaderType::Fragment, String::from("void main() {}")).unwrap();
+        let program = ShaderProgram::new(&[vertex, fragment]).unwrap();
+        gl.fail_linking(Some("error: vertex output `uv' not read by fragment shader"));
+
+        let Err(error) = program.link() else {
+            panic!("Linked a program while linking fails");
+        };
+
+        assert_eq!(error, ShaderError::Link {
+            shaders: vec![
+                (ShaderType::Vertex, Some(String::from("basic/vert.glsl"))),
+                (ShaderType::Fragment, None),
+            ],
+            info_log: String::from("error: vertex output `uv' not read by fragment shader"),
+        });
+        assert_eq!(
+            error.to_string(),
+            "Failed to link shader program (vertex shader basic/vert.glsl, fragment shader):\n\
+             error: vertex output `uv' not read by fragment shader",
+        );
+    }
+
+    #[test]
+    fn uniforms_the_program_does_not_use_are_set_at_location_minus_one() {
+        let gl = RecordingGl::install();
+        let vertex = Shader::new(ShaderType::Vertex, String::from("void main() {}")).unwrap();
+        let program = ShaderProgram::new(&[vertex]).unwrap();
+        gl.remove_uniform("unused");
+
+        program.set_uniform("unused", &UniformValue::Float(1.0));
+        program.set_uniform("used", &UniformValue::Float(2.0));
+
+        assert_eq!(program.uniform_location("unused"), -1);
+        assert_eq!(gl.count(|call| matches!(call, GlCall::Uniform { location: -1, .. })), 1);
+        assert!(program.uniform_location("used") >= 0);
+    }
+
+    #[test]
+    fn failing_to_create_a_program_deletes_its_shaders() {
+        let gl = RecordingGl::install();
+        let vertex = Shader::new(ShaderType::Vertex, String::from("void main() {}")).unwrap();
+        let fragment = Shader::new(ShaderType::Fragment, String::from("void main() {}")).unwrap();
+        gl.fail_create_program(true);
+
+        let Err(error) = ShaderProgram::new(&[vertex, fragment]) else {
+            panic!("Created a program while glCreateProgram fails");
+        };
+
+        assert_eq!(error, ShaderError::CreateProgram {
+            shaders: vec![(ShaderType::Vertex, None), (ShaderType::Fragment, None)],
+        });
+        assert_eq!(gl.count(|call| matches!(call, GlCall::AttachShader { .. })), 0);
+        assert!(gl.live_objects(GlObjectKind::Shader).is_empty());
+    }
+}
@@ -1,8 +1,10 @@
 use std::collections::HashMap;
-use std::ffi::c_void;
+use std::error::Error;
+use std::rc::Rc;
 use sdl2::image::LoadSurface;
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::surface::Surface;
+use crate::gl_backend;
 use crate::opengl_utils::check_opengl_error;
 
 pub struct Texture {
@@ -14,68 +16,104 @@ pub struct Texture {
 
 impl Drop for Texture {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteTextures(1, &self.id);
-        }
+        gl_backend::current().delete_texture(self.id);
     }
 }
 
+/// Loads image files into OpenGL textures and keeps them by the path they were loaded from.
+#[derive(Default)]
 pub struct TextureLoader {
-    texture_map: HashMap<String, Texture>
+    texture_map: HashMap<String, Rc<Texture>>
 }
 
 impl TextureLoader {
     pub fn new() -> Self {
-        Self {
-            texture_map: HashMap::new()
-        }
+        Self::default()
     }
 
-    fn get_texture_data(&self, texture_path: &str) -> (Vec<u8>, u32, u32) {
-        let surface = Surface::from_file(texture_path).unwrap();
-        let surface = surface.convert_format(PixelFormatEnum::RGBA32).unwrap();
+    fn get_texture_data(&self, texture_path: &str) -> Result<(Vec<u8>, u32, u32), Box<dyn Error>> {
+        let surface = Surface::from_file(texture_path)?;
+        let surface = surface.convert_format(PixelFormatEnum::RGBA32)?;
         let (width, height) = surface.size();
 
-        let pixel_data = surface.without_lock().unwrap();
-        (pixel_data.to_vec(), width, height)
+        let pixel_data = surface.without_lock().ok_or("Surface pixels can't be read without locking")?;
+        Ok((pixel_data.to_vec(), width, height))
     }
 
-    pub fn load_texture(&mut self, texture_path: &str) {
-        let (pixel_data, width, height) = self.get_texture_data(texture_path);
-
-        let mut texture_id = 0u32;
-        unsafe {
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-
-            gl::GenTextures(1, &mut texture_id);
-            gl::BindTexture(gl::TEXTURE_2D, texture_id);
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGBA as i32,
-                width as i32,
-                height as i32,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                pixel_data.as_ptr() as *const c_void,);
-
-            gl::GenerateMipmap(gl::TEXTURE_2D);
-            #[cfg(debug_assertions)]
-            check_opengl_error("texture_management", 67);
-        }
-
-        let texture = Texture {
+    /// Load the image at `texture_path` into a new texture, replacing any earlier texture loaded
+    /// from the same path.
+    pub fn load_texture(&mut self, texture_path: &str) -> Result<Rc<Texture>, Box<dyn Error>> {
+        let (pixel_data, width, height) = self.get_texture_data(texture_path)
+            .map_err(|e| format!("Failed to load texture {}: {}", texture_path, e))?;
+
+        let backend = gl_backend::current();
+        let texture_id = backend.gen_texture();
+        backend.bind_texture(gl::TEXTURE_2D, texture_id);
+
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+        backend.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+        backend.tex_image_2d(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as i32,
+            width as i32,
+            height as i32,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            &pixel_data);
+
+        backend.generate_mipmap(gl::TEXTURE_2D);
+        #[cfg(debug_assertions)]
+        check_opengl_error("texture_management", line!());
+
+        let texture = Rc::new(Texture {
             id: texture_id,
             width,
             height,
             format: PixelFormatEnum::RGBA32,
+        });
+
+        self.texture_map.insert(texture_path.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    /// A loaded texture by the path it was loaded from, e.g. `"trident-engine-2024/res/textures/grass.png"`.
+    pub fn get_texture(&self, texture_path: &str) -> Option<Rc<Texture>> {
+        self.texture_map.get(texture_path).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gl_backend::recording::{GlCall, GlObjectKind, RecordingGl};
+
+    #[test]
+    fn missing_files_are_an_error() {
+        let mut loader = TextureLoader::new();
+        let Err(error) = loader.load_texture("does/not/exist.png") else {
+            panic!("Loaded a texture that doesn't exist");
         };
 
-        let texture_name = texture_path.split("/").last().unwrap().to_string();
-        self.texture_map.insert(texture_name, texture);
+        assert!(error.to_string().contains("does/not/exist.png"), "{}", error);
+        assert!(loader.get_texture("does/not/exist.png").is_none());
+    }
+
+    #[test]
+    fn dropping_a_texture_deletes_only_its_name() {
+        let gl = RecordingGl::install();
+        let backend = gl_backend::current();
+        let kept = backend.gen_texture();
+        let texture = Texture { id: backend.gen_texture(), width: 4, height: 4, format: PixelFormatEnum::RGBA32 };
+        let id = texture.id;
+
+        gl.take_calls();
+        drop(texture);
+
+        assert_eq!(gl.calls(), vec![GlCall::DeleteTexture(id)]);
+        assert_eq!(gl.live_objects(GlObjectKind::Texture), vec![kept]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,66 @@
+use std::env;
+use std::rc::Rc;
+use nalgebra_glm::{self as glm, Mat4};
+use sdl2::video::Window;
+use trident_engine_2024::application::Application;
+use trident_engine_2024::application_config::ApplicationConfig;
+use trident_engine_2024::game::camera::Camera;
+use trident_engine_2024::game::mesh_management::Mesh;
+use trident_engine_2024::game::transform::Transform;
+use trident_engine_2024::game_loop::{FrameContext, GameLoop};
+use trident_engine_2024::headless::{assert_golden, Tolerance};
+use trident_engine_2024::material_management::{Material, MaterialLibrary};
+use trident_engine_2024::rendering::Renderer;
+use trident_engine_2024::texture_management::TextureLoader;
+
+/// Rendering needs an OpenGL 4.5 context. Mesa's software rasterizer gives the same pixels on
+/// every machine, so the golden test is ignored unless asked for, and then insists on it.
+const SOFTWARE_GL_VAR: &str = "LIBGL_ALWAYS_SOFTWARE";
+
+const DEMO_MATERIAL: &str = "trident-engine-2024/res/materials/demo.toml";
+const DEMO_QUAD_GOLDEN: &str = "trident-engine-2024/res/goldens/demo_quad.png";
+
+struct QuadScene {
+    mesh: Rc<Mesh>,
+    material: Rc<Material>,
+    renderer: Renderer,
+    view_projection: Mat4,
+}
+
+impl GameLoop for QuadScene {
+    fn render(&mut self, _frame: &FrameContext, _window: &mut Window) {
+        self.renderer.draw(&self.mesh, &self.material, Mat4::identity());
+        self.renderer.render(&self.view_projection);
+    }
+}
+
+#[test]
+#[ignore = "needs Mesa's software rasterizer: run with LIBGL_ALWAYS_SOFTWARE=1 and --ignored"]
+fn demo_quad_matches_golden() {
+    assert!(env::var_os(SOFTWARE_GL_VAR).is_some(), "Set {}=1 to render with Mesa", SOFTWARE_GL_VAR);
+
+    // Asset paths are relative to the workspace root, like when running the demo.
+    env::set_current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/..")).unwrap();
+
+    let config = ApplicationConfig::builder()
+        .size(64, 64)
+        .target_fps(None)
+        .build();
+    let mut application = Application::headless(config).unwrap();
+
+    let camera = Camera::orthographic(2.0, 0.1, 10.0)
+        .with_viewport_size(64, 64)
+        .with_transform(Transform::from_translation(glm::vec3(0.0, 0.0, 1.0)));
+
+    let mut textures = TextureLoader::new();
+    let mut scene = QuadScene {
+        mesh: Rc::new(Mesh::quad("Quad")),
+        material: MaterialLibrary::new().load(DEMO_MATERIAL, &mut textures).unwrap(),
+        renderer: Renderer::new(),
+        view_projection: camera.view_projection_matrix(),
+    };
+
+    let image = application.render_to_image(&mut scene, 1).unwrap();
+    assert_eq!(scene.renderer.stats().triangles, 2);
+    assert_golden(&image, DEMO_QUAD_GOLDEN, Tolerance::default());
+}